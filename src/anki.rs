@@ -0,0 +1,427 @@
+use std::collections::BTreeMap;
+use std::io::Write;
+use std::{env, fs, path::Path, process, time::SystemTime};
+
+use rusqlite::{params, Connection};
+use serde_json::json;
+
+use crate::book::Word;
+use crate::handler::schema::Entry;
+use crate::handler::AudioType;
+use crate::result::Result;
+use crate::util;
+
+// An .apkg is a zip of a sqlite collection (the legacy schema 11, which all
+// the Anki versions import), a "media" json and the media files named 0, 1...
+// The ids of the note type and the deck are fixed and the note guids come from
+// the phrase, so that importing an export again updates the notes in place.
+const MODEL_ID: i64 = 1_666_000_000_001;
+const DECK_ID: i64 = 1_666_000_000_002;
+const DECK_NAME: &str = "rdict";
+const FIELDS: [&str; 4] = ["Phrase", "Phonetic", "Meanings", "Examples"];
+
+const SCHEMA: &str = "
+CREATE TABLE col (
+    id integer primary key, crt integer not null, mod integer not null,
+    scm integer not null, ver integer not null, dty integer not null,
+    usn integer not null, ls integer not null, conf text not null,
+    models text not null, decks text not null, dconf text not null, tags text not null
+);
+CREATE TABLE notes (
+    id integer primary key, guid text not null, mid integer not null,
+    mod integer not null, usn integer not null, tags text not null,
+    flds text not null, sfld integer not null, csum integer not null,
+    flags integer not null, data text not null
+);
+CREATE TABLE cards (
+    id integer primary key, nid integer not null, did integer not null,
+    ord integer not null, mod integer not null, usn integer not null,
+    type integer not null, queue integer not null, due integer not null,
+    ivl integer not null, factor integer not null, reps integer not null,
+    lapses integer not null, left integer not null, odue integer not null,
+    odid integer not null, flags integer not null, data text not null
+);
+CREATE TABLE revlog (
+    id integer primary key, cid integer not null, usn integer not null,
+    ease integer not null, ivl integer not null, lastIvl integer not null,
+    factor integer not null, time integer not null, type integer not null
+);
+CREATE TABLE graves (usn integer not null, oid integer not null, type integer not null);
+CREATE INDEX ix_notes_usn on notes (usn);
+CREATE INDEX ix_cards_usn on cards (usn);
+CREATE INDEX ix_revlog_usn on revlog (usn);
+CREATE INDEX ix_cards_nid on cards (nid);
+CREATE INDEX ix_cards_sched on cards (did, queue, due);
+CREATE INDEX ix_revlog_cid on revlog (cid);
+CREATE INDEX ix_notes_csum on notes (csum);
+";
+
+const QFMT: &str = r#"<div class="phrase">{{Phrase}}</div>"#;
+const AFMT: &str = r#"{{FrontSide}}<hr id="answer">
+<div class="phonetic">{{Phonetic}}</div>
+<div class="meanings">{{Meanings}}</div>
+<div class="examples">{{Examples}}</div>"#;
+const CSS: &str = ".card { font-family: arial; font-size: 18px; text-align: left; }
+.phrase { font-size: 28px; text-align: center; }
+.phonetic { color: #888; }
+.examples { margin-top: 1em; font-size: 15px; }
+.trans { color: #888; }";
+
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct Exported {
+    pub notes: usize,
+    pub media: usize,
+}
+
+// Writes the words into an Anki package at `path`. `audio` hands out the mp3
+// of a phrase if there's one, a word without it just has no sound.
+pub fn export<F>(words: &[Word], path: &Path, audio: F) -> Result<Exported>
+where
+    F: Fn(&str, AudioType) -> Option<Vec<u8>>,
+{
+    let now = util::since_epoch(SystemTime::now());
+    let (secs, millis) = (now.as_secs() as i64, now.as_millis() as i64);
+
+    let tmp = env::temp_dir().join(format!("rdict-{}.anki2", process::id()));
+    let _ = fs::remove_file(&tmp);
+    let conn = Connection::open(&tmp)?;
+    conn.execute_batch(SCHEMA)?;
+    conn.execute(
+        "INSERT INTO col VALUES (1, ?1, ?2, ?2, 11, 0, 0, 0, ?3, ?4, ?5, ?6, '{}')",
+        params![
+            secs,
+            millis,
+            conf().to_string(),
+            models(secs).to_string(),
+            decks(secs).to_string(),
+            dconf().to_string(),
+        ],
+    )?;
+
+    let mut media = vec![];
+    for (i, w) in words.iter().enumerate() {
+        let mut sounds = String::new();
+        for (t, accent) in [(AudioType::UK, "uk"), (AudioType::US, "us")] {
+            if let Some(data) = audio(&w.phrase, t) {
+                let name = format!("rdict-{}-{}.mp3", file_name(&w.phrase), accent);
+                sounds.push_str(&format!(" [sound:{}]", name));
+                media.push((name, data));
+            }
+        }
+
+        let fields = fields(w, &sounds);
+        let id = millis + i as i64;
+        conn.execute(
+            "INSERT INTO notes VALUES (?1, ?2, ?3, ?4, -1, ?5, ?6, ?7, ?8, 0, '')",
+            params![
+                id,
+                guid(&w.phrase),
+                MODEL_ID,
+                secs,
+                tags(w),
+                fields.join("\x1f"),
+                w.phrase,
+                checksum(&w.phrase),
+            ],
+        )?;
+        // a new card, shown in the order of the book
+        conn.execute(
+            "INSERT INTO cards VALUES (?1, ?1, ?2, 0, ?3, -1, 0, 0, ?4, 0, 0, 0, 0, 0, 0, 0, 0, '')",
+            params![id, DECK_ID, secs, i as i64 + 1],
+        )?;
+    }
+    drop(conn);
+
+    let collection = fs::read(&tmp)?;
+    let _ = fs::remove_file(&tmp);
+
+    let mut zip = zip::ZipWriter::new(fs::File::create(path)?);
+    let opts = zip::write::FileOptions::default();
+    zip.start_file("collection.anki2", opts)?;
+    zip.write_all(&collection)?;
+    let mut index = BTreeMap::new();
+    for (i, (name, data)) in media.iter().enumerate() {
+        index.insert(i.to_string(), name);
+        zip.start_file(i.to_string(), opts)?;
+        zip.write_all(data)?;
+    }
+    zip.start_file("media", opts)?;
+    zip.write_all(serde_json::to_string(&index)?.as_bytes())?;
+    zip.finish()?;
+
+    Ok(Exported {
+        notes: words.len(),
+        media: media.len(),
+    })
+}
+
+fn fields(w: &Word, sounds: &str) -> [String; 4] {
+    let e = match &w.vocabulary {
+        Some(v) => Entry::new(w.engine.as_deref().unwrap_or(""), &w.phrase, Some(v), None),
+        None => Entry::new("", &w.phrase, None, None),
+    };
+
+    let mut phonetic = vec![];
+    if let Some(p) = &e.phonetic {
+        if let Some(uk) = &p.uk {
+            phonetic.push(format!("英 [{}]", escape(uk)));
+        }
+        if let Some(us) = &p.us {
+            phonetic.push(format!("美 [{}]", escape(us)));
+        }
+    }
+    let mut meanings: Vec<_> = e
+        .explains
+        .iter()
+        .map(|x| match &x.pos {
+            Some(p) => format!("<b>{}</b> {}", escape(p), escape(&x.meaning)),
+            None => escape(&x.meaning),
+        })
+        .collect();
+    if let Some(n) = &w.note {
+        meanings.push(format!("<i>{}</i>", escape(n)));
+    }
+    let examples: Vec<_> = e
+        .examples
+        .iter()
+        .map(|x| {
+            format!(
+                "{}<br><span class=\"trans\">{}</span>",
+                escape(&x.sentence),
+                escape(&x.translation)
+            )
+        })
+        .collect();
+
+    [
+        escape(&w.phrase),
+        format!("{}{}", phonetic.join(" "), sounds),
+        meanings.join("<br>"),
+        examples.join("<br><br>"),
+    ]
+}
+
+fn models(secs: i64) -> serde_json::Value {
+    let flds: Vec<_> = FIELDS
+        .iter()
+        .enumerate()
+        .map(|(i, name)| {
+            json!({
+                "name": name, "ord": i, "font": "Arial", "size": 20,
+                "media": [], "rtl": false, "sticky": false,
+            })
+        })
+        .collect();
+    json!({
+        MODEL_ID.to_string(): {
+            "id": MODEL_ID, "name": DECK_NAME, "type": 0, "mod": secs, "usn": -1,
+            "sortf": 0, "did": DECK_ID, "tags": [], "vers": [],
+            "flds": flds,
+            "tmpls": [{
+                "name": "Card 1", "ord": 0, "qfmt": QFMT, "afmt": AFMT,
+                "bqfmt": "", "bafmt": "", "did": null,
+            }],
+            "css": CSS,
+            "latexPre": "\\documentclass[12pt]{article}\n\\special{papersize=3in,5in}\n\\usepackage[utf8]{inputenc}\n\\usepackage{amssymb,amsmath}\n\\pagestyle{empty}\n\\setlength{\\parindent}{0in}\n\\begin{document}\n",
+            "latexPost": "\\end{document}",
+            // the card needs the phrase
+            "req": [[0, "all", [0]]],
+        }
+    })
+}
+
+fn decks(secs: i64) -> serde_json::Value {
+    let deck = |id: i64, name: &str| {
+        json!({
+            "id": id, "name": name, "mod": secs, "usn": -1, "desc": "",
+            "dyn": 0, "conf": 1, "collapsed": false,
+            "extendNew": 10, "extendRev": 50,
+            "newToday": [0, 0], "revToday": [0, 0], "lrnToday": [0, 0], "timeToday": [0, 0],
+        })
+    };
+    json!({
+        "1": deck(1, "Default"),
+        DECK_ID.to_string(): deck(DECK_ID, DECK_NAME),
+    })
+}
+
+fn dconf() -> serde_json::Value {
+    json!({
+        "1": {
+            "id": 1, "name": "Default", "mod": 0, "usn": 0,
+            "maxTaken": 60, "autoplay": true, "timer": 0, "replayq": true,
+            "new": {
+                "bury": true, "delays": [1, 10], "initialFactor": 2500,
+                "ints": [1, 4, 7], "order": 1, "perDay": 20, "separate": true,
+            },
+            "lapse": {"delays": [10], "leechAction": 0, "leechFails": 8, "minInt": 1, "mult": 0},
+            "rev": {
+                "bury": true, "ease4": 1.3, "fuzz": 0.05, "ivlFct": 1,
+                "maxIvl": 36500, "minSpace": 1, "perDay": 100,
+            },
+        }
+    })
+}
+
+fn conf() -> serde_json::Value {
+    json!({
+        "activeDecks": [1], "curDeck": 1, "newSpread": 0, "collapseTime": 1200,
+        "timeLim": 0, "estTimes": true, "dueCounts": true, "curModel": null,
+        "nextPos": 1, "sortType": "noteFld", "sortBackwards": false, "addToCur": true,
+    })
+}
+
+// anki keeps tags space separated, with a space around
+fn tags(w: &Word) -> String {
+    match w.tags.is_empty() {
+        true => String::new(),
+        false => {
+            let t: Vec<_> = w.tags.iter().map(|t| t.replace(' ', "_")).collect();
+            format!(" {} ", t.join(" "))
+        }
+    }
+}
+
+fn escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+// "a b" and "a-b" would share a name, so the guid's hash goes in too.
+// the guid itself has '/' and such, hence hex
+fn file_name(phrase: &str) -> String {
+    let safe: String = phrase
+        .chars()
+        .map(|c| match c.is_alphanumeric() {
+            true => c,
+            false => '_',
+        })
+        .collect();
+    let hex: String = digest(phrase)[..4]
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect();
+    format!("{}-{}", safe, hex)
+}
+
+// the same phrase always gets the same guid, in anki's base91 alphabet
+fn guid(phrase: &str) -> String {
+    const ALPHABET: &[u8] =
+        b"abcdefghijklmnopqrstuvwxyzABCDEFGHIJKLMNOPQRSTUVWXYZ0123456789!#$%&()*+,-./:;<=>?@[]^_`{|}~";
+    let h = digest(phrase);
+    let mut n = u64::from_be_bytes(h[..8].try_into().unwrap());
+    let mut s = vec![];
+    while n > 0 {
+        s.push(ALPHABET[(n % 91) as usize]);
+        n /= 91;
+    }
+    s.reverse();
+    String::from_utf8(s).unwrap()
+}
+
+// what anki uses to find duplicates: the first 8 hex digits of the sha1 of the sort field
+fn checksum(phrase: &str) -> i64 {
+    let h = sha1(phrase.as_bytes());
+    u32::from_be_bytes(h[..4].try_into().unwrap()) as i64
+}
+
+fn digest(phrase: &str) -> [u8; 20] {
+    sha1(format!("rdict:{}", phrase).as_bytes())
+}
+
+fn sha1(data: &[u8]) -> [u8; 20] {
+    sha1_smol::Sha1::from(data).digest().bytes()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::handler::{youdao::Youdao, Dictionary};
+    use std::io::Read;
+
+    #[test]
+    fn test_sha1() {
+        let hex = |h: [u8; 20]| h.iter().map(|b| format!("{:02x}", b)).collect::<String>();
+        assert_eq!(hex(sha1(b"")), "da39a3ee5e6b4b0d3255bfef95601890afd80709");
+        assert_eq!(
+            hex(sha1(b"abc")),
+            "a9993e364706816aba3e25717850c26c9cd0d89d"
+        );
+        assert_eq!(checksum("abc"), 0xa9993e36);
+        assert_eq!(guid("hello"), guid("hello"));
+        assert_ne!(guid("hello"), guid("world"));
+    }
+
+    #[test]
+    fn test_file_name() {
+        let names: Vec<_> = ["a b", "a-b", "a_b"].iter().map(|p| file_name(p)).collect();
+        assert!(names.iter().all(|n| n.starts_with("a_b-")));
+        assert_ne!(names[0], names[1]);
+        assert_ne!(names[1], names[2]);
+        assert_ne!(names[0], names[2]);
+        assert_eq!(file_name("a b"), names[0]);
+    }
+
+    #[test]
+    fn test_export() {
+        let raw = fs::read("tests/fixtures/youdao/hello.json").unwrap();
+        let vb = Youdao.parse(&raw).unwrap();
+        let words = vec![
+            Word::new("hello", Some(("youdao", &vb))).tagged(vec!["cet 4".into()]),
+            Word::new("a<b", None),
+        ];
+        let path = env::temp_dir().join(format!("rdict-test-{}.apkg", process::id()));
+        let audio = |p: &str, t: AudioType| match (p, t) {
+            ("hello", AudioType::UK) => Some(b"mp3".to_vec()),
+            _ => None,
+        };
+        let n = export(&words, &path, audio).unwrap();
+        assert_eq!(n, Exported { notes: 2, media: 1 });
+
+        let mut zip = zip::ZipArchive::new(fs::File::open(&path).unwrap()).unwrap();
+        let mut media = String::new();
+        zip.by_name("media")
+            .unwrap()
+            .read_to_string(&mut media)
+            .unwrap();
+        let name = format!("rdict-{}-uk.mp3", file_name("hello"));
+        assert_eq!(media, format!(r#"{{"0":"{}"}}"#, name));
+        let mut col = vec![];
+        zip.by_name("collection.anki2")
+            .unwrap()
+            .read_to_end(&mut col)
+            .unwrap();
+        let db = env::temp_dir().join(format!("rdict-test-{}.anki2", process::id()));
+        fs::write(&db, col).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        let conn = Connection::open(&db).unwrap();
+        let (guid_, tags, flds): (String, String, String) = conn
+            .query_row(
+                "SELECT guid, tags, flds FROM notes WHERE sfld = 'hello'",
+                [],
+                |r| Ok((r.get(0)?, r.get(1)?, r.get(2)?)),
+            )
+            .unwrap();
+        assert_eq!(guid_, guid("hello"));
+        assert_eq!(tags, " cet_4 ");
+        let f: Vec<_> = flds.split('\x1f').collect();
+        assert_eq!(f.len(), 4);
+        assert!(f[1].contains(&format!("[sound:{}]", name)));
+        assert!(f[2].contains("<b>int.</b> 喂"));
+        assert!(f[3].contains("喂，有人吗？"));
+
+        let escaped: String = conn
+            .query_row("SELECT flds FROM notes WHERE sfld = 'a<b'", [], |r| {
+                r.get(0)
+            })
+            .unwrap();
+        assert!(escaped.starts_with("a&lt;b\x1f"));
+        let cards: i64 = conn
+            .query_row("SELECT count(*) FROM cards", [], |r| r.get(0))
+            .unwrap();
+        assert_eq!(cards, 2);
+        fs::remove_file(&db).unwrap();
+    }
+}
@@ -0,0 +1,353 @@
+use std::collections::{HashMap, HashSet};
+use std::io::{self, BufReader, Read};
+use std::{fs, path::PathBuf, sync::Arc};
+
+use crate::batch::{self, JOBS};
+use crate::config;
+use crate::handler::schema::Entry;
+use crate::handler::{ecdict::Ecdict, Dictionary, Engines};
+use crate::query::{self, Aggregate};
+use crate::result::Result;
+
+// one word per line, the same as a batch file
+const KNOWN_FILE: &str = "known.txt";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Gloss {
+    // right after the word
+    Inline,
+    // numbered notes at the end, a tooltip in html
+    Footnote,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Markup {
+    Text,
+    Markdown,
+    // ruby for inline glosses
+    Html,
+}
+
+pub struct AnnotateOptions {
+    // "-" for stdin
+    pub file: PathBuf,
+    // words to leave alone [default: known.txt in the config directory]
+    pub known: Option<PathBuf>,
+    // the most common words by the ECDICT rank are known too, 0 for none
+    pub common: u32,
+    pub gloss: Gloss,
+    pub markup: Markup,
+}
+
+// the first explain of a word, and its first sense for inline glosses
+#[derive(Debug, Clone, PartialEq)]
+struct Note {
+    full: String,
+    short: String,
+}
+
+impl Note {
+    fn new(e: &Entry) -> Option<Self> {
+        let x = e.explains.first()?;
+        let short = x
+            .meaning
+            .split([',', '，', ';', '；'])
+            .next()
+            .unwrap_or_default()
+            .trim()
+            .to_string();
+        Some(Note {
+            full: match &x.pos {
+                Some(p) => format!("{} {}", p, x.meaning),
+                None => x.meaning.clone(),
+            },
+            short,
+        })
+    }
+}
+
+// Byte ranges of the words. Contractions, acronyms and CamelCase names
+// ("don't", "HTTP", "JavaScript") aren't worth a gloss and are skipped.
+fn words(text: &str) -> Vec<(usize, usize)> {
+    let mut res = vec![];
+    let mut start = None;
+    let mut chars = text.char_indices().peekable();
+    while let Some((i, c)) = chars.next() {
+        let inner = c == '\''
+            && start.is_some()
+            && chars.peek().is_some_and(|(_, n)| n.is_ascii_alphabetic());
+        match (c.is_ascii_alphabetic() || inner, start) {
+            (true, None) => start = Some(i),
+            (false, Some(s)) => {
+                res.push((s, i));
+                start = None;
+            }
+            _ => {}
+        }
+    }
+    if let Some(s) = start {
+        res.push((s, text.len()));
+    }
+    res.retain(|&(s, e)| {
+        let w = &text[s..e];
+        w.len() > 2 && !w.contains('\'') && !w.chars().skip(1).any(|c| c.is_ascii_uppercase())
+    });
+    res
+}
+
+// The words worth a lookup, in the order and spelling they first show up,
+// so that "China" isn't "china". `rank` is how common a word is, 1 for the
+// most common one.
+fn unknown<F>(text: &str, known: &HashSet<String>, common: u32, rank: F) -> Vec<String>
+where
+    F: Fn(&str) -> Option<u32>,
+{
+    let mut seen = HashSet::new();
+    words(text)
+        .into_iter()
+        .map(|(s, e)| &text[s..e])
+        .filter(|w| seen.insert(w.to_lowercase()))
+        .filter(|w| !known.contains(&w.to_lowercase()))
+        .filter(|w| !rank(w).is_some_and(|r| r > 0 && r <= common))
+        .map(String::from)
+        .collect()
+}
+
+// ECDICT ranks words by frq (COCA) and bnc, the more common one counts
+fn ecdict_rank(ecdict: &Ecdict, word: &str) -> Option<u32> {
+    let r = ecdict.get(word).ok()??;
+    [r.frq, r.bnc]
+        .into_iter()
+        .flatten()
+        .filter(|n| *n > 0)
+        .min()
+}
+
+fn escape(s: &str, markup: Markup) -> String {
+    match markup {
+        Markup::Html => s
+            .replace('&', "&amp;")
+            .replace('<', "&lt;")
+            .replace('>', "&gt;")
+            .replace('"', "&quot;"),
+        _ => s.to_string(),
+    }
+}
+
+fn mark(word: &str, note: &Note, n: usize, gloss: Gloss, markup: Markup) -> String {
+    let (w, full, short) = (
+        escape(word, markup),
+        escape(&note.full, markup),
+        escape(&note.short, markup),
+    );
+    match (markup, gloss) {
+        (Markup::Text, Gloss::Inline) => format!("{}({})", w, short),
+        (Markup::Text, Gloss::Footnote) => format!("{}[{}]", w, n),
+        (Markup::Markdown, Gloss::Inline) => format!("{} (*{}*)", w, short),
+        (Markup::Markdown, Gloss::Footnote) => format!("{}[^{}]", w, n),
+        (Markup::Html, Gloss::Inline) => format!("<ruby>{}<rt>{}</rt></ruby>", w, short),
+        (Markup::Html, Gloss::Footnote) => {
+            format!("<abbr title=\"{full}\">{w}</abbr><sup><a href=\"#rdict-{n}\">{n}</a></sup>")
+        }
+    }
+}
+
+fn footnotes(notes: &[(String, &Note)], markup: Markup) -> String {
+    if notes.is_empty() {
+        return String::new();
+    }
+    let lines: Vec<_> = notes
+        .iter()
+        .enumerate()
+        .map(|(i, (word, note))| {
+            let (n, word, full) = (i + 1, escape(word, markup), escape(&note.full, markup));
+            match markup {
+                Markup::Text => format!("[{}] {}  {}", n, word, full),
+                Markup::Markdown => format!("[^{}]: **{}** {}", n, word, full),
+                Markup::Html => format!("<li id=\"rdict-{}\"><b>{}</b> {}</li>", n, word, full),
+            }
+        })
+        .collect();
+    match markup {
+        Markup::Html => format!("\n<ol>\n{}\n</ol>\n", lines.join("\n")),
+        _ => format!("\n\n{}\n", lines.join("\n")),
+    }
+}
+
+// The text with a gloss on the first time each word of `notes` shows up.
+// Html comes out as paragraphs, split on blank lines.
+fn render(text: &str, notes: &HashMap<String, Note>, gloss: Gloss, markup: Markup) -> String {
+    let mut out = String::new();
+    let mut used = vec![];
+    let mut last = 0;
+    for (s, e) in words(text) {
+        let key = text[s..e].to_lowercase();
+        let Some(note) = notes.get(&key) else {
+            continue;
+        };
+        if used
+            .iter()
+            .any(|(w, _): &(String, _)| w.to_lowercase() == key)
+        {
+            continue;
+        }
+        used.push((text[s..e].to_string(), note));
+        out.push_str(&escape(&text[last..s], markup));
+        out.push_str(&mark(&text[s..e], note, used.len(), gloss, markup));
+        last = e;
+    }
+    out.push_str(&escape(&text[last..], markup));
+
+    if markup == Markup::Html {
+        out = out
+            .split("\n\n")
+            .map(str::trim)
+            .filter(|p| !p.is_empty())
+            .map(|p| format!("<p>{}</p>", p))
+            .collect::<Vec<_>>()
+            .join("\n");
+        out.push('\n');
+    }
+    if gloss == Gloss::Footnote {
+        out = out.trim_end().to_string();
+        out.push_str(&footnotes(&used, markup));
+    }
+    out
+}
+
+// Looks up the words of the file which aren't known, with the engines of a
+// plain lookup. Answers are cached, so only new words are slow on a rerun.
+pub fn run(opts: AnnotateOptions) -> Result<String> {
+    let mut text = String::new();
+    match opts.file.to_str() {
+        Some("-") => io::stdin().read_to_string(&mut text)?,
+        _ => fs::File::open(&opts.file)?.read_to_string(&mut text)?,
+    };
+
+    let known_file = opts
+        .known
+        .clone()
+        .unwrap_or_else(|| config::dir().join(KNOWN_FILE));
+    let known: HashSet<_> = match fs::File::open(&known_file) {
+        Ok(f) => batch::phrases(BufReader::new(f))?
+            .into_iter()
+            .map(|w| w.to_lowercase())
+            .collect(),
+        // only an error if it was asked for
+        Err(e) if opts.known.is_some() => return Err(e.into()),
+        Err(_) => HashSet::new(),
+    };
+    let ecdict = Ecdict::new();
+    let words = unknown(&text, &known, opts.common, |w| ecdict_rank(&ecdict, w));
+
+    let (names, mode) = query::default_engines();
+    let engines = names
+        .iter()
+        .map(|n| Engines::get(n).map(Arc::from))
+        .collect::<Result<Vec<Arc<dyn Dictionary>>>>()?;
+    let bar = batch::bar(words.len() as u64);
+    let mut notes = HashMap::new();
+    let keep = |res: &mut Aggregate| res.keep().map(|_| ());
+    batch::lookup(&words, &engines, mode, JOBS, keep, |res, _| {
+        bar.inc(1);
+        if let Some((engine, vb)) = res.answer() {
+            let e = Entry::new(engine, res.phrase(), Some(vb), None);
+            if let Some(n) = Note::new(&e) {
+                notes.insert(res.phrase().to_lowercase(), n);
+            }
+        }
+        Ok(())
+    })?;
+    bar.finish_and_clear();
+
+    Ok(render(&text, &notes, opts.gloss, opts.markup))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const TEXT: &str = "The cache can't perceive HTTP, so it's Perceived as a <b>stale</b> JavaScript value.\n\nPerceive again.";
+
+    fn notes() -> HashMap<String, Note> {
+        let note = |full: &str, short: &str| Note {
+            full: full.to_string(),
+            short: short.to_string(),
+        };
+        HashMap::from([
+            ("perceive".to_string(), note("vt. 察觉, 感知", "察觉")),
+            ("stale".to_string(), note("adj. 陈旧的", "陈旧的")),
+        ])
+    }
+
+    #[test]
+    fn test_words() {
+        let ws: Vec<_> = words(TEXT).iter().map(|&(s, e)| &TEXT[s..e]).collect();
+        assert_eq!(
+            ws,
+            [
+                "The",
+                "cache",
+                "perceive",
+                "Perceived",
+                "stale",
+                "value",
+                "Perceive",
+                "again"
+            ]
+        );
+
+        let known = HashSet::from(["value".to_string()]);
+        let rank = |w: &str| match w {
+            "The" => Some(1),
+            "again" => Some(300),
+            "cache" => Some(9000),
+            _ => None,
+        };
+        assert_eq!(
+            unknown(TEXT, &known, 5000, rank),
+            ["cache", "perceive", "Perceived", "stale"]
+        );
+        assert_eq!(unknown(TEXT, &known, 0, rank).len(), 6);
+    }
+
+    #[test]
+    fn test_note() {
+        let raw = std::fs::read("tests/fixtures/youdao/hello.json").unwrap();
+        let vb = crate::handler::youdao::Youdao.parse(&raw).unwrap();
+        let n = Note::new(&Entry::new("youdao", "hello", Some(&vb), None)).unwrap();
+        assert_eq!(n.full, "int. 喂；哈罗，你好，您好");
+        assert_eq!(n.short, "喂");
+        assert!(Note::new(&Entry::new("youdao", "x", None, None)).is_none());
+    }
+
+    #[test]
+    fn test_render() {
+        let notes = notes();
+        let text = render(TEXT, &notes, Gloss::Inline, Markup::Text);
+        assert!(text.starts_with("The cache can't perceive(察觉) HTTP, so"));
+        // only the first one
+        assert!(text.ends_with("<b>stale(陈旧的)</b> JavaScript value.\n\nPerceive again."));
+
+        let md = render(TEXT, &notes, Gloss::Footnote, Markup::Markdown);
+        assert!(md.contains("perceive[^1]"));
+        assert!(md.contains("stale[^2]"));
+        assert!(md.ends_with(
+            "again.\n\n[^1]: **perceive** vt. 察觉, 感知\n[^2]: **stale** adj. 陈旧的\n"
+        ));
+
+        let html = render(TEXT, &notes, Gloss::Inline, Markup::Html);
+        assert!(html.starts_with("<p>The cache can't <ruby>perceive<rt>察觉</rt></ruby>"));
+        assert!(html.contains("&lt;b&gt;<ruby>stale<rt>陈旧的</rt></ruby>&lt;/b&gt;"));
+        assert!(html.ends_with("value.</p>\n<p>Perceive again.</p>\n"));
+
+        let html = render(TEXT, &notes, Gloss::Footnote, Markup::Html);
+        assert!(html.contains(
+            "<abbr title=\"vt. 察觉, 感知\">perceive</abbr><sup><a href=\"#rdict-1\">1</a></sup>"
+        ));
+        assert!(html.ends_with("<li id=\"rdict-2\"><b>stale</b> adj. 陈旧的</li>\n</ol>\n"));
+
+        let none = render(TEXT, &HashMap::new(), Gloss::Footnote, Markup::Text);
+        assert_eq!(none, TEXT);
+    }
+}
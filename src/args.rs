@@ -1,87 +1,526 @@
-use std::env;
+use std::{env, path::PathBuf};
 
-use clap::{ArgGroup, Parser};
+use clap::{ArgGroup, CommandFactory, Parser, Subcommand};
 
-use crate::handler::{AudioType, Engines};
+use crate::annotate::{AnnotateOptions, Gloss, Markup};
+use crate::batch::{self, BatchOptions};
+use crate::config::{self, Config, Source, DEFAULT_FALLBACK};
+use crate::handler::{AudioType, Dictionary, Engines, SECTIONS};
+use crate::query::{Format, Mode};
+use crate::quiz::{Kind, QuizOptions};
 use crate::result::Result;
+use crate::review::ReviewOptions;
+use crate::theme::{self, Theme};
+use crate::util::{self, ColorMode, Style};
 
 #[derive(Parser, Debug, Clone)]
 #[clap(author, version, about, long_about  = None)]
-#[clap(group(ArgGroup::new("query").args(&["phrase", "dict","voice"]).multiple(true).requires("phrase")))]
-#[clap(group(ArgGroup::new("function").args(&["list"]).conflicts_with_all(&["query"])))]
+#[clap(group(ArgGroup::new("input").args(&["phrase", "batch"])))]
+#[clap(group(ArgGroup::new("query").args(&["dict", "all", "fallback", "voice", "prefetch", "format", "save", "jobs"]).multiple(true).requires("input")))]
+#[clap(group(ArgGroup::new("function").args(&["list", "show-db"]).conflicts_with_all(&["query", "input"])))]
 pub struct Args {
     /// What do you want to query?
     #[clap(short, long, multiple_values = true)]
     phrase: Vec<String>,
 
-    /// Where do you want to query from?
+    /// Where do you want to query from? separate several with commas [default: youdao, or engines of the config]
     #[clap(
         short,
         long,
-        default_value = "youdao",
-        default_missing_value = "youdao",
-        possible_values= ["youdao","bing"]
+        value_delimiter = ',',
+        possible_values = Engines::names()
     )]
-    dict: String,
+    dict: Option<Vec<String>>,
 
-    /// query with voice, uk or 1 for uk, us or 2 for us
+    /// query from all the engines
+    #[clap(short, long)]
+    all: bool,
+
+    /// try the engines in order until one answers, cached answers first [default: youdao,ecdict,stardict, or fallback of the config]
+    #[clap(
+        short,
+        long,
+        value_delimiter = ',',
+        min_values = 0,
+        possible_values = Engines::names()
+    )]
+    fallback: Option<Vec<String>>,
+
+    /// query with voice, uk or 1 for uk, us or 2 for us [default: uk, or voice of the config]
+    #[clap(
+        short,
+        long,
+        possible_values= ["us","uk", "1", "2"],
+        conflicts_with = "batch"
+    )]
+    voice: Option<Option<String>>,
+
+    /// download both the uk and us audio, so that -v works offline
+    #[clap(long)]
+    prefetch: bool,
+
+    /// add the phrase to the word book as well
+    #[clap(long)]
+    save: bool,
+
+    /// print as text, or as json documents or csv rows for scripts [default: text]
+    #[clap(long, possible_values = ["text", "json", "jsonl", "csv"])]
+    format: Option<String>,
+
+    /// look up every line of a file, `rdict -` reads them from stdin
+    #[clap(long, value_name = "FILE")]
+    batch: Option<PathBuf>,
+
+    /// how many lookups of --batch run at once [default: 4]
+    #[clap(short, long, requires = "batch")]
+    jobs: Option<usize>,
+
+    /// list query history [default: 5]
+    #[clap(short, long)]
+    list: Option<Option<usize>>,
+
+    /// list databases of the dictd server
+    #[clap(long)]
+    show_db: bool,
+
+    /// when to use colors, auto turns them off if stdout isn't a terminal or NO_COLOR is set [default: auto]
+    #[clap(long, possible_values = ["always", "never", "auto"])]
+    color: Option<String>,
+
+    /// dark, light, or a theme in the config directory [default: dark]
+    #[clap(long)]
+    theme: Option<String>,
+
+    /// sections to show, separate several with commas, all for every one [default: phonetic,explains,forms,examples]
     #[clap(
         short,
         long,
-        default_missing_value = "uk",
-        possible_values= ["us","uk", "1", "2"]
+        value_delimiter = ',',
+        possible_values = SECTIONS.iter().copied().chain(["all"])
     )]
-    voice: Option<String>,
+    sections: Option<Vec<String>>,
 
-    /// list query history
-    #[clap(short, long, default_missing_value = "5")]
-    list: Option<usize>,
+    #[clap(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Subcommand, Debug, Clone)]
+enum Command {
+    /// import a local dictionary, eg: rdict import ecdict ecdict.csv
+    Import {
+        #[clap(possible_values = ["ecdict"])]
+        format: String,
+
+        /// the dictionary file
+        file: PathBuf,
+    },
+    /// manage the cache of online engines and audio
+    Cache {
+        #[clap(subcommand)]
+        action: CacheAction,
+    },
+    /// the word book, eg: rdict book add hello -t cet4
+    Book {
+        #[clap(subcommand)]
+        action: BookAction,
+    },
+    /// review the due words of the book, spaced by SM-2
+    Review {
+        /// review at most this many words
+        #[clap(short = 'n', long, default_value_t = 20)]
+        limit: usize,
+
+        /// only the words with this tag
+        #[clap(short, long)]
+        tag: Option<String>,
+
+        /// play each word first, uk or 1 for uk, us or 2 for us [default: uk, or voice of the config]
+        #[clap(short, long, possible_values = ["us", "uk", "1", "2"])]
+        voice: Option<Option<String>>,
+
+        /// only count the words due today and tomorrow
+        #[clap(short, long)]
+        summary: bool,
+    },
+    /// practice the words of the book, eg: rdict quiz choice -n 10
+    Quiz {
+        /// dictation: spell what you hear, meaning: type the word of a meaning, choice: pick the meaning of a word
+        #[clap(possible_values = ["dictation", "meaning", "choice"])]
+        kind: String,
+
+        /// how many questions
+        #[clap(short = 'n', long, default_value_t = 10)]
+        count: usize,
+
+        /// only the words with this tag
+        #[clap(short, long)]
+        tag: Option<String>,
+
+        /// grade the review schedule with the answers, right as 4 and wrong as 1
+        #[clap(short, long)]
+        schedule: bool,
+    },
+    /// gloss the words of a text you may not know, eg: rdict annotate paper.txt -g footnote
+    Annotate {
+        /// the text, - for stdin
+        file: PathBuf,
+
+        /// words you know, one per line [default: known.txt in the config directory]
+        #[clap(short, long, value_name = "FILE")]
+        known: Option<PathBuf>,
+
+        /// the most common words by the ECDICT rank are known too, 0 for none
+        #[clap(short, long, default_value_t = 5000)]
+        common: u32,
+
+        /// inline: after the word, footnote: numbered notes at the end
+        #[clap(short, long, default_value = "inline", possible_values = ["inline", "footnote"])]
+        gloss: String,
+
+        /// html glosses inline as ruby and footnotes as tooltips
+        #[clap(short, long, default_value = "text", possible_values = ["text", "markdown", "html"])]
+        format: String,
+    },
+    /// browse lookups and history in a full-screen interface
+    Tui,
+    /// the config file, eg: rdict config show
+    Config {
+        #[clap(subcommand)]
+        action: ConfigAction,
+    },
+}
+
+#[derive(Subcommand, Debug, Clone)]
+enum ConfigAction {
+    /// print the effective settings and where they come from
+    Show,
+}
+
+#[derive(Subcommand, Debug, Clone)]
+pub enum CacheAction {
+    /// count entries and bytes of each cache
+    Stats,
+    /// remove all the cached entries
+    Clear,
+    /// remove expired entries, and the least recently used ones over the size limit
+    Prune,
+    /// list entries which can't be parsed anymore
+    Verify,
+}
+
+#[derive(Subcommand, Debug, Clone)]
+pub enum BookAction {
+    /// look up a phrase and add it, or add tags and a note to one already there
+    Add {
+        #[clap(required = true)]
+        phrase: Vec<String>,
+
+        /// separate several with commas
+        #[clap(short, long, value_delimiter = ',')]
+        tags: Vec<String>,
+
+        #[clap(short, long)]
+        note: Option<String>,
+    },
+    /// remove a phrase
+    Rm {
+        #[clap(required = true)]
+        phrase: Vec<String>,
+    },
+    /// list the phrases, newest first
+    Ls {
+        /// only the ones with this tag
+        #[clap(short, long)]
+        tag: Option<String>,
+    },
+    /// show a phrase as it was when added
+    Show {
+        #[clap(required = true)]
+        phrase: Vec<String>,
+    },
+    /// write the book as an Anki deck, with the cached audio
+    Export {
+        /// the .apkg file to write, import it again to update the notes
+        #[clap(long, value_name = "FILE")]
+        anki: PathBuf,
+
+        /// only the phrases with this tag
+        #[clap(short, long)]
+        tag: Option<String>,
+    },
 }
 
 pub enum CliAction {
     Query(QueryContent), //phrases and engine
     ListHistory(usize),
+    ShowDb,
+    Import(PathBuf),
+    Cache(CacheAction),
+    Book(BookAction),
+    Review(ReviewOptions),
+    Quiz(QuizOptions),
+    Batch(BatchOptions),
+    Annotate(AnnotateOptions),
+    ShowConfig,
+    Tui,
     Other,
 }
 pub struct QueryContent {
     pub phrase: String,
-    pub engine: Engines,
+    pub engines: Vec<Box<dyn Dictionary>>,
+    pub mode: Mode,
     pub voice: Option<AudioType>,
+    pub prefetch: bool,
+    pub save: bool,
+    pub format: Format,
 }
 
 pub fn parse_args() -> Result<CliAction> {
     let input = handle_input();
     let args = Args::parse_from(input);
 
-    if !args.phrase.is_empty() {
+    // flags win over the environment and the config file
+    let mut cfg = Config::load()?;
+    if let Some(d) = &args.dict {
+        cfg.engines.set(d.clone(), Source::Cli);
+    }
+    if let Some(f) = args.fallback.as_ref().filter(|f| !f.is_empty()) {
+        cfg.fallback.set(Some(f.clone()), Source::Cli);
+    }
+    if let Some(Some(v)) = &args.voice {
+        cfg.voice.set(v.clone(), Source::Cli);
+    }
+    if let Some(f) = &args.format {
+        cfg.format.set(f.clone(), Source::Cli);
+    }
+    if let Some(Some(n)) = args.list {
+        cfg.history.set(n, Source::Cli);
+    }
+    if let Some(c) = &args.color {
+        cfg.color.set(c.clone(), Source::Cli);
+    }
+    if let Some(t) = &args.theme {
+        cfg.theme.set(t.clone(), Source::Cli);
+    }
+    if let Some(s) = &args.sections {
+        cfg.sections.set(s.clone(), Source::Cli);
+    }
+    config::init(cfg);
+    let cfg = config::get();
+
+    util::set_color(match cfg.color.value.as_str() {
+        "always" => ColorMode::Always,
+        "never" => ColorMode::Never,
+        _ => ColorMode::Auto,
+    });
+    // a typo in the theme shouldn't keep every command from running
+    theme::set_theme(Theme::load(&cfg.theme.value).unwrap_or_else(|e| {
+        eprintln!(
+            "{}theme {}: {}, the default one is used",
+            ' '.align_right(4),
+            cfg.theme.value,
+            e
+        );
+        Theme::dark()
+    }));
+
+    match args.command {
+        Some(Command::Import { file, .. }) => return Ok(CliAction::Import(file)),
+        Some(Command::Cache { action }) => return Ok(CliAction::Cache(action)),
+        Some(Command::Book { action }) => return Ok(CliAction::Book(action)),
+        Some(Command::Config { .. }) => return Ok(CliAction::ShowConfig),
+        Some(Command::Review {
+            limit,
+            tag,
+            voice,
+            summary,
+        }) => {
+            // the accent of the config, when -v has none or autoplay is on
+            let voice = match voice {
+                Some(Some(v)) => Some(AudioType::try_from(v)?),
+                Some(None) => Some(AudioType::try_from(cfg.voice.value.clone())?),
+                None if cfg.autoplay.value => Some(AudioType::try_from(cfg.voice.value.clone())?),
+                None => None,
+            };
+            return Ok(CliAction::Review(ReviewOptions {
+                limit,
+                tag,
+                voice,
+                summary,
+            }));
+        }
+        Some(Command::Quiz {
+            kind,
+            count,
+            tag,
+            schedule,
+        }) => {
+            return Ok(CliAction::Quiz(QuizOptions {
+                kind: match kind.as_str() {
+                    "dictation" => Kind::Dictation,
+                    "meaning" => Kind::Meaning,
+                    _ => Kind::Choice,
+                },
+                count,
+                tag,
+                schedule,
+            }))
+        }
+        Some(Command::Annotate {
+            file,
+            known,
+            common,
+            gloss,
+            format,
+        }) => {
+            return Ok(CliAction::Annotate(AnnotateOptions {
+                file,
+                known,
+                common,
+                gloss: match gloss.as_str() {
+                    "footnote" => Gloss::Footnote,
+                    _ => Gloss::Inline,
+                },
+                markup: match format.as_str() {
+                    "markdown" => Markup::Markdown,
+                    "html" => Markup::Html,
+                    _ => Markup::Text,
+                },
+            }))
+        }
+        Some(Command::Tui) => return Ok(CliAction::Tui),
+        None => {}
+    }
+
+    if !args.phrase.is_empty() || args.batch.is_some() {
+        // -d and --all win over the fallback chain, which may come from the config
+        let default_chain = || match &cfg.fallback.value {
+            Some(f) => f.clone(),
+            None => DEFAULT_FALLBACK.iter().map(|v| v.to_string()).collect(),
+        };
+        let (mut names, mode) = match (args.all, args.dict.is_some(), &args.fallback) {
+            (true, _, _) => (
+                Engines::names().into_iter().map(String::from).collect(),
+                Mode::All,
+            ),
+            (false, true, _) => (cfg.engines.value.clone(), Mode::All),
+            (false, false, Some(_)) => (default_chain(), Mode::Fallback),
+            (false, false, None) => match &cfg.fallback.value {
+                Some(f) => (f.clone(), Mode::Fallback),
+                None => (cfg.engines.value.clone(), Mode::All),
+            },
+        };
+        let mut seen = vec![];
+        names.retain(|n| match seen.contains(n) {
+            true => false,
+            false => {
+                seen.push(n.clone());
+                true
+            }
+        });
+
+        let format = match cfg.format.value.as_str() {
+            "json" => Format::Json,
+            "jsonl" => Format::Jsonl,
+            "csv" => Format::Csv,
+            _ => Format::Text,
+        };
+        if let Some(file) = args.batch {
+            return Ok(CliAction::Batch(BatchOptions {
+                file,
+                engines: names,
+                mode,
+                jobs: args.jobs.unwrap_or(batch::JOBS).max(1),
+                format,
+                save: args.save,
+                prefetch: args.prefetch,
+            }));
+        }
+
         let mut c = QueryContent {
             phrase: args.phrase.join(" "),
-            engine: Engines::from(args.dict),
+            engines: names
+                .iter()
+                .map(|n| Engines::get(n))
+                .collect::<Result<_>>()?,
+            mode,
             voice: None,
+            prefetch: args.prefetch,
+            save: args.save,
+            format,
         };
-        if let Some(t) = args.voice {
-            c.voice = Some(AudioType::try_from(t)?)
+        // -v alone plays the accent of the config, autoplay plays it without -v
+        if args.voice.is_some() || cfg.autoplay.value {
+            c.voice = Some(AudioType::try_from(cfg.voice.value.clone())?)
         }
         return Ok(CliAction::Query(c));
-    } else if let Some(list) = args.list {
-        return Ok(CliAction::ListHistory(list));
+    } else if args.list.is_some() {
+        return Ok(CliAction::ListHistory(cfg.history.value));
+    } else if args.show_db {
+        return Ok(CliAction::ShowDb);
     } else {
         return Ok(CliAction::Other);
     }
 
     fn handle_input() -> Vec<String> {
-        let mut input: Vec<_> = env::args_os().map(|v| v.into_string().unwrap()).collect();
+        rewrite(env::args_os().map(|v| v.into_string().unwrap()).collect())
+    }
+}
 
-        assert_ne!(input.len(), 0);
+// `rdict hello` is `rdict -p hello`, and so is `rdict book` unless the words
+// after it make a command, like `rdict book ls`
+fn rewrite(mut input: Vec<String>) -> Vec<String> {
+    assert_ne!(input.len(), 0);
 
-        if input.len() == 1 {
-            return Vec::new();
-        }
+    if input.len() == 1 {
+        return Vec::new();
+    }
 
-        let phrase = input.get(1).unwrap();
-        if !phrase.starts_with('-') {
-            input.insert(1, "-p".to_string());
-        }
-        input
+    // `rdict -` looks up the lines of stdin
+    if input[1] == "-" {
+        input.splice(1..2, ["--batch".to_string(), "-".to_string()]);
+        return input;
+    }
+
+    let phrase = &input[1];
+    let is_command = Args::command()
+        .get_subcommands()
+        .any(|c| c.get_name() == phrase)
+        && match Args::try_parse_from(&input) {
+            Ok(_) => true,
+            Err(e) => matches!(
+                e.kind(),
+                clap::ErrorKind::DisplayHelp | clap::ErrorKind::DisplayVersion
+            ),
+        };
+    if !phrase.starts_with('-') && !is_command {
+        input.insert(1, "-p".to_string());
+    }
+    input
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn rewritten(args: &str) -> Vec<String> {
+        rewrite(args.split_whitespace().map(String::from).collect())
+    }
+
+    #[test]
+    fn test_rewrite() {
+        assert_eq!(rewritten("rdict hello"), ["rdict", "-p", "hello"]);
+        assert_eq!(rewritten("rdict book"), ["rdict", "-p", "book"]);
+        assert_eq!(
+            rewritten("rdict review this"),
+            ["rdict", "-p", "review", "this"]
+        );
+        assert_eq!(
+            rewritten("rdict book ls -t cet4"),
+            ["rdict", "book", "ls", "-t", "cet4"]
+        );
+        assert_eq!(rewritten("rdict book --help"), ["rdict", "book", "--help"]);
+        assert_eq!(rewritten("rdict -p book"), ["rdict", "-p", "book"]);
+        assert_eq!(rewritten("rdict -"), ["rdict", "--batch", "-"]);
     }
 }
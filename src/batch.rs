@@ -0,0 +1,454 @@
+use std::collections::BTreeMap;
+use std::io::{self, BufRead, BufReader, IsTerminal};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{mpsc, Arc, Mutex};
+use std::{fmt, fs, path::PathBuf, thread};
+
+use indicatif::{ProgressBar, ProgressStyle};
+
+use crate::book::{Book, Word};
+use crate::cache::{self, Policy};
+use crate::handler::schema::Document;
+use crate::handler::{AudioType, Capabilities, Dictionary, Engines, VocabBody};
+use crate::meta::DictMsg;
+use crate::query::{Aggregate, Format, Mode};
+use crate::result::{Error, Result};
+use crate::util::{self, ColorfulRole as Role, Style};
+
+// lookups at once, unless --jobs says otherwise
+pub const JOBS: usize = 4;
+
+const CSV_HEADER: [&str; 7] = ["phrase", "engine", "found", "uk", "us", "meanings", "error"];
+
+pub struct BatchOptions {
+    // one phrase per line, "-" for stdin
+    pub file: PathBuf,
+    pub engines: Vec<String>,
+    pub mode: Mode,
+    // how many lookups run at once
+    pub jobs: usize,
+    pub format: Format,
+    pub save: bool,
+    pub prefetch: bool,
+}
+
+#[derive(Debug, Default)]
+pub struct Report {
+    pub total: usize,
+    // the phrase and why nobody answered it
+    pub failed: Vec<(String, String)>,
+    // answered, but the audio couldn't be downloaded
+    pub no_audio: Vec<(String, String)>,
+}
+
+// One engine for all the workers, so that the stardict books are loaded
+// once. The sled handle and the http agent are shared already.
+struct Shared(Arc<dyn Dictionary>);
+
+impl Dictionary for Shared {
+    fn name(&self) -> &'static str {
+        self.0.name()
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        self.0.capabilities()
+    }
+
+    fn fetch(&self, phrase: &str) -> Result<Vec<u8>> {
+        self.0.fetch(phrase)
+    }
+
+    fn parse(&self, raw: &[u8]) -> Result<VocabBody> {
+        self.0.parse(raw)
+    }
+
+    fn fetch_audio(&self, phrase: &str, t: AudioType) -> Result<Vec<u8>> {
+        self.0.fetch_audio(phrase, t)
+    }
+}
+
+// blank lines and "#" comments are skipped
+pub fn phrases(r: impl BufRead) -> Result<Vec<String>> {
+    let mut res = vec![];
+    for line in r.lines() {
+        let line = line?;
+        let p = line.trim();
+        if !p.is_empty() && !p.starts_with('#') {
+            res.push(p.to_string());
+        }
+    }
+    Ok(res)
+}
+
+// Looks the phrases up with `jobs` workers, which run `each` after the
+// query. `done` gets the results in the order of the input, each one as soon
+// as the ones before it are done.
+pub fn lookup<E, D>(
+    phrases: &[String],
+    engines: &[Arc<dyn Dictionary>],
+    mode: Mode,
+    jobs: usize,
+    each: E,
+    mut done: D,
+) -> Result<()>
+where
+    E: Fn(&mut Aggregate) -> Result<()> + Sync,
+    D: FnMut(Aggregate, Result<()>) -> Result<()>,
+{
+    let next = AtomicUsize::new(0);
+    let (tx, rx) = mpsc::channel();
+    thread::scope(|s| {
+        for _ in 0..jobs.clamp(1, phrases.len().max(1)) {
+            let (tx, next, each) = (tx.clone(), &next, &each);
+            s.spawn(move || loop {
+                let i = next.fetch_add(1, Ordering::Relaxed);
+                let Some(p) = phrases.get(i) else { break };
+                let engines = engines
+                    .iter()
+                    .map(|e| Box::new(Shared(e.clone())) as Box<dyn Dictionary>)
+                    .collect();
+                let mut res = Aggregate::new(p.clone(), engines, mode);
+                res.query();
+                let extra = each(&mut res);
+                // the receiver is gone if `done` failed
+                if tx.send((i, res, extra)).is_err() {
+                    break;
+                }
+            });
+        }
+        drop(tx);
+
+        let mut pending = BTreeMap::new();
+        let mut want = 0;
+        for (i, res, extra) in rx {
+            pending.insert(i, (res, extra));
+            while let Some((res, extra)) = pending.remove(&want) {
+                done(res, extra)?;
+                want += 1;
+            }
+        }
+        Ok(())
+    })
+}
+
+// Why a lookup failed, None if some engine answered. The errors of the
+// engines, or of caching and prefetching after it.
+fn failure(res: &Aggregate, extra: &Result<()>) -> Option<String> {
+    if let Err(e) = extra {
+        return Some(e.to_string());
+    }
+    if res.answer().is_some() {
+        return None;
+    }
+    let errors: Vec<_> = res
+        .to_document()
+        .results
+        .into_iter()
+        .filter_map(|e| e.error)
+        .collect();
+    Some(match errors.is_empty() {
+        true => DictMsg::NotFound.to_string(),
+        false => errors.join("; "),
+    })
+}
+
+// a header line first if `header`, then a row for each engine of the document
+pub fn to_csv(res: &Aggregate, header: bool) -> Result<String> {
+    let mut w = csv::Writer::from_writer(vec![]);
+    if header {
+        w.write_record(CSV_HEADER)?;
+    }
+    let doc = res.to_document();
+    for e in doc.results {
+        let meanings: Vec<_> = e
+            .explains
+            .iter()
+            .map(|x| match &x.pos {
+                Some(p) => format!("{} {}", p, x.meaning),
+                None => x.meaning.clone(),
+            })
+            .collect();
+        let (uk, us) = match e.phonetic {
+            Some(p) => (p.uk.unwrap_or_default(), p.us.unwrap_or_default()),
+            None => Default::default(),
+        };
+        w.write_record([
+            doc.phrase.as_str(),
+            &e.engine,
+            &e.found.to_string(),
+            &uk,
+            &us,
+            &meanings.join("; "),
+            e.error.as_deref().unwrap_or_default(),
+        ])?;
+    }
+    let buf = w.into_inner().map_err(|e| Error::from(e.into_error()))?;
+    Ok(String::from_utf8_lossy(&buf).into_owned())
+}
+
+// what is printed for each result, `json` is collected into one array at the end
+fn render(res: &Aggregate, format: Format, docs: &mut Vec<Document>) -> Result<Option<String>> {
+    Ok(match format {
+        Format::Text => Some(format!(
+            "\n{s}{phrase}\n{res}",
+            s = ' '.align_right(2),
+            phrase = res.phrase().coloring(Role::Emphasis),
+        )),
+        Format::Jsonl => Some(format!("{}\n", serde_json::to_string(&res.to_document())?)),
+        Format::Csv => Some(to_csv(res, false)?),
+        Format::Json => {
+            docs.push(res.to_document());
+            None
+        }
+    })
+}
+
+// Looks up the phrases of the file. The results go to stdout, the progress
+// to stderr, and a failed phrase is reported at the end without stopping the
+// others.
+pub fn run(opts: BatchOptions) -> Result<Report> {
+    let phrases = match opts.file.to_str() {
+        Some("-") => phrases(io::stdin().lock())?,
+        _ => phrases(BufReader::new(fs::File::open(&opts.file)?))?,
+    };
+    let engines = opts
+        .engines
+        .iter()
+        .map(|n| Engines::get(n).map(Arc::from))
+        .collect::<Result<Vec<Arc<dyn Dictionary>>>>()?;
+    let book = match opts.save {
+        true => Some(Book::open()?),
+        false => None,
+    };
+
+    // the answer is what counts, a word without audio is reported apart
+    let audio = engines.iter().any(|e| e.capabilities().audio);
+    if opts.prefetch && !audio {
+        eprintln!("{}none of the engines has audio", ' '.align_right(4));
+    }
+
+    let bar = bar(phrases.len() as u64);
+    // the bar is on the terminal too, the results go above it
+    let above = io::stdout().is_terminal() && !bar.is_hidden();
+    let print = |s: &str| match above {
+        true => bar.println(s.trim_end_matches('\n')),
+        false => print!("{}", s),
+    };
+    if opts.format == Format::Csv {
+        print(&format!("{}\n", CSV_HEADER.join(",")));
+    }
+
+    let mut report = Report {
+        total: phrases.len(),
+        ..Default::default()
+    };
+    let mut docs = vec![];
+    let no_audio = Mutex::new(vec![]);
+    let each = |res: &mut Aggregate| {
+        res.store()?;
+        let mut saved = false;
+        if let (Some(book), Some(answer)) = (&book, res.answer()) {
+            book.add(Word::new(res.phrase(), Some(answer)))?;
+            saved = true;
+        }
+        let fetched = match (opts.prefetch && audio, saved) {
+            (true, _) if res.answer().is_some() => res.prefetch_audio(),
+            (false, true) => res.prefetch_for_book(),
+            _ => Ok(()),
+        };
+        if let Err(e) = fetched {
+            let mut v = no_audio.lock().unwrap();
+            v.push((res.phrase().to_string(), e.to_string()));
+        }
+        Ok(())
+    };
+    lookup(
+        &phrases,
+        &engines,
+        opts.mode,
+        opts.jobs,
+        each,
+        |res, extra| {
+            bar.inc(1);
+            bar.set_message(res.phrase().to_string());
+            if let Some(why) = failure(&res, &extra) {
+                report.failed.push((res.phrase().to_string(), why));
+            }
+            if let Some(s) = render(&res, opts.format, &mut docs)? {
+                print(&s);
+            }
+            Ok(())
+        },
+    )?;
+    bar.finish_and_clear();
+    report.no_audio = no_audio.into_inner().unwrap();
+    // the workers finish in any order
+    let order: BTreeMap<_, _> = phrases.iter().enumerate().map(|(i, p)| (p, i)).collect();
+    report.no_audio.sort_by_key(|(p, _)| order.get(p).copied());
+
+    if opts.format == Format::Json {
+        println!("{}", serde_json::to_string_pretty(&docs)?);
+    }
+    cache::auto_prune(&util::open_db()?, Policy::from_config())?;
+    Ok(report)
+}
+
+pub fn bar(len: u64) -> ProgressBar {
+    let bar = ProgressBar::new(len);
+    bar.set_style(
+        ProgressStyle::default_bar()
+            .template("{prefix}{bar:30.green} {pos}/{len} {msg:.green}")
+            .progress_chars("━╸ "),
+    );
+    bar.set_prefix(format!("{:>4}", " "));
+    bar
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f)?;
+        writeln!(
+            f,
+            "{s}{done}",
+            s = ' '.align_right(4),
+            done = format!(
+                "{} looked up, {} failed",
+                self.total - self.failed.len(),
+                self.failed.len()
+            )
+            .coloring(Role::Title),
+        )?;
+        let list = |f: &mut fmt::Formatter<'_>, words: &[(String, String)]| -> fmt::Result {
+            for (phrase, why) in words {
+                writeln!(
+                    f,
+                    "{s}{phrase}{why}",
+                    s = ' '.align_right(6),
+                    phrase = phrase.align_left(16).coloring(Role::Emphasis),
+                    why = why.coloring(Role::Wip),
+                )?;
+            }
+            Ok(())
+        };
+        list(f, &self.failed)?;
+        if !self.no_audio.is_empty() {
+            writeln!(
+                f,
+                "{s}{msg}",
+                s = ' '.align_right(4),
+                msg = format!("{} without audio", self.no_audio.len()).coloring(Role::Title),
+            )?;
+            list(f, &self.no_audio)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::handler::youdao::Youdao;
+
+    // offline, knows "hello" only and fails on "down"
+    struct Fake;
+
+    impl Dictionary for Fake {
+        fn name(&self) -> &'static str {
+            "fake"
+        }
+
+        fn capabilities(&self) -> Capabilities {
+            Capabilities {
+                online: false,
+                audio: false,
+            }
+        }
+
+        fn fetch(&self, phrase: &str) -> Result<Vec<u8>> {
+            match phrase {
+                "down" => Err(Error::Engine("fake", "down")),
+                _ => Ok(std::fs::read("tests/fixtures/youdao/hello.json")?),
+            }
+        }
+
+        fn parse(&self, raw: &[u8]) -> Result<VocabBody> {
+            Youdao.parse(raw)
+        }
+    }
+
+    #[test]
+    fn test_phrases() {
+        let input = "hello\n\n  # a comment\n  good morning \nworld";
+        assert_eq!(
+            phrases(input.as_bytes()).unwrap(),
+            ["hello", "good morning", "world"]
+        );
+    }
+
+    #[test]
+    fn test_lookup() {
+        let input: Vec<_> = (0..20)
+            .map(|i| match i % 5 {
+                3 => "down".to_string(),
+                _ => format!("hello{}", i),
+            })
+            .collect();
+        let engines: Vec<Arc<dyn Dictionary>> = vec![Arc::new(Fake)];
+        let each = |res: &mut Aggregate| match res.phrase() {
+            "hello4" => Err(Error::Engine("fake", "no audio")),
+            _ => Ok(()),
+        };
+
+        let mut seen = vec![];
+        let mut failed = vec![];
+        lookup(&input, &engines, Mode::Fallback, 4, each, |res, extra| {
+            if let Some(why) = failure(&res, &extra) {
+                failed.push((res.phrase().to_string(), why));
+            }
+            seen.push(res.phrase().to_string());
+            Ok(())
+        })
+        .unwrap();
+
+        // in the order of the input, a failed one doesn't stop the rest
+        assert_eq!(seen, input);
+        assert_eq!(failed.len(), 5);
+        assert_eq!(
+            failed[0],
+            ("down".into(), "engine error: (fake, down)".into())
+        );
+        assert_eq!(failed[1].0, "hello4");
+
+        // an error of `done` stops the run
+        let mut n = 0;
+        let res = lookup(&input, &engines, Mode::All, 4, each, |_, _| {
+            n += 1;
+            Err(Error::Engine("fake", "full"))
+        });
+        assert!(res.is_err());
+        assert_eq!(n, 1);
+    }
+
+    #[test]
+    fn test_csv() {
+        let mut res = Aggregate::new(
+            "hello".to_string(),
+            vec![Box::new(Fake), Box::new(Fake)],
+            Mode::Fallback,
+        );
+        res.query();
+        let out = to_csv(&res, true).unwrap();
+        let lines: Vec<_> = out.lines().collect();
+        assert_eq!(lines[0], "phrase,engine,found,uk,us,meanings,error");
+        assert_eq!(lines.len(), 2);
+        assert!(lines[1].starts_with("hello,fake,true,"));
+        assert!(lines[1].contains(",int. 喂；哈罗，你好，您好; "));
+
+        let mut res = Aggregate::new("down".to_string(), vec![Box::new(Fake)], Mode::All);
+        res.query();
+        assert_eq!(
+            to_csv(&res, false).unwrap(),
+            "down,fake,false,,,,\"engine error: (fake, down)\"\n"
+        );
+    }
+}
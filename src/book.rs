@@ -0,0 +1,233 @@
+use std::fmt;
+
+use chrono::{Local, TimeZone};
+use serde::{Deserialize, Serialize};
+
+use crate::handler::VocabBody;
+use crate::result::Result;
+use crate::review::Schedule;
+use crate::util::{self, ColorfulRole as Role, Style};
+
+// The word book (生词本), phrase -> json Word. Unlike the cache it's user
+// data and never expires.
+const BOOK_TREE: &str = "book";
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Word {
+    pub phrase: String,
+    // seconds since the epoch
+    pub added: i64,
+    pub tags: Vec<String>,
+    pub note: Option<String>,
+    // the engine the snapshot comes from
+    pub engine: Option<String>,
+    // what the word looked like when it was added, so that it reads offline
+    pub vocabulary: Option<VocabBody>,
+    // when to review it, see `rdict review`
+    #[serde(default)]
+    pub schedule: Schedule,
+}
+
+pub struct Book {
+    tree: sled::Tree,
+}
+
+impl Word {
+    pub fn new(phrase: &str, answer: Option<(&str, &VocabBody)>) -> Self {
+        Word {
+            phrase: phrase.trim().to_string(),
+            added: util::now(),
+            tags: vec![],
+            note: None,
+            engine: answer.map(|(e, _)| e.to_string()),
+            vocabulary: answer.map(|(_, v)| v.clone()),
+            schedule: Schedule::default(),
+        }
+    }
+
+    pub fn tagged(mut self, tags: Vec<String>) -> Self {
+        self.tags = tags;
+        self
+    }
+
+    pub fn noted(mut self, note: Option<String>) -> Self {
+        self.note = note;
+        self
+    }
+
+    // a word added again keeps its date and schedule, gets the new snapshot and note if any
+    fn merge(&mut self, new: Word) {
+        for t in new.tags {
+            if !self.tags.contains(&t) {
+                self.tags.push(t);
+            }
+        }
+        if new.note.is_some() {
+            self.note = new.note;
+        }
+        if new.vocabulary.is_some() {
+            self.engine = new.engine;
+            self.vocabulary = new.vocabulary;
+        }
+    }
+}
+
+impl Book {
+    pub fn open() -> Result<Self> {
+        Ok(Book::with_tree(util::open_db()?.open_tree(BOOK_TREE)?))
+    }
+
+    fn with_tree(tree: sled::Tree) -> Self {
+        Book { tree }
+    }
+
+    // true if the word is new to the book
+    pub fn add(&self, word: Word) -> Result<bool> {
+        let (word, new) = match self.get(&word.phrase)? {
+            Some(mut old) => {
+                old.merge(word);
+                (old, false)
+            }
+            None => (word, true),
+        };
+        self.put(&word)?;
+        Ok(new)
+    }
+
+    // stores the word as it is, replacing the old one
+    pub fn put(&self, word: &Word) -> Result<()> {
+        self.tree
+            .insert(word.phrase.as_bytes(), serde_json::to_vec(word)?)?;
+        self.tree.flush()?;
+        Ok(())
+    }
+
+    pub fn remove(&self, phrase: &str) -> Result<bool> {
+        let removed = self.tree.remove(phrase.trim())?.is_some();
+        self.tree.flush()?;
+        Ok(removed)
+    }
+
+    pub fn get(&self, phrase: &str) -> Result<Option<Word>> {
+        match self.tree.get(phrase.trim())? {
+            Some(v) => Ok(Some(serde_json::from_slice(&v)?)),
+            None => Ok(None),
+        }
+    }
+
+    // newest first, only the ones with `tag` if it's given
+    pub fn list(&self, tag: Option<&str>) -> Result<Vec<Word>> {
+        let mut res = vec![];
+        for v in self.tree.iter().values() {
+            let w: Word = serde_json::from_slice(&v?)?;
+            if tag.is_none_or(|t| w.tags.iter().any(|x| x == t)) {
+                res.push(w);
+            }
+        }
+        res.sort_by(|a, b| b.added.cmp(&a.added).then(a.phrase.cmp(&b.phrase)));
+        Ok(res)
+    }
+}
+
+fn date(secs: i64) -> String {
+    match Local.timestamp_opt(secs, 0).single() {
+        Some(t) => t.format("%Y-%m-%d %H:%M").to_string(),
+        None => String::new(),
+    }
+}
+
+fn tags(w: &Word) -> String {
+    w.tags
+        .iter()
+        .map(|t| format!("#{}", t))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+// the list of `book ls`
+pub struct Words(pub Vec<Word>);
+
+impl fmt::Display for Words {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f)?;
+        let width = self.0.iter().map(|w| w.phrase.len()).max().unwrap_or(0) + 2;
+        for (k, w) in self.0.iter().enumerate() {
+            writeln!(
+                f,
+                "{s}{index}{dot}{value}{time}{tags}",
+                s = " ".repeat(4),
+                index = (k + 1).align_right(2).coloring(Role::Index),
+                dot = ".".align_left(2).coloring(Role::Dot),
+                value = w.phrase.align_left(width).coloring(Role::Content),
+                time = date(w.added).align_left(18).coloring(Role::Other),
+                tags = tags(w).coloring(Role::Dot),
+            )?;
+        }
+        Ok(())
+    }
+}
+
+// `book show`, the snapshot with what the user added
+impl fmt::Display for Word {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f)?;
+        writeln!(
+            f,
+            "{s}{phrase}  {time}  {tags}",
+            s = ' '.align_right(4),
+            phrase = self.phrase.coloring(Role::Emphasis),
+            time = date(self.added).coloring(Role::Other),
+            tags = tags(self).coloring(Role::Dot),
+        )?;
+        if let Some(n) = &self.note {
+            writeln!(f, "{}{}", ' '.align_right(4), n.coloring(Role::Content))?;
+        }
+        writeln!(f)?;
+        match &self.vocabulary {
+            Some(v) => write!(f, "{}", v),
+            None => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::handler::{youdao::Youdao, Dictionary};
+
+    #[test]
+    fn test_book() {
+        let db = sled::Config::new().temporary(true).open().unwrap();
+        let book = Book::with_tree(db.open_tree(BOOK_TREE).unwrap());
+        let raw = std::fs::read("tests/fixtures/youdao/hello.json").unwrap();
+        let vb = Youdao.parse(&raw).unwrap();
+
+        let w = Word::new(" hello ", Some(("youdao", &vb))).tagged(vec!["greeting".into()]);
+        assert!(book.add(w).unwrap());
+        let mut w = Word::new("world", None).noted(Some("from a song".into()));
+        w.added -= 10;
+        assert!(book.add(w).unwrap());
+
+        // adding again merges, the date stays
+        let old = book.get("hello").unwrap().unwrap();
+        let again = Word::new("hello", None).tagged(vec!["greeting".into(), "cet4".into()]);
+        assert!(!book.add(again).unwrap());
+        let w = book.get("hello").unwrap().unwrap();
+        assert_eq!(w.tags, ["greeting", "cet4"]);
+        assert_eq!(w.added, old.added);
+        assert_eq!(w.engine.as_deref(), Some("youdao"));
+        assert!(w.to_string().contains("heˈloʊ"));
+
+        let all: Vec<_> = book.list(None).unwrap();
+        assert_eq!(
+            all.iter().map(|w| w.phrase.as_str()).collect::<Vec<_>>(),
+            ["hello", "world"]
+        );
+        assert_eq!(book.list(Some("cet4")).unwrap().len(), 1);
+        assert_eq!(all[1].note.as_deref(), Some("from a song"));
+
+        assert!(book.remove("world").unwrap());
+        assert!(!book.remove("world").unwrap());
+        assert!(book.get("world").unwrap().is_none());
+    }
+}
@@ -0,0 +1,530 @@
+use std::collections::BTreeSet;
+
+use crate::config;
+use crate::handler::{youdao::Youdao, Dictionary, Engines};
+use crate::result::Result;
+use crate::util::{self, ColorfulRole as Role, Style};
+
+// Raw responses of the online engines are kept in a tree named after the
+// engine, downloaded audio of all the engines in this one.
+pub const AUDIO_TREE: &str = "audio";
+
+// When the entries were last read, keyed by "tree/key". They're kept apart
+// so that a read writes a few bytes instead of the whole entry again.
+const ACCESS_TREE: &str = "cache_access";
+// how many entries have been inserted since the last prune, in ACCESS_TREE
+// as no tree is named like this
+const INSERTS_KEY: &str = "#inserts";
+// the cache is pruned after this many inserts, not on every lookup
+pub const PRUNE_EVERY: u64 = 64;
+// dictd isn't cached any more, see migrate
+const DICTD_TREE: &str = "dictd";
+
+// Every cached value starts with this header:
+// magic | fetched (u64 be) | accessed (u64 be) | engine length (u8) | engine | raw
+const MAGIC: &[u8] = b"RDC1";
+const HEADER: usize = MAGIC.len() + 8 + 8 + 1;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Entry {
+    pub engine: String,
+    // seconds since the epoch
+    pub fetched: u64,
+    pub accessed: u64,
+    pub raw: Vec<u8>,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Policy {
+    // seconds, 0 never expires
+    pub ttl: u64,
+    pub max_bytes: u64,
+}
+
+// A cache tree, entries older than the ttl are treated as missing.
+pub struct Cache {
+    name: String,
+    tree: sled::Tree,
+    access: sled::Tree,
+    policy: Policy,
+}
+
+#[derive(Debug, Default)]
+pub struct Stats {
+    pub tree: String,
+    pub entries: usize,
+    pub bytes: u64,
+    pub expired: usize,
+}
+
+#[derive(Debug)]
+pub struct Broken {
+    pub tree: String,
+    pub key: String,
+    pub reason: String,
+}
+
+impl Entry {
+    pub fn new(engine: &str, raw: &[u8]) -> Self {
+        let now = util::now() as u64;
+        Entry {
+            engine: engine.to_string(),
+            fetched: now,
+            accessed: now,
+            raw: raw.to_vec(),
+        }
+    }
+
+    fn encode(&self) -> Vec<u8> {
+        let engine = &self.engine.as_bytes()[..self.engine.len().min(u8::MAX as usize)];
+        let mut v = Vec::with_capacity(HEADER + engine.len() + self.raw.len());
+        v.extend_from_slice(MAGIC);
+        v.extend_from_slice(&self.fetched.to_be_bytes());
+        v.extend_from_slice(&self.accessed.to_be_bytes());
+        v.push(engine.len() as u8);
+        v.extend_from_slice(engine);
+        v.extend_from_slice(&self.raw);
+        v
+    }
+
+    // None for values written before entries had a header
+    fn decode(v: &[u8]) -> Option<Self> {
+        if v.len() < HEADER || !v.starts_with(MAGIC) {
+            return None;
+        }
+        let u64_at = |i: usize| u64::from_be_bytes(v[i..i + 8].try_into().unwrap());
+        let n = v[HEADER - 1] as usize;
+        let engine = v.get(HEADER..HEADER + n)?;
+        Some(Entry {
+            engine: String::from_utf8(engine.to_vec()).ok()?,
+            fetched: u64_at(MAGIC.len()),
+            accessed: u64_at(MAGIC.len() + 8),
+            raw: v[HEADER + n..].to_vec(),
+        })
+    }
+}
+
+impl Policy {
+    pub fn from_config() -> Self {
+        let c = config::get();
+        Policy {
+            ttl: c.cache_ttl.value * 24 * 60 * 60,
+            max_bytes: c.cache_size.value * 1024 * 1024,
+        }
+    }
+
+    fn expired(&self, e: &Entry, now: u64) -> bool {
+        self.ttl != 0 && now.saturating_sub(e.fetched) > self.ttl
+    }
+}
+
+impl Cache {
+    pub fn open(name: &str) -> Result<Self> {
+        Cache::with_db(&util::open_db()?, name, Policy::from_config())
+    }
+
+    pub fn with_db(db: &sled::Db, name: &str, policy: Policy) -> Result<Self> {
+        Ok(Cache {
+            name: name.to_string(),
+            tree: db.open_tree(name)?,
+            access: db.open_tree(ACCESS_TREE)?,
+            policy,
+        })
+    }
+
+    pub fn get(&self, key: &str) -> Result<Option<Vec<u8>>> {
+        let e = match self.tree.get(key)?.and_then(|v| Entry::decode(&v)) {
+            Some(e) => e,
+            None => return Ok(None),
+        };
+        let now = util::now() as u64;
+        if self.policy.expired(&e, now) {
+            return Ok(None);
+        }
+
+        // remember when it was used, the least recently used go first
+        self.access
+            .insert(access_key(&self.name, key.as_bytes()), &now.to_be_bytes())?;
+        Ok(Some(e.raw))
+    }
+
+    pub fn insert(&self, key: &str, engine: &str, raw: &[u8]) -> Result<()> {
+        self.tree.insert(key, Entry::new(engine, raw).encode())?;
+        self.access.remove(access_key(&self.name, key.as_bytes()))?;
+        self.access.update_and_fetch(INSERTS_KEY, |v| {
+            let n = v
+                .and_then(|v| v.try_into().ok())
+                .map_or(0, u64::from_be_bytes);
+            Some((n + 1).to_be_bytes().to_vec())
+        })?;
+        self.tree.flush()?;
+        Ok(())
+    }
+}
+
+// trees that hold cached data, the rest of the database is user data
+pub fn tree_names() -> Vec<&'static str> {
+    let mut names: Vec<_> = Engines::names()
+        .into_iter()
+        .filter(|n| Engines::get(n).is_ok_and(|e| e.capabilities().online))
+        .collect();
+    names.push(AUDIO_TREE);
+    names
+}
+
+// phrases with a cached answer, sorted and without duplicates
+pub fn phrases(db: &sled::Db) -> Result<Vec<String>> {
+    let mut res = BTreeSet::new();
+    for name in tree_names().into_iter().filter(|n| *n != AUDIO_TREE) {
+        for k in db.open_tree(name)?.iter().keys() {
+            res.insert(String::from_utf8_lossy(&k?).into_owned());
+        }
+    }
+    Ok(res.into_iter().collect())
+}
+
+pub fn stats(db: &sled::Db, policy: Policy) -> Result<Vec<Stats>> {
+    let now = util::now() as u64;
+    let mut res = vec![];
+    for name in tree_names() {
+        let mut s = Stats {
+            tree: name.to_string(),
+            ..Default::default()
+        };
+        for kv in db.open_tree(name)?.iter() {
+            let (k, v) = kv?;
+            s.entries += 1;
+            s.bytes += (k.len() + v.len()) as u64;
+            if Entry::decode(&v).is_none_or(|e| policy.expired(&e, now)) {
+                s.expired += 1;
+            }
+        }
+        res.push(s);
+    }
+    Ok(res)
+}
+
+pub fn clear(db: &sled::Db) -> Result<usize> {
+    let mut n = 0;
+    for name in tree_names() {
+        let tree = db.open_tree(name)?;
+        n += tree.len();
+        tree.clear()?;
+        tree.flush()?;
+    }
+    let access = db.open_tree(ACCESS_TREE)?;
+    access.clear()?;
+    access.flush()?;
+    Ok(n)
+}
+
+// Older versions kept youdao's answers and the audio, keyed by "{phrase}_{n}",
+// in the default tree, which nothing uses now. The answers are moved to the
+// youdao tree and the rest is dropped, the audio is downloaded again when
+// it's played. Returns how many entries were moved.
+pub fn migrate(db: &sled::Db) -> Result<usize> {
+    // dictd's answers were cached by phrase only, whatever the server was
+    if db.drop_tree(DICTD_TREE)? {
+        let access = db.open_tree(ACCESS_TREE)?;
+        for k in access.scan_prefix(format!("{}/", DICTD_TREE)).keys() {
+            access.remove(k?)?;
+        }
+    }
+    if db.is_empty() {
+        return Ok(0);
+    }
+    let youdao = db.open_tree(Youdao.name())?;
+    let mut moved = 0;
+    for kv in db.iter() {
+        let (k, v) = kv?;
+        if Youdao.parse(&v).is_ok() && !youdao.contains_key(&k)? {
+            youdao.insert(&k, Entry::new(Youdao.name(), &v).encode())?;
+            moved += 1;
+        }
+        db.remove(k)?;
+    }
+    db.flush()?;
+    Ok(moved)
+}
+
+// Prunes once every PRUNE_EVERY inserts, a lookup calls this instead of
+// walking the whole cache each time.
+pub fn auto_prune(db: &sled::Db, policy: Policy) -> Result<usize> {
+    let access = db.open_tree(ACCESS_TREE)?;
+    let inserts = access
+        .get(INSERTS_KEY)?
+        .and_then(|v| v.as_ref().try_into().ok())
+        .map_or(0, u64::from_be_bytes);
+    if inserts < PRUNE_EVERY {
+        return Ok(0);
+    }
+    prune(db, policy)
+}
+
+// Drops expired entries, then the least recently used ones until the cache
+// fits in the size limit. Returns how many entries were removed.
+pub fn prune(db: &sled::Db, policy: Policy) -> Result<usize> {
+    let now = util::now() as u64;
+    let mut removed = 0;
+    // (accessed, bytes, tree, key) of what's left
+    let mut alive = vec![];
+    let mut total = 0;
+    let access = db.open_tree(ACCESS_TREE)?;
+    access.remove(INSERTS_KEY)?;
+
+    for name in tree_names() {
+        let tree = db.open_tree(name)?;
+        for kv in tree.iter() {
+            let (k, v) = kv?;
+            let ak = access_key(name, &k);
+            match Entry::decode(&v).filter(|e| !policy.expired(e, now)) {
+                Some(e) => {
+                    let bytes = (k.len() + v.len()) as u64;
+                    let read = access
+                        .get(&ak)?
+                        .and_then(|v| v.as_ref().try_into().ok())
+                        .map_or(0, u64::from_be_bytes);
+                    total += bytes;
+                    alive.push((e.accessed.max(read), bytes, tree.clone(), k, ak));
+                }
+                None => {
+                    tree.remove(k)?;
+                    access.remove(ak)?;
+                    removed += 1;
+                }
+            }
+        }
+    }
+
+    alive.sort_by_key(|(accessed, ..)| *accessed);
+    for (_, bytes, tree, key, ak) in alive {
+        if total <= policy.max_bytes {
+            break;
+        }
+        tree.remove(key)?;
+        access.remove(ak)?;
+        total -= bytes;
+        removed += 1;
+    }
+
+    db.flush()?;
+    Ok(removed)
+}
+
+// entries which can't be read back by their engine anymore
+pub fn verify(db: &sled::Db) -> Result<Vec<Broken>> {
+    let mut res = vec![];
+    for name in tree_names() {
+        let engine = Engines::get(name).ok();
+        for kv in db.open_tree(name)?.iter() {
+            let (k, v) = kv?;
+            let reason = match (Entry::decode(&v), &engine) {
+                (None, _) => Some("unknown format".to_string()),
+                (Some(e), Some(engine)) => engine.parse(&e.raw).err().map(|e| e.to_string()),
+                (Some(e), None) if e.raw.is_empty() => Some("empty".to_string()),
+                _ => None,
+            };
+            if let Some(reason) = reason {
+                res.push(Broken {
+                    tree: name.to_string(),
+                    key: String::from_utf8_lossy(&k).to_string(),
+                    reason,
+                });
+            }
+        }
+    }
+    Ok(res)
+}
+
+pub fn print_stats(stats: &[Stats]) {
+    println!();
+    for s in stats {
+        println!(
+            "{s}{name}{entries}{bytes}{expired}",
+            s = ' '.align_right(4),
+            name = s.tree.align_left(10).coloring(Role::Index),
+            entries = format!("{} entries", s.entries)
+                .align_left(16)
+                .coloring(Role::Content),
+            bytes = human_bytes(s.bytes).align_left(12).coloring(Role::Content),
+            expired = format!("{} expired", s.expired).coloring(Role::Dot),
+        );
+    }
+}
+
+pub fn print_broken(broken: &[Broken]) {
+    println!();
+    if broken.is_empty() {
+        println!(
+            "{s}{msg}",
+            s = ' '.align_right(4),
+            msg = "all the entries are fine".coloring(Role::Content)
+        );
+    }
+    for b in broken {
+        println!(
+            "{s}{tree}{key}{reason}",
+            s = ' '.align_right(4),
+            tree = b.tree.align_left(10).coloring(Role::Index),
+            key = b.key.align_left(20).coloring(Role::Content),
+            reason = b.reason.coloring(Role::Wip),
+        );
+    }
+}
+
+fn access_key(tree: &str, key: &[u8]) -> Vec<u8> {
+    let mut k = Vec::with_capacity(tree.len() + 1 + key.len());
+    k.extend_from_slice(tree.as_bytes());
+    k.push(b'/');
+    k.extend_from_slice(key);
+    k
+}
+
+fn human_bytes(n: u64) -> String {
+    match n {
+        n if n >= 1024 * 1024 => format!("{:.1} MiB", n as f64 / 1024.0 / 1024.0),
+        n if n >= 1024 => format!("{:.1} KiB", n as f64 / 1024.0),
+        n => format!("{} B", n),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const DAY: u64 = 24 * 60 * 60;
+
+    fn put(db: &sled::Db, tree: &str, key: &str, fetched: u64, raw: &[u8]) {
+        let e = Entry {
+            engine: tree.to_string(),
+            fetched,
+            accessed: fetched,
+            raw: raw.to_vec(),
+        };
+        db.open_tree(tree).unwrap().insert(key, e.encode()).unwrap();
+    }
+
+    #[test]
+    fn test_entry() {
+        let e = Entry::new("youdao", b"{}");
+        assert_eq!(Entry::decode(&e.encode()), Some(e));
+        // written by an older version
+        assert_eq!(Entry::decode(b"{\"meta\": {}}"), None);
+    }
+
+    #[test]
+    fn test_ttl() {
+        let db = sled::Config::new().temporary(true).open().unwrap();
+        let policy = Policy {
+            ttl: DAY,
+            max_bytes: u64::MAX,
+        };
+        let cache = Cache::with_db(&db, "youdao", policy).unwrap();
+        cache.insert("fresh", "youdao", b"1").unwrap();
+        put(&db, "youdao", "stale", util::now() as u64 - 2 * DAY, b"2");
+
+        assert_eq!(cache.get("fresh").unwrap(), Some(b"1".to_vec()));
+        assert_eq!(cache.get("stale").unwrap(), None);
+        assert_eq!(cache.get("nothing").unwrap(), None);
+
+        let s = stats(&db, policy).unwrap();
+        assert_eq!(s[0].tree, "youdao");
+        assert_eq!((s[0].entries, s[0].expired), (2, 1));
+
+        assert_eq!(prune(&db, policy).unwrap(), 1);
+        assert_eq!(db.open_tree("youdao").unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_lru() {
+        let db = sled::Config::new().temporary(true).open().unwrap();
+        let now = util::now() as u64;
+        for (i, key) in ["a", "b", "c"].iter().enumerate() {
+            put(&db, AUDIO_TREE, key, now - 10 + i as u64, &[0; 100]);
+        }
+        let size = db.open_tree(AUDIO_TREE).unwrap().iter().next().unwrap();
+        let size = size.map(|(k, v)| k.len() + v.len()).unwrap() as u64;
+        let policy = Policy {
+            ttl: 0,
+            max_bytes: size * 2,
+        };
+
+        // "a" is the oldest one, but it has just been used
+        let cache = Cache::with_db(&db, AUDIO_TREE, policy).unwrap();
+        let before = db.open_tree(AUDIO_TREE).unwrap().get("a").unwrap();
+        assert!(cache.get("a").unwrap().is_some());
+        // a read doesn't rewrite the entry
+        assert_eq!(db.open_tree(AUDIO_TREE).unwrap().get("a").unwrap(), before);
+
+        assert_eq!(prune(&db, policy).unwrap(), 1);
+        let tree = db.open_tree(AUDIO_TREE).unwrap();
+        assert!(tree.contains_key("a").unwrap());
+        assert!(!tree.contains_key("b").unwrap());
+        assert!(tree.contains_key("c").unwrap());
+    }
+
+    #[test]
+    fn test_auto_prune() {
+        let db = sled::Config::new().temporary(true).open().unwrap();
+        let policy = Policy {
+            ttl: DAY,
+            max_bytes: u64::MAX,
+        };
+        put(&db, "youdao", "stale", util::now() as u64 - 2 * DAY, b"1");
+        let cache = Cache::with_db(&db, "youdao", policy).unwrap();
+        for i in 1..PRUNE_EVERY {
+            cache.insert(&i.to_string(), "youdao", b"2").unwrap();
+        }
+        assert_eq!(auto_prune(&db, policy).unwrap(), 0);
+        assert!(cache.tree.contains_key("stale").unwrap());
+
+        cache.insert("last", "youdao", b"2").unwrap();
+        assert_eq!(auto_prune(&db, policy).unwrap(), 1);
+        assert!(!cache.tree.contains_key("stale").unwrap());
+        // counting starts over
+        assert_eq!(auto_prune(&db, policy).unwrap(), 0);
+    }
+
+    #[test]
+    fn test_migrate() {
+        let db = sled::Config::new().temporary(true).open().unwrap();
+        let hello = std::fs::read("tests/fixtures/youdao/hello.json").unwrap();
+        db.insert("hello", &hello[..]).unwrap();
+        db.insert("hello_1", &b"ID3 mp3"[..]).unwrap();
+        put(&db, "youdao", "world", util::now() as u64, b"{}");
+        db.insert("world", &hello[..]).unwrap();
+
+        assert_eq!(migrate(&db).unwrap(), 1);
+        assert!(db.is_empty());
+        let cache = Cache::with_db(&db, "youdao", Policy::from_config()).unwrap();
+        assert_eq!(cache.get("hello").unwrap(), Some(hello));
+        // what's cached already wins
+        assert_eq!(cache.get("world").unwrap(), Some(b"{}".to_vec()));
+        assert_eq!(migrate(&db).unwrap(), 0);
+
+        put(&db, DICTD_TREE, "hello", util::now() as u64, b"{}");
+        migrate(&db).unwrap();
+        assert!(!db.tree_names().iter().any(|n| n == DICTD_TREE.as_bytes()));
+    }
+
+    #[test]
+    fn test_verify() {
+        let db = sled::Config::new().temporary(true).open().unwrap();
+        let good = std::fs::read("tests/fixtures/youdao/hello.json").unwrap();
+        put(&db, "youdao", "hello", util::now() as u64, &good);
+        put(&db, "youdao", "broken", util::now() as u64, b"{\"ec\": 1}");
+        db.open_tree("youdao")
+            .unwrap()
+            .insert("legacy", &good[..])
+            .unwrap();
+
+        let broken = verify(&db).unwrap();
+        let keys: Vec<_> = broken.iter().map(|b| b.key.as_str()).collect();
+        assert_eq!(keys, ["broken", "legacy"]);
+        assert_eq!(broken[1].reason, "unknown format");
+        assert_eq!(phrases(&db).unwrap(), ["broken", "hello", "legacy"]);
+
+        assert_eq!(clear(&db).unwrap(), 3);
+        assert!(db.open_tree("youdao").unwrap().is_empty());
+    }
+}
@@ -0,0 +1,467 @@
+use std::{env, fmt, fs, io, path::PathBuf, sync::OnceLock};
+
+use serde::Deserialize;
+
+use crate::handler::{Engines, DEFAULT_SECTIONS, SECTIONS};
+use crate::result::{Error, Result};
+use crate::util::{ColorfulRole as Role, Style};
+
+// Settings come from, in order of precedence: command line flags, environment
+// variables, the config file, and the defaults compiled in.
+const CONFIG_ENV: &str = "RDICT_CONFIG";
+const CONFIG_FILE: &str = "config.toml";
+
+// what `-f` tries when it's given without engines and nothing is configured
+pub const DEFAULT_FALLBACK: &[&str] = &["youdao", "ecdict", "stardict"];
+
+static CONFIG: OnceLock<Config> = OnceLock::new();
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Source {
+    Default,
+    File,
+    Env(&'static str),
+    Cli,
+}
+
+#[derive(Debug, Clone)]
+pub struct Setting<T> {
+    pub value: T,
+    pub source: Source,
+}
+
+#[derive(Debug, Clone)]
+pub struct Config {
+    // where the file was looked for, it may not exist
+    pub path: PathBuf,
+    pub engines: Setting<Vec<String>>,
+    // when set, lookups go through this chain unless -d or --all is given
+    pub fallback: Setting<Option<Vec<String>>>,
+    pub voice: Setting<String>,
+    pub autoplay: Setting<bool>,
+    // download the audio of a word added to the book
+    pub book_audio: Setting<bool>,
+    pub format: Setting<String>,
+    pub color: Setting<String>,
+    pub theme: Setting<String>,
+    // what the text output shows, see handler::SECTIONS
+    pub sections: Setting<Vec<String>>,
+    pub history: Setting<usize>,
+    // days, 0 never expires
+    pub cache_ttl: Setting<u64>,
+    // MiB
+    pub cache_size: Setting<u64>,
+    pub db_path: Setting<PathBuf>,
+    // seconds
+    pub timeout: Setting<u64>,
+    pub proxy: Setting<Option<String>>,
+    pub stardict_dir: Setting<PathBuf>,
+    pub dictd_host: Setting<String>,
+    pub dictd_db: Setting<String>,
+}
+
+// config.toml, every key is optional
+#[derive(Deserialize, Debug, Default)]
+#[serde(default, deny_unknown_fields)]
+struct ConfigFile {
+    engines: Option<Vec<String>>,
+    fallback: Option<Vec<String>>,
+    voice: Option<String>,
+    autoplay: Option<bool>,
+    book_audio: Option<bool>,
+    format: Option<String>,
+    color: Option<String>,
+    theme: Option<String>,
+    sections: Option<Vec<String>>,
+    history: Option<usize>,
+    cache_ttl: Option<u64>,
+    cache_size: Option<u64>,
+    db_path: Option<PathBuf>,
+    timeout: Option<u64>,
+    proxy: Option<String>,
+    stardict_dir: Option<PathBuf>,
+    dictd_host: Option<String>,
+    dictd_db: Option<String>,
+}
+
+impl<T> Setting<T> {
+    pub fn set(&mut self, value: T, source: Source) {
+        self.value = value;
+        self.source = source;
+    }
+}
+
+impl Config {
+    pub fn load() -> Result<Self> {
+        let path = match env::var_os(CONFIG_ENV) {
+            Some(p) => PathBuf::from(p),
+            None => dir().join(CONFIG_FILE),
+        };
+        let file = match fs::read_to_string(&path) {
+            Ok(s) => Some(s),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => None,
+            Err(e) => return Err(e.into()),
+        };
+        Config::resolve(path, file.as_deref(), |k| env::var(k).ok())
+    }
+
+    fn resolve(
+        path: PathBuf,
+        file: Option<&str>,
+        env: impl Fn(&str) -> Option<String>,
+    ) -> Result<Self> {
+        let f: ConfigFile = match file {
+            Some(s) => toml::from_str(s)
+                .map_err(|e| Error::ConfigFile(format!("{}: {}", path.display(), e)))?,
+            None => ConfigFile::default(),
+        };
+        let c = Config::build(path, f, env);
+        c.check()?;
+        Ok(c)
+    }
+
+    // what's compiled in, for when the config can't be read
+    fn defaults() -> Self {
+        Config::build(dir().join(CONFIG_FILE), ConfigFile::default(), |_| None)
+    }
+
+    fn build(path: PathBuf, f: ConfigFile, env: impl Fn(&str) -> Option<String>) -> Self {
+        Config {
+            path,
+            engines: layer(
+                vec!["youdao".to_string()],
+                f.engines,
+                "RDICT_ENGINES",
+                &env,
+                list,
+            ),
+            fallback: layer(None, f.fallback.map(Some), "RDICT_FALLBACK", &env, |s| {
+                Some(list(s))
+            }),
+            voice: layer("uk".to_string(), f.voice, "RDICT_VOICE", &env, string),
+            autoplay: layer(false, f.autoplay, "RDICT_AUTOPLAY", &env, boolean),
+            book_audio: layer(true, f.book_audio, "RDICT_BOOK_AUDIO", &env, boolean),
+            format: layer("text".to_string(), f.format, "RDICT_FORMAT", &env, string),
+            color: layer("auto".to_string(), f.color, "RDICT_COLOR", &env, string),
+            theme: layer("dark".to_string(), f.theme, "RDICT_THEME", &env, string),
+            sections: layer(
+                DEFAULT_SECTIONS.iter().map(|s| s.to_string()).collect(),
+                f.sections,
+                "RDICT_SECTIONS",
+                &env,
+                list,
+            ),
+            history: layer(5, f.history, "RDICT_HISTORY", &env, number),
+            cache_ttl: layer(30, f.cache_ttl, "RDICT_CACHE_TTL", &env, number),
+            cache_size: layer(64, f.cache_size, "RDICT_CACHE_SIZE", &env, number),
+            db_path: layer(default_db_path(), f.db_path, "RDICT_DB_PATH", &env, |s| {
+                Some(PathBuf::from(s))
+            }),
+            timeout: layer(10, f.timeout, "RDICT_TIMEOUT", &env, number),
+            proxy: layer(None, f.proxy.map(Some), "RDICT_PROXY", &env, |s| {
+                Some(Some(s.to_string()).filter(|s| !s.is_empty()))
+            }),
+            stardict_dir: layer(
+                default_stardict_dir(),
+                f.stardict_dir,
+                "RDICT_STARDICT_DIR",
+                &env,
+                |s| Some(PathBuf::from(s)),
+            ),
+            dictd_host: layer(
+                "localhost:2628".to_string(),
+                f.dictd_host,
+                "RDICT_DICTD_HOST",
+                &env,
+                string,
+            ),
+            // "*" means all the databases, "!" stops at the first one that matches
+            dictd_db: layer("*".to_string(), f.dictd_db, "RDICT_DICTD_DB", &env, string),
+        }
+    }
+
+    fn check(&self) -> Result<()> {
+        let one_of =
+            |key, s: &Setting<String>, values: &[&str]| match values.contains(&s.value.as_str()) {
+                true => Ok(()),
+                false => Err(Error::Config(key, "invalid value")),
+            };
+        one_of("voice", &self.voice, &["uk", "us", "1", "2"])?;
+        one_of("format", &self.format, &["text", "json", "jsonl", "csv"])?;
+        one_of("color", &self.color, &["always", "never", "auto"])?;
+        for s in &self.sections.value {
+            if s != "all" && !SECTIONS.contains(&s.as_str()) {
+                return Err(Error::Config("sections", "invalid value"));
+            }
+        }
+        // a typo here would fail every lookup later
+        let engines = [
+            ("engines", Some(&self.engines.value)),
+            ("fallback", self.fallback.value.as_ref()),
+        ];
+        for (key, names) in engines {
+            if names
+                .into_iter()
+                .flatten()
+                .any(|n| !Engines::names().contains(&n.as_str()))
+            {
+                return Err(Error::Config(key, "no such engine"));
+            }
+        }
+        Ok(())
+    }
+
+    // (key, value, source) of every setting, for `rdict config show`
+    fn rows(&self) -> Vec<(&'static str, String, &Source)> {
+        let list = |v: &[String]| v.join(",");
+        let path = |p: &PathBuf| p.display().to_string();
+        let none = || "-".to_string();
+        vec![
+            ("engines", list(&self.engines.value), &self.engines.source),
+            (
+                "fallback",
+                self.fallback.value.as_deref().map_or_else(none, list),
+                &self.fallback.source,
+            ),
+            ("voice", self.voice.value.clone(), &self.voice.source),
+            (
+                "autoplay",
+                self.autoplay.value.to_string(),
+                &self.autoplay.source,
+            ),
+            (
+                "book_audio",
+                self.book_audio.value.to_string(),
+                &self.book_audio.source,
+            ),
+            ("format", self.format.value.clone(), &self.format.source),
+            ("color", self.color.value.clone(), &self.color.source),
+            ("theme", self.theme.value.clone(), &self.theme.source),
+            (
+                "sections",
+                list(&self.sections.value),
+                &self.sections.source,
+            ),
+            (
+                "history",
+                self.history.value.to_string(),
+                &self.history.source,
+            ),
+            (
+                "cache_ttl",
+                self.cache_ttl.value.to_string(),
+                &self.cache_ttl.source,
+            ),
+            (
+                "cache_size",
+                self.cache_size.value.to_string(),
+                &self.cache_size.source,
+            ),
+            ("db_path", path(&self.db_path.value), &self.db_path.source),
+            (
+                "timeout",
+                self.timeout.value.to_string(),
+                &self.timeout.source,
+            ),
+            (
+                "proxy",
+                self.proxy.value.clone().unwrap_or_else(none),
+                &self.proxy.source,
+            ),
+            (
+                "stardict_dir",
+                path(&self.stardict_dir.value),
+                &self.stardict_dir.source,
+            ),
+            (
+                "dictd_host",
+                self.dictd_host.value.clone(),
+                &self.dictd_host.source,
+            ),
+            (
+                "dictd_db",
+                self.dictd_db.value.clone(),
+                &self.dictd_db.source,
+            ),
+        ]
+    }
+}
+
+// the file wins over the default, the environment wins over both
+fn layer<T>(
+    default: T,
+    file: Option<T>,
+    key: &'static str,
+    env: &impl Fn(&str) -> Option<String>,
+    parse: impl Fn(&str) -> Option<T>,
+) -> Setting<T> {
+    let mut s = Setting {
+        value: default,
+        source: Source::Default,
+    };
+    if let Some(v) = file {
+        s.set(v, Source::File);
+    }
+    if let Some(v) = env(key).and_then(|v| parse(&v)) {
+        s.set(v, Source::Env(key));
+    }
+    s
+}
+
+// env values are strings, separated by commas for lists
+fn list(s: &str) -> Option<Vec<String>> {
+    Some(
+        s.split(',')
+            .map(str::trim)
+            .filter(|v| !v.is_empty())
+            .map(String::from)
+            .collect(),
+    )
+}
+
+fn boolean(s: &str) -> Option<bool> {
+    match s.trim().to_lowercase().as_str() {
+        "1" | "true" | "yes" => Some(true),
+        "0" | "false" | "no" => Some(false),
+        _ => None,
+    }
+}
+
+fn string(s: &str) -> Option<String> {
+    Some(s.to_string())
+}
+
+fn number<T: std::str::FromStr>(s: &str) -> Option<T> {
+    s.trim().parse().ok()
+}
+
+// $XDG_CONFIG_HOME/rdict
+pub fn dir() -> PathBuf {
+    let mut p = dirs::config_dir().unwrap_or_default();
+    p.push("rdict");
+    p
+}
+
+fn default_db_path() -> PathBuf {
+    let mut p = match dirs::data_dir() {
+        Some(v) => v,
+        None => env::current_dir().unwrap_or_default(),
+    };
+    p.push("rdict");
+    p
+}
+
+fn default_stardict_dir() -> PathBuf {
+    let mut p = dirs::home_dir().unwrap_or_default();
+    p.push(".stardict");
+    p.push("dic");
+    p
+}
+
+// the command line has been applied, nothing changes afterwards
+pub fn init(c: Config) {
+    let _ = CONFIG.set(c);
+}
+
+// parse_args loads the config before anything else and main reports what's
+// wrong with it, so only the tests may get here first. They go on with the
+// defaults then.
+pub fn get() -> &'static Config {
+    CONFIG.get_or_init(|| Config::load().unwrap_or_else(|_| Config::defaults()))
+}
+
+impl fmt::Display for Source {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Source::Default => write!(f, "default"),
+            Source::File => write!(f, "config file"),
+            Source::Env(k) => write!(f, "env {}", k),
+            Source::Cli => write!(f, "command line"),
+        }
+    }
+}
+
+impl fmt::Display for Config {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f)?;
+        writeln!(
+            f,
+            "{s}{path}",
+            s = ' '.align_right(4),
+            path = self.path.display().coloring(Role::Title)
+        )?;
+        let rows = self.rows();
+        let width = rows.iter().map(|r| r.1.chars().count()).max().unwrap_or(0) + 2;
+        for (key, value, source) in rows {
+            writeln!(
+                f,
+                "{s}{key}{value}{source}",
+                s = ' '.align_right(4),
+                key = key.align_left(14).coloring(Role::Index),
+                value = value.align_left(width).coloring(Role::Content),
+                source = source.coloring(Role::Dot),
+            )?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::collections::HashMap;
+
+    fn resolve(file: &str, env: &[(&str, &str)]) -> Result<Config> {
+        let env: HashMap<_, _> = env
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect();
+        Config::resolve(PathBuf::from("config.toml"), Some(file), |k| {
+            env.get(k).cloned()
+        })
+    }
+
+    #[test]
+    fn test_layers() {
+        let c = resolve("", &[]).unwrap();
+        assert_eq!(c.engines.value, ["youdao"]);
+        assert_eq!(c.history.source, Source::Default);
+        assert!(c.fallback.value.is_none());
+
+        let file = r#"
+            engines = ["bing", "ecdict"]
+            fallback = ["ecdict", "youdao"]
+            history = 10
+            theme = "light"
+            proxy = "http://localhost:8080"
+        "#;
+        let c = resolve(file, &[("RDICT_THEME", "mine"), ("RDICT_HISTORY", "oops")]).unwrap();
+        assert_eq!(c.engines.value, ["bing", "ecdict"]);
+        assert_eq!(c.fallback.value.unwrap(), ["ecdict", "youdao"]);
+        assert_eq!(c.proxy.value.as_deref(), Some("http://localhost:8080"));
+        assert_eq!(c.theme.value, "mine");
+        assert_eq!(c.theme.source, Source::Env("RDICT_THEME"));
+        // unparsable values from env are ignored
+        assert_eq!(c.history.value, 10);
+        assert_eq!(c.history.source, Source::File);
+
+        let c = resolve("", &[("RDICT_FALLBACK", "dictd, ecdict")]).unwrap();
+        assert_eq!(c.fallback.value.unwrap(), ["dictd", "ecdict"]);
+
+        assert!(resolve("engine = \"youdao\"", &[]).is_err());
+        // where it's wrong in the file
+        let e = resolve("\nhistory = \"ten\"", &[])
+            .err()
+            .unwrap()
+            .to_string();
+        assert!(e.contains("config.toml") && e.contains("line 2"), "{}", e);
+        assert!(resolve("engines = [\"bnig\"]", &[]).is_err());
+        assert!(resolve("", &[("RDICT_FALLBACK", "youdao,bnig")]).is_err());
+        assert!(resolve("format = \"xml\"", &[]).is_err());
+        assert!(resolve("", &[("RDICT_VOICE", "fr")]).is_err());
+
+        let c = resolve("sections = [\"explains\", \"collins\"]", &[]).unwrap();
+        assert_eq!(c.sections.value, ["explains", "collins"]);
+        assert!(resolve("", &[("RDICT_SECTIONS", "explains,nope")]).is_err());
+    }
+}
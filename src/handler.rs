@@ -1,32 +1,104 @@
+pub mod bing;
+pub mod dictd;
+pub mod ecdict;
+pub mod schema;
+pub mod soup;
+pub mod stardict;
 pub mod youdao;
 
-use std::{fmt, io, io::Read};
+use std::{collections::HashSet, fmt, io, io::Read, sync::OnceLock, time::Duration};
 
-use crate::meta::{self, DictMsg};
-use crate::result::Result;
+use serde::{Deserialize, Serialize};
+
+use crate::config;
+use crate::meta::DictMsg;
+use crate::morph::{self, Lemma};
+use crate::result::{Error, Result};
 use crate::util::{ColorfulRole as Role, Style};
 
-// http://dict.youdao.com/jsonapi?q=keyword
-const YD_PHRASE_API: &str = "http://dict.youdao.com/jsonapi";
+// A source of vocabularies. Fetching and parsing are split so that the raw
+// response can be cached and parsed again later without the network.
+pub trait Dictionary: Send + Sync {
+    fn name(&self) -> &'static str;
+
+    fn capabilities(&self) -> Capabilities;
+
+    fn fetch(&self, phrase: &str) -> Result<Vec<u8>>;
 
-// http://dict.youdao.com/dictvoice?audio=keyword&type=1
-const YD_AUDIO_API: &str = "http://dict.youdao.com/dictvoice";
+    fn parse(&self, raw: &[u8]) -> Result<VocabBody>;
+
+    fn fetch_audio(&self, _phrase: &str, _t: AudioType) -> Result<Vec<u8>> {
+        Err(Error::Engine(self.name(), "audio is not supported"))
+    }
+}
 
-#[derive(Debug, PartialEq, Eq)]
-pub enum Engines {
-    Youdao,
-    Bing,
+#[derive(Debug, Clone, Copy)]
+pub struct Capabilities {
+    // raw responses come from the network and are worth caching
+    pub online: bool,
+    pub audio: bool,
 }
 
-#[derive(Debug)]
+// The registry of all engines, `--dict` accepts exactly these names.
+pub struct Engines;
+
+type Builder = fn() -> Box<dyn Dictionary>;
+
+const REGISTRY: &[(&str, Builder)] = &[
+    ("youdao", || Box::new(youdao::Youdao)),
+    ("bing", || Box::new(bing::Bing)),
+    ("stardict", || Box::new(stardict::StarDict::new())),
+    ("dictd", || Box::new(dictd::Dictd::new())),
+    ("ecdict", || Box::new(ecdict::Ecdict::new())),
+];
+
+// Also kept in the word book as a snapshot, so renaming a field needs a serde alias.
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct VocabBody {
     phrase: String,
     phonetic: Option<Phonetic>,
     explains: Option<Vec<Explain>>,
+    forms: Option<Vec<WordForm>>,
     examples: Option<Vec<Example>>,
     typo: Option<Vec<Typo>>,
+    // set by the query when the phrase is an inflected form, "running" -> "run"
+    #[serde(default)]
+    lemma: Option<Lemma>,
+    // the extra sections of youdao, shown when asked for with --sections
+    #[serde(default)]
+    synonyms: Option<Vec<Synonym>>,
+    #[serde(default)]
+    phrases: Option<Vec<PhraseExplain>>,
+    #[serde(default)]
+    related: Option<Vec<RelatedWord>>,
+    #[serde(default)]
+    etymology: Option<Vec<Etymology>>,
+    #[serde(default)]
+    collins: Option<Vec<CollinsEntry>>,
+    #[serde(default)]
+    web: Option<Vec<WebExplain>>,
+    // "CET4", "考研" and so on
+    #[serde(default)]
+    exams: Option<Vec<String>>,
 }
 
+// every section the text output knows, in the order they are shown
+pub const SECTIONS: &[&str] = &[
+    "phonetic",
+    "explains",
+    "forms",
+    "exams",
+    "synonyms",
+    "phrases",
+    "related",
+    "web",
+    "etymology",
+    "collins",
+    "examples",
+];
+
+pub const DEFAULT_SECTIONS: &[&str] = &["phonetic", "explains", "forms", "examples"];
+
 #[allow(dead_code)]
 #[derive(Debug, Clone)]
 pub enum AudioType {
@@ -41,70 +113,134 @@ struct PhoneticUri {
     us: Option<String>,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 struct Phonetic {
     us: Option<String>,
     uk: Option<String>,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 struct Typo {
     pub guessing: Option<String>,
     pub meaning: Option<String>,
 }
 
-#[derive(Debug, Default, Clone)]
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
 struct Explain {
     content: Option<String>,
+    // where it's from, the database name of dictd
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    source: Option<String>,
 }
 
-#[derive(Debug, Default, Clone)]
+// plural, past tense, etc.
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+struct WordForm {
+    name: String,
+    value: String,
+}
+
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
 struct Example {
     sentence_eng: String,
     trans: String,
 }
 
-#[allow(unreachable_code)]
-impl From<String> for Engines {
-    fn from(engine: String) -> Self {
-        match engine.as_str() {
-            "bing" => {
-                // FIXME
-                meta::wip();
-                std::process::exit(0);
-                Engines::Bing
-            }
+// "int. hi, hallo 喂；哈罗"
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+struct Synonym {
+    pos: Option<String>,
+    words: Vec<String>,
+    trans: String,
+}
 
-            _ => Engines::Youdao,
-        }
-    }
+// "say hello 打招呼；问好"
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+struct PhraseExplain {
+    phrase: String,
+    trans: String,
+}
+
+// words of the same stem, "n. hellos 问候（复数）"
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+struct RelatedWord {
+    pos: Option<String>,
+    word: String,
+    trans: String,
+}
+
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+struct Etymology {
+    source: Option<String>,
+    desc: String,
+}
+
+// one headword of the Collins COBUILD dictionary, a word may have a few
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+struct CollinsEntry {
+    headword: String,
+    // how frequent the word is, 1 to 5
+    star: Option<u8>,
+    senses: Vec<CollinsSense>,
+}
+
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+struct CollinsSense {
+    num: usize,
+    // "N-COUNT", "VERB" and so on
+    pos: Option<String>,
+    definition: String,
+    gloss: Option<String>,
+    examples: Vec<Example>,
+}
+
+// what the web translates the phrase, or a phrase around it, into
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+struct WebExplain {
+    key: String,
+    values: Vec<String>,
 }
 
 impl Engines {
-    pub fn request_meaning(&self, phrase: &str) -> Result<Vec<u8>> {
-        let api: ureq::Request = match self {
-            Engines::Bing => todo!(),
-            Engines::Youdao => ureq::get(YD_PHRASE_API).query("q", phrase),
-        };
-        self.req(api)
+    pub fn names() -> Vec<&'static str> {
+        REGISTRY.iter().map(|(name, _)| *name).collect()
     }
 
-    pub fn request_audio(&self, phrase: &str, t: AudioType) -> Result<Vec<u8>> {
-        let t: &str = t.into();
-        let api = ureq::get(YD_AUDIO_API)
-            .query("audio", phrase)
-            .query("type", t);
-        self.req(api)
+    pub fn get(name: &str) -> Result<Box<dyn Dictionary>> {
+        REGISTRY
+            .iter()
+            .find(|(n, _)| *n == name)
+            .map(|(_, build)| build())
+            .ok_or_else(|| Error::NoEngine(name.to_string()))
     }
+}
 
-    fn req(&self, req_body: ureq::Request) -> Result<Vec<u8>> {
-        match req_body.call() {
-            Err(e) => Err(e.into()),
-            Ok(v) => {
-                let mut res = vec![];
-                v.into_reader().read_to_end(&mut res)?;
-                Ok(res)
-            }
+// all the http requests go through this agent, set up by the config
+fn agent() -> &'static ureq::Agent {
+    static AGENT: OnceLock<ureq::Agent> = OnceLock::new();
+    AGENT.get_or_init(|| {
+        let c = config::get();
+        let mut b = ureq::AgentBuilder::new().timeout(Duration::from_secs(c.timeout.value));
+        // the usual http_proxy and friends are honored if there's none
+        if let Some(p) = c
+            .proxy
+            .value
+            .as_deref()
+            .and_then(|p| ureq::Proxy::new(p).ok())
+        {
+            b = b.proxy(p);
+        }
+        b.build()
+    })
+}
+
+fn req(req_body: ureq::Request) -> Result<Vec<u8>> {
+    match req_body.call() {
+        Err(e) => Err(e.into()),
+        Ok(v) => {
+            let mut res = vec![];
+            v.into_reader().read_to_end(&mut res)?;
+            Ok(res)
         }
     }
 }
@@ -115,20 +251,130 @@ impl VocabBody {
             phrase,
             phonetic: None,
             explains: None,
+            forms: None,
             examples: None,
             typo: None,
+            lemma: None,
+            synonyms: None,
+            phrases: None,
+            related: None,
+            etymology: None,
+            collins: None,
+            web: None,
+            exams: None,
         }
     }
     pub fn is_empty(&self) -> bool {
         self.phonetic.is_none()
             && self.explains.is_none()
+            && self.forms.is_none()
             && self.examples.is_none()
             && self.typo.is_none()
+            && self.synonyms.is_none()
+            && self.phrases.is_none()
+            && self.related.is_none()
+            && self.etymology.is_none()
+            && self.collins.is_none()
+            && self.web.is_none()
+            && self.exams.is_none()
+    }
+
+    pub fn has_explains(&self) -> bool {
+        self.explains.as_ref().is_some_and(|e| !e.is_empty())
+    }
+
+    // what the engine answered for, the lemma's answer may be cached under a form
+    pub fn phrase(&self) -> &str {
+        &self.phrase
+    }
+
+    // "running" among the forms of "run", youdao has "ran 或 running" and such
+    pub fn has_form(&self, word: &str) -> bool {
+        self.forms.iter().flatten().any(|w| {
+            w.value
+                .split(|c: char| !c.is_alphabetic())
+                .any(|v| v.eq_ignore_ascii_case(word))
+        })
+    }
+
+    pub fn set_lemma(&mut self, lemma: Option<Lemma>) {
+        self.lemma = lemma;
+    }
+
+    // the phrase, its lemma, and every form they may take in a sentence
+    fn inflections(&self) -> HashSet<String> {
+        let mut words: Vec<String> = self.phrase.split_whitespace().map(String::from).collect();
+        words.extend(self.lemma.iter().map(|l| l.lemma.clone()));
+        let mut res: HashSet<_> = words.iter().flat_map(|w| morph::inflections(w)).collect();
+        // ECDICT's "run (现在分词)" of the 原型 form
+        for w in self.forms.iter().flatten() {
+            if let Some(v) = w.value.split_whitespace().next() {
+                res.insert(v.to_lowercase());
+            }
+        }
+        res
     }
 }
 
+// Good enough for the markups dictionaries use: line breaks are kept, all
+// the other tags are dropped.
+fn strip_markup(text: &str) -> String {
+    let mut res = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(i) = rest.find('<') {
+        res.push_str(&rest[..i]);
+        let end = match rest[i..].find('>') {
+            Some(n) => i + n,
+            None => break,
+        };
+        let tag = rest[i + 1..end].trim().to_lowercase();
+        if tag.starts_with("br") || tag == "/p" || tag == "/div" || tag == "/li" {
+            res.push('\n');
+        }
+        rest = &rest[end + 1..];
+    }
+    res.push_str(rest);
+
+    res.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&apos;", "'")
+        .replace("&nbsp;", " ")
+        .replace("&amp;", "&")
+}
+
+impl Explain {
+    // "n. 问候" -> (Some("n"), "问候"), but "e.g. ..." and "Mr. ..." have no part
+    fn split(&self) -> Option<(Option<&str>, &str)> {
+        let c = self.content.as_deref()?.trim();
+        let pos = |p: &str| (1..=5).contains(&p.len()) && p.bytes().all(|b| b.is_ascii_lowercase());
+        Some(match c.split_once('.') {
+            // free texts of dictd
+            _ if self.source.is_some() => (None, c),
+            Some((p, meaning))
+                if pos(p) && (meaning.is_empty() || meaning.starts_with(char::is_whitespace)) =>
+            {
+                (Some(p), meaning.trim())
+            }
+            _ => (None, c),
+        })
+    }
+}
+
+// the default sections, the query shows what --sections asks for with `fmt_sections`
 impl fmt::Display for VocabBody {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.fmt_sections(f, DEFAULT_SECTIONS)
+    }
+}
+
+impl VocabBody {
+    // only the `sections` of SECTIONS, "all" for every one of them
+    pub fn fmt_sections<S: AsRef<str>>(
+        &self,
+        f: &mut fmt::Formatter<'_>,
+        sections: &[S],
+    ) -> fmt::Result {
         if self.is_empty() {
             writeln!(
                 f,
@@ -152,9 +398,31 @@ impl fmt::Display for VocabBody {
         let dot = || ". ".coloring(Role::Dot);
         let content = |c: &str| c.coloring(Role::Content);
         let emphasis = |word: &str| word.coloring(Role::Emphasis);
+        let shown = |name: &str| {
+            sections
+                .iter()
+                .any(|s| s.as_ref() == name || s.as_ref() == "all")
+        };
+
+        // running → run (现在分词)
+        if let Some(l) = &self.lemma {
+            writeln!(
+                f,
+                "{s}{form}{arrow}{lemma}{kind}",
+                s = space(4),
+                form = emphasis(&l.form),
+                arrow = " → ".coloring(Role::Dot),
+                lemma = emphasis(&l.lemma),
+                kind = match &l.kind {
+                    Some(k) => format!(" ({})", k).coloring(Role::Other),
+                    None => String::new(),
+                },
+            )?;
+            writeln!(f)?;
+        }
 
         // 音标
-        if let Some(p) = self.phonetic.clone() {
+        if let Some(p) = self.phonetic.clone().filter(|_| shown("phonetic")) {
             writeln!(f, "{s}{t}", s = space(4), t = title("音标"))?;
             write!(f, "{s}", s = space(4))?;
             let mut wp = |us_uk: &str, ph: &str| {
@@ -181,34 +449,207 @@ impl fmt::Display for VocabBody {
         }
 
         // 释义
-        if let Some(exp) = &self.explains {
+        if let Some(exp) = self.explains.as_ref().filter(|_| shown("explains")) {
             writeln!(f, "{s}{t}", s = space(4), t = title("释义"))?;
-            for e in exp.iter() {
-                if let Some(i) = e.content.clone().unwrap().split_once('.') {
-                    writeln!(
+            for e in exp {
+                let Some((pos, meaning)) = e.split() else {
+                    continue;
+                };
+                // dictd has no parts, the database name takes the place
+                match e.source.as_deref().or(pos) {
+                    Some(p) => writeln!(
                         f,
                         "{s}{part}{dot}{zh}",
                         s = space(8),
-                        part = index(i.0.trim()),
+                        part = index(p),
                         dot = dot(),
-                        zh = (i.1.trim().coloring(Role::Content)),
-                    )?;
-                } else {
-                    writeln!(
+                        zh = content(meaning),
+                    )?,
+                    None => writeln!(
                         f,
                         "{s}{dot}{zh}",
                         s = space(7),
                         dot = index(">> "),
-                        zh = content(e.content.clone().unwrap().trim())
+                        zh = content(meaning)
+                    )?,
+                }
+            }
+            writeln!(f)?;
+        }
+
+        // 词形
+        if let Some(forms) = self.forms.as_ref().filter(|_| shown("forms")) {
+            writeln!(f, "{s}{t}", s = space(4), t = title("词形"))?;
+            for w in forms {
+                writeln!(
+                    f,
+                    "{s}{name}{dot}{value}",
+                    s = space(8),
+                    name = index(&w.name),
+                    dot = dot(),
+                    value = content(&w.value),
+                )?;
+            }
+            writeln!(f)?;
+        }
+
+        // 考试
+        if let Some(exams) = self.exams.as_ref().filter(|_| shown("exams")) {
+            writeln!(f, "{s}{t}", s = space(4), t = title("考试"))?;
+            writeln!(
+                f,
+                "{s}{exams}",
+                s = space(8),
+                exams = content(&exams.join(" / "))
+            )?;
+            writeln!(f)?;
+        }
+
+        // 同近义词
+        if let Some(synos) = self.synonyms.as_ref().filter(|_| shown("synonyms")) {
+            writeln!(f, "{s}{t}", s = space(4), t = title("同近义词"))?;
+            for w in synos {
+                if let Some(p) = &w.pos {
+                    write!(f, "{s}{pos} ", s = space(8), pos = index(p))?;
+                } else {
+                    write!(f, "{s}", s = space(8))?;
+                }
+                writeln!(
+                    f,
+                    "{trans}{dot}{words}",
+                    trans = content(&w.trans),
+                    dot = symbol(": "),
+                    words = emphasis(&w.words.join(", ")),
+                )?;
+            }
+            writeln!(f)?;
+        }
+
+        // 短语
+        if let Some(phrs) = self.phrases.as_ref().filter(|_| shown("phrases")) {
+            writeln!(f, "{s}{t}", s = space(4), t = title("短语"))?;
+            for p in phrs {
+                writeln!(
+                    f,
+                    "{s}{phrase} {trans}",
+                    s = space(8),
+                    phrase = emphasis(&p.phrase),
+                    trans = content(&p.trans),
+                )?;
+            }
+            writeln!(f)?;
+        }
+
+        // 同根词
+        if let Some(rels) = self.related.as_ref().filter(|_| shown("related")) {
+            writeln!(f, "{s}{t}", s = space(4), t = title("同根词"))?;
+            for r in rels {
+                if let Some(p) = &r.pos {
+                    write!(f, "{s}{pos} ", s = space(8), pos = index(p))?;
+                } else {
+                    write!(f, "{s}", s = space(8))?;
+                }
+                writeln!(
+                    f,
+                    "{word} {trans}",
+                    word = emphasis(&r.word),
+                    trans = content(&r.trans),
+                )?;
+            }
+            writeln!(f)?;
+        }
+
+        // 网络释义
+        if let Some(web) = self.web.as_ref().filter(|_| shown("web")) {
+            writeln!(f, "{s}{t}", s = space(4), t = title("网络释义"))?;
+            for w in web {
+                writeln!(
+                    f,
+                    "{s}{key}{dot}{values}",
+                    s = space(8),
+                    key = emphasis(&w.key),
+                    dot = symbol(": "),
+                    values = content(&w.values.join("；")),
+                )?;
+            }
+            writeln!(f)?;
+        }
+
+        // 词源
+        if let Some(etym) = self.etymology.as_ref().filter(|_| shown("etymology")) {
+            writeln!(f, "{s}{t}", s = space(4), t = title("词源"))?;
+            for e in etym {
+                writeln!(f, "{s}{desc}", s = space(8), desc = content(&e.desc))?;
+                if let Some(src) = &e.source {
+                    writeln!(
+                        f,
+                        "{s}{src}",
+                        s = space(8),
+                        src = format!("—— {}", src).coloring(Role::Other)
                     )?;
                 }
             }
             writeln!(f)?;
         }
 
+        // 柯林斯
+        if let Some(collins) = self.collins.as_ref().filter(|_| shown("collins")) {
+            writeln!(f, "{s}{t}", s = space(4), t = title("柯林斯"))?;
+            for c in collins {
+                // hello ★★★☆☆
+                let star = c.star.unwrap_or(0).min(5) as usize;
+                writeln!(
+                    f,
+                    "{s}{word} {full}{empty}",
+                    s = space(8),
+                    word = emphasis(&c.headword),
+                    full = "★".repeat(star).coloring(Role::Emphasis),
+                    empty = "☆".repeat(5 - star).coloring(Role::Dot),
+                )?;
+                for e in &c.senses {
+                    write!(
+                        f,
+                        "{s}{num}{dot}",
+                        s = space(8),
+                        num = index(&e.num.to_string()),
+                        dot = dot(),
+                    )?;
+                    if let Some(p) = &e.pos {
+                        write!(f, "{pos} ", pos = index(p))?;
+                    }
+                    writeln!(f, "{def}", def = content(&e.definition))?;
+                    if let Some(g) = &e.gloss {
+                        writeln!(
+                            f,
+                            "{p:>11}{gloss}",
+                            p = " ",
+                            gloss = g.coloring(Role::Other)
+                        )?;
+                    }
+                    for x in &e.examples {
+                        writeln!(
+                            f,
+                            "{p:>11}{arrow}{eng}",
+                            p = " ",
+                            arrow = symbol("> "),
+                            eng = content(&x.sentence_eng),
+                        )?;
+                        writeln!(
+                            f,
+                            "{p:>13}{cn}",
+                            p = " ",
+                            cn = x.trans.coloring(Role::Other)
+                        )?;
+                    }
+                }
+                writeln!(f)?;
+            }
+        }
+
         // 例句
-        if let Some(exa) = &self.examples {
+        if let Some(exa) = self.examples.as_ref().filter(|_| shown("examples")) {
             writeln!(f, "{s}{t}", s = space(4), t = title("例句"))?;
+            let forms = self.inflections();
             for (i, e) in exa.clone().iter().enumerate() {
                 write!(
                     f,
@@ -219,14 +660,23 @@ impl fmt::Display for VocabBody {
                     dot = dot(),
                 )?;
 
-                let phrase = self.phrase.clone().to_lowercase();
+                // every form of the word, without the punctuation around it
                 let mut sentence_eng = String::new();
                 for v in e.sentence_eng.split(' ') {
-                    let x = v.to_lowercase();
-                    if x.starts_with(phrase.as_str()) || x.ends_with(phrase.as_str()) {
-                        sentence_eng.push_str(emphasis(v).as_str());
-                    } else {
-                        sentence_eng.push_str(content(v).as_str());
+                    let core = v.trim_matches(|c: char| !c.is_alphanumeric());
+                    match forms.contains(&core.to_lowercase()) && !core.is_empty() {
+                        true => {
+                            let (start, end) = v.split_at(v.find(core).unwrap_or(0));
+                            let (core, end) = end.split_at(core.len());
+                            if !start.is_empty() {
+                                sentence_eng.push_str(&content(start));
+                            }
+                            sentence_eng.push_str(&emphasis(core));
+                            if !end.is_empty() {
+                                sentence_eng.push_str(&content(end));
+                            }
+                        }
+                        false => sentence_eng.push_str(content(v).as_str()),
                     }
                     sentence_eng.push(' ');
                 }
@@ -244,7 +694,7 @@ impl fmt::Display for VocabBody {
                 f,
                 "{s}{phrase} {msg}",
                 s = space(4),
-                phrase = emphasis(&*self.phrase.clone()),
+                phrase = emphasis(&self.phrase.clone()),
                 msg = content("may be a typo, are you looking for:"),
             )?;
             writeln!(f)?;
@@ -297,3 +747,27 @@ impl From<AudioType> for &str {
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_split() {
+        let split = |c: &str| {
+            let e = Explain {
+                content: Some(c.to_string()),
+                source: None,
+            };
+            e.split()
+                .map(|(p, m)| (p.map(str::to_string), m.to_string()))
+        };
+        let part = |c: &str| split(c).unwrap().0;
+        assert_eq!(part("n. 世界；地球").as_deref(), Some("n"));
+        assert_eq!(part("adj.").as_deref(), Some("adj"));
+        assert_eq!(part("e.g. for example"), None);
+        assert_eq!(part("Mr. Smith"), None);
+        assert_eq!(part("a sentence. another one"), None);
+        assert_eq!(split("Mr. Smith").unwrap().1, "Mr. Smith");
+    }
+}
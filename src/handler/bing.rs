@@ -0,0 +1,220 @@
+use super::soup::{self, Soup};
+use super::*;
+
+// Bing has no public json api for the dictionary, so we scrape the html page
+// https://cn.bing.com/dict/search?q=keyword
+const BING_PHRASE_API: &str = "https://cn.bing.com/dict/search";
+
+pub struct Bing;
+
+impl Dictionary for Bing {
+    fn name(&self) -> &'static str {
+        "bing"
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        Capabilities {
+            online: true,
+            audio: true,
+        }
+    }
+
+    fn fetch(&self, phrase: &str) -> Result<Vec<u8>> {
+        // mkt is required, or bing may serve the english-english page
+        req(agent()
+            .get(BING_PHRASE_API)
+            .query("q", phrase)
+            .query("mkt", "zh-cn"))
+    }
+
+    fn parse(&self, raw: &[u8]) -> Result<VocabBody> {
+        let html = String::from_utf8_lossy(raw);
+        Ok(BingRes::from(html.as_ref()).into())
+    }
+
+    // the pronunciations are the same, borrow them from youdao's voice api
+    fn fetch_audio(&self, phrase: &str, t: AudioType) -> Result<Vec<u8>> {
+        youdao::request_audio(phrase, t)
+    }
+}
+
+// html root
+#[derive(Debug, Clone, Default)]
+pub struct BingRes {
+    headword: Option<String>,
+    phonetic_us: Option<String>,
+    phonetic_uk: Option<String>,
+    defs: Vec<Def>,
+    sentences: Vec<Sentence>,
+    suggestions: Vec<Suggestion>,
+}
+
+// .qdef > ul > li
+#[derive(Debug, Clone)]
+struct Def {
+    pos: Option<String>,
+    def: String,
+}
+
+// #sentenceSeg .se_li
+#[derive(Debug, Clone)]
+struct Sentence {
+    en: String,
+    cn: String,
+}
+
+// .df_wb_c, shown when nothing matched
+#[derive(Debug, Clone)]
+struct Suggestion {
+    word: Option<String>,
+    trans: Option<String>,
+}
+
+impl From<&str> for BingRes {
+    fn from(html: &str) -> Self {
+        let soup = Soup::new(html);
+
+        let mut res = BingRes {
+            // the search box keeps the query even when nothing matched
+            headword: soup
+                .text("#headword h1")
+                .or_else(|| soup.attr("#sb_form_q", "value")),
+            phonetic_us: soup.text(".hd_prUS").and_then(|v| bracketed(&v)),
+            phonetic_uk: soup.text(".hd_pr").and_then(|v| bracketed(&v)),
+            ..Default::default()
+        };
+
+        for li in soup.select(".qdef > ul > li") {
+            if let Some(def) = soup::select_text(&li, ".def") {
+                res.defs.push(Def {
+                    pos: soup::select_text(&li, ".pos"),
+                    def,
+                });
+            }
+        }
+
+        for li in soup.select("#sentenceSeg .se_li") {
+            let en = soup::select_text(&li, ".sen_en");
+            let cn = soup::select_text(&li, ".sen_cn");
+            if let (Some(en), Some(cn)) = (en, cn) {
+                res.sentences.push(Sentence { en, cn });
+            }
+        }
+
+        for c in soup.select(".df_wb_c") {
+            res.suggestions.push(Suggestion {
+                word: soup::select_text(&c, ".df_wb_a"),
+                trans: soup::select_text(&c, ".df_wb_text"),
+            });
+        }
+
+        res
+    }
+}
+
+// "美 [heˈləʊ]" -> "heˈləʊ"
+fn bracketed(s: &str) -> Option<String> {
+    let start = s.find('[')? + 1;
+    let end = s.rfind(']')?;
+    (start < end).then(|| s[start..end].trim().to_string())
+}
+
+impl From<BingRes> for VocabBody {
+    fn from(br: BingRes) -> VocabBody {
+        let mut vb = VocabBody::new(br.headword.unwrap_or_default());
+
+        if br.phonetic_uk.is_some() || br.phonetic_us.is_some() {
+            vb.phonetic = Some(Phonetic {
+                uk: br.phonetic_uk,
+                us: br.phonetic_us,
+            })
+        }
+
+        if !br.defs.is_empty() {
+            vb.explains = Some(
+                br.defs
+                    .into_iter()
+                    .map(|d| Explain {
+                        content: Some(match d.pos {
+                            Some(p) => format!("{} {}", p, d.def),
+                            None => d.def,
+                        }),
+                        source: None,
+                    })
+                    .collect(),
+            );
+        }
+
+        if !br.sentences.is_empty() {
+            vb.examples = Some(
+                br.sentences
+                    .into_iter()
+                    .map(|s| Example {
+                        sentence_eng: s.en,
+                        trans: s.cn,
+                    })
+                    .collect(),
+            );
+        }
+
+        if !br.suggestions.is_empty() {
+            vb.typo = Some(
+                br.suggestions
+                    .into_iter()
+                    .map(|s| super::Typo {
+                        guessing: s.word,
+                        meaning: s.trans,
+                    })
+                    .collect(),
+            );
+        }
+
+        vb
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const HELLO: &str = include_str!("../../tests/fixtures/bing/hello.html");
+    const TYPO: &str = include_str!("../../tests/fixtures/bing/helo.html");
+
+    #[test]
+    fn test_parse_word() {
+        let vb = VocabBody::from(BingRes::from(HELLO));
+        assert_eq!(vb.phrase, "hello");
+
+        let p = vb.phonetic.unwrap();
+        assert_eq!(p.us.as_deref(), Some("heˈləʊ"));
+        assert_eq!(p.uk.as_deref(), Some("həˈləʊ"));
+
+        let explains = vb.explains.unwrap();
+        assert_eq!(explains.len(), 2);
+        assert_eq!(
+            explains[0].content.as_deref(),
+            Some("int. 你好；喂；您好；哈喽")
+        );
+
+        let examples = vb.examples.unwrap();
+        assert_eq!(examples.len(), 2);
+        assert_eq!(examples[0].sentence_eng, "Hello, is anybody there?");
+        assert_eq!(examples[1].sentence_eng, "She said hello to me.");
+        assert_eq!(examples[1].trans, "她向我问好。");
+
+        assert!(vb.typo.is_none());
+    }
+
+    #[test]
+    fn test_parse_typo() {
+        let vb = VocabBody::from(BingRes::from(TYPO));
+        assert_eq!(vb.phrase, "helo");
+        assert!(vb.phonetic.is_none());
+        assert!(vb.explains.is_none());
+
+        let typo = vb.typo.unwrap();
+        assert_eq!(typo.len(), 3);
+        assert_eq!(typo[0].guessing.as_deref(), Some("hello"));
+        assert_eq!(typo[1].meaning.as_deref(), Some("n. 光环；晕"));
+    }
+}
@@ -0,0 +1,483 @@
+use std::io::{BufRead, BufReader, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+use super::*;
+use crate::config;
+
+// A client of the DICT protocol, talking to a dictd server.
+// https://datatracker.ietf.org/doc/html/rfc2229
+pub struct Dictd {
+    host: String,
+    db: String,
+    timeout: Duration,
+}
+
+pub struct Client {
+    reader: BufReader<TcpStream>,
+    writer: TcpStream,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Definition {
+    pub db: String,
+    pub desc: String,
+    pub text: Vec<String>,
+}
+
+// what fetch hands over to parse
+#[derive(Serialize, Deserialize, Debug)]
+struct Raw {
+    phrase: String,
+    definitions: Vec<Definition>,
+    matches: Vec<String>,
+}
+
+impl Dictd {
+    pub fn new() -> Self {
+        Dictd::with_host(
+            config::get().dictd_host.value.clone(),
+            config::get().dictd_db.value.clone(),
+        )
+    }
+
+    pub fn with_host(host: String, db: String) -> Self {
+        Dictd {
+            host,
+            db,
+            timeout: Duration::from_secs(config::get().timeout.value),
+        }
+    }
+
+    pub fn connect(&self) -> Result<Client> {
+        Client::connect(&self.host, self.timeout)
+    }
+}
+
+impl Dictionary for Dictd {
+    fn name(&self) -> &'static str {
+        "dictd"
+    }
+
+    // a service on the LAN, and the answers depend on dictd_host and dictd_db,
+    // so they're always asked for instead of cached
+    fn capabilities(&self) -> Capabilities {
+        Capabilities {
+            online: false,
+            audio: false,
+        }
+    }
+
+    fn fetch(&self, phrase: &str) -> Result<Vec<u8>> {
+        let mut client = self.connect()?;
+        let definitions = client.define(&self.db, phrase)?;
+        // only guess when nothing is defined, like a typo
+        let matches = if definitions.is_empty() {
+            client.matches(&self.db, "lev", phrase)?
+        } else {
+            vec![]
+        };
+        client.quit()?;
+
+        let mut raw = Raw {
+            phrase: phrase.to_string(),
+            definitions,
+            matches: vec![],
+        };
+        for (_, word) in matches {
+            if !raw.matches.contains(&word) {
+                raw.matches.push(word);
+            }
+        }
+        serde_json::to_vec(&raw).map_err(Into::into)
+    }
+
+    fn parse(&self, raw: &[u8]) -> Result<VocabBody> {
+        let raw: Raw = serde_json::from_slice(raw)?;
+        Ok(raw.into())
+    }
+}
+
+impl Client {
+    pub fn connect(host: &str, timeout: Duration) -> Result<Self> {
+        // a firewalled host would keep us waiting for minutes without a timeout
+        let mut stream = Err(Error::Io(io::ErrorKind::NotFound, "no address of the host"));
+        for addr in host.to_socket_addrs()? {
+            stream = TcpStream::connect_timeout(&addr, timeout).map_err(Error::from);
+            if stream.is_ok() {
+                break;
+            }
+        }
+        let stream = stream?;
+        stream.set_read_timeout(Some(timeout))?;
+        stream.set_write_timeout(Some(timeout))?;
+
+        let mut client = Client {
+            reader: BufReader::new(stream.try_clone()?),
+            writer: stream,
+        };
+        client.expect(220)?;
+        Ok(client)
+    }
+
+    // DEFINE database word
+    pub fn define(&mut self, db: &str, word: &str) -> Result<Vec<Definition>> {
+        self.send(&format!("DEFINE {} {}", db, quote(word)))?;
+        let (code, _) = self.status()?;
+        match code {
+            // no match
+            552 => return Ok(vec![]),
+            150 => {}
+            _ => return Err(unexpected()),
+        }
+
+        let mut res = vec![];
+        loop {
+            let (code, line) = self.status()?;
+            match code {
+                151 => {
+                    // 151 "word" database "database description"
+                    let p = params(&line);
+                    res.push(Definition {
+                        db: p.get(2).cloned().unwrap_or_default(),
+                        desc: p.get(3).cloned().unwrap_or_default(),
+                        text: self.text()?,
+                    })
+                }
+                250 => return Ok(res),
+                _ => return Err(unexpected()),
+            }
+        }
+    }
+
+    // MATCH database strategy word, returns (database, word)
+    pub fn matches(
+        &mut self,
+        db: &str,
+        strategy: &str,
+        word: &str,
+    ) -> Result<Vec<(String, String)>> {
+        self.send(&format!("MATCH {} {} {}", db, strategy, quote(word)))?;
+        let (code, _) = self.status()?;
+        match code {
+            152 => {}
+            552 => return Ok(vec![]),
+            // the server doesn't know this strategy, try its default one
+            551 if strategy != "." => return self.matches(db, ".", word),
+            _ => return Err(unexpected()),
+        }
+
+        let res = self.pairs()?;
+        self.expect(250)?;
+        Ok(res)
+    }
+
+    // SHOW DB, returns (database, description)
+    pub fn show_db(&mut self) -> Result<Vec<(String, String)>> {
+        self.send("SHOW DB")?;
+        let (code, _) = self.status()?;
+        match code {
+            110 => {}
+            // no databases present
+            554 => return Ok(vec![]),
+            _ => return Err(unexpected()),
+        }
+
+        let res = self.pairs()?;
+        self.expect(250)?;
+        Ok(res)
+    }
+
+    pub fn quit(&mut self) -> Result<()> {
+        self.send("QUIT")?;
+        self.expect(221)
+    }
+
+    fn send(&mut self, cmd: &str) -> Result<()> {
+        self.writer.write_all(cmd.as_bytes())?;
+        self.writer.write_all(b"\r\n")?;
+        self.writer.flush().map_err(Into::into)
+    }
+
+    fn line(&mut self) -> Result<String> {
+        let mut buf = vec![];
+        if self.reader.read_until(b'\n', &mut buf)? == 0 {
+            return Err(Error::Io(io::ErrorKind::UnexpectedEof, "dictd closed"));
+        }
+        let line = String::from_utf8_lossy(&buf);
+        Ok(line.trim_end_matches(['\r', '\n']).to_string())
+    }
+
+    fn status(&mut self) -> Result<(u16, String)> {
+        let line = self.line()?;
+        let code = line
+            .get(..3)
+            .and_then(|c| c.parse().ok())
+            .ok_or_else(unexpected)?;
+        Ok((code, line))
+    }
+
+    fn expect(&mut self, code: u16) -> Result<()> {
+        match self.status()? {
+            (c, _) if c == code => Ok(()),
+            _ => Err(unexpected()),
+        }
+    }
+
+    // a text response ends with a single dot, and leading dots are doubled
+    fn text(&mut self) -> Result<Vec<String>> {
+        let mut res = vec![];
+        loop {
+            let line = self.line()?;
+            if line == "." {
+                return Ok(res);
+            }
+            match line.strip_prefix("..") {
+                Some(l) => res.push(format!(".{}", l)),
+                None => res.push(line),
+            }
+        }
+    }
+
+    fn pairs(&mut self) -> Result<Vec<(String, String)>> {
+        Ok(self
+            .text()?
+            .iter()
+            .filter_map(|l| {
+                let mut p = params(l).into_iter();
+                Some((p.next()?, p.next()?))
+            })
+            .collect())
+    }
+}
+
+fn unexpected() -> Error {
+    Error::Engine("dictd", "unexpected response")
+}
+
+fn quote(word: &str) -> String {
+    format!("\"{}\"", word.replace('"', ""))
+}
+
+// split a line into atoms and quoted strings
+fn params(line: &str) -> Vec<String> {
+    let mut res = vec![];
+    let mut chars = line.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            ' ' | '\t' => {}
+            '"' | '\'' => {
+                let mut s = String::new();
+                for n in chars.by_ref() {
+                    if n == c {
+                        break;
+                    }
+                    s.push(n);
+                }
+                res.push(s);
+            }
+            _ => {
+                let mut s = String::from(c);
+                while let Some(&n) = chars.peek() {
+                    if n == ' ' || n == '\t' {
+                        break;
+                    }
+                    s.push(n);
+                    chars.next();
+                }
+                res.push(s);
+            }
+        }
+    }
+    res
+}
+
+impl From<Raw> for VocabBody {
+    fn from(raw: Raw) -> VocabBody {
+        let mut vb = VocabBody::new(raw.phrase);
+
+        let mut explains = vec![];
+        for d in raw.definitions {
+            for p in paragraphs(&d.text, &vb.phrase) {
+                // database name, so that we know where it's from
+                explains.push(Explain {
+                    content: Some(p),
+                    source: Some(d.db.clone()),
+                });
+            }
+        }
+        if !explains.is_empty() {
+            vb.explains = Some(explains);
+        }
+
+        if !raw.matches.is_empty() {
+            vb.typo = Some(
+                raw.matches
+                    .into_iter()
+                    .map(|w| super::Typo {
+                        guessing: Some(w),
+                        meaning: None,
+                    })
+                    .collect(),
+            );
+        }
+        vb
+    }
+}
+
+// Definitions are hard wrapped plain texts, join the lines of each paragraph.
+fn paragraphs(text: &[String], phrase: &str) -> Vec<String> {
+    let mut res = vec![];
+    let mut p: Vec<&str> = vec![];
+    for (i, l) in text.iter().enumerate() {
+        let l = l.trim();
+        // most databases repeat the headword first
+        if i == 0 && l.eq_ignore_ascii_case(phrase) {
+            continue;
+        }
+        if l.is_empty() {
+            if !p.is_empty() {
+                res.push(p.join(" "));
+                p.clear();
+            }
+        } else {
+            p.push(l);
+        }
+    }
+    if !p.is_empty() {
+        res.push(p.join(" "));
+    }
+    res
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::net::TcpListener;
+    use std::thread;
+
+    // a stand-in dictd, knows a single word in a single database
+    fn serve() -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                let stream = stream.unwrap();
+                let mut reader = BufReader::new(stream.try_clone().unwrap());
+                let mut w = stream;
+                w.write_all(b"220 test dictd <auth.mime> <1@test>\r\n")
+                    .unwrap();
+
+                let mut line = String::new();
+                while reader.read_line(&mut line).unwrap() > 0 {
+                    let cmd = line.trim_end().to_string();
+                    line.clear();
+                    let res = if cmd == "DEFINE * \"hello\"" {
+                        concat!(
+                            "150 1 definitions retrieved\r\n",
+                            "151 \"hello\" wn \"WordNet (r) 3.0 (2006)\"\r\n",
+                            "hello\r\n",
+                            "    n 1: an expression of greeting [syn: {hello},\r\n",
+                            "         {hullo}, {hi}]\r\n",
+                            "\r\n",
+                            "..dot stuffed\r\n",
+                            ".\r\n",
+                            "250 ok\r\n"
+                        )
+                    } else if cmd.starts_with("DEFINE") {
+                        "552 no match\r\n"
+                    } else if cmd == "MATCH * lev \"helo\"" {
+                        "152 2 matches found\r\n\
+                         wn \"hello\"\r\n\
+                         wn \"halo\"\r\n\
+                         .\r\n\
+                         250 ok\r\n"
+                    } else if cmd.starts_with("MATCH") {
+                        "552 no match\r\n"
+                    } else if cmd == "SHOW DB" {
+                        "110 2 databases present\r\n\
+                         wn \"WordNet (r) 3.0 (2006)\"\r\n\
+                         gcide \"The Collaborative International Dictionary of English\"\r\n\
+                         .\r\n\
+                         250 ok\r\n"
+                    } else if cmd == "QUIT" {
+                        w.write_all(b"221 bye\r\n").unwrap();
+                        break;
+                    } else {
+                        "500 unknown command\r\n"
+                    };
+                    w.write_all(res.as_bytes()).unwrap();
+                }
+            }
+        });
+        addr
+    }
+
+    fn lookup(host: &str, phrase: &str) -> VocabBody {
+        let d = Dictd::with_host(host.to_string(), "*".to_string());
+        let raw = d.fetch(phrase).unwrap();
+        d.parse(&raw).unwrap()
+    }
+
+    #[test]
+    fn test_define() {
+        let host = serve();
+        let vb = lookup(&host, "hello");
+        let explains = vb.explains.unwrap();
+        assert_eq!(explains.len(), 2);
+        assert_eq!(
+            explains[0].content.as_deref(),
+            Some("n 1: an expression of greeting [syn: {hello}, {hullo}, {hi}]")
+        );
+        assert_eq!(explains[0].source.as_deref(), Some("wn"));
+        assert_eq!(explains[1].content.as_deref(), Some(".dot stuffed"));
+        assert_eq!(explains[1].split(), Some((None, ".dot stuffed")));
+        assert!(vb.typo.is_none());
+    }
+
+    #[test]
+    fn test_match() {
+        let host = serve();
+        let vb = lookup(&host, "helo");
+        assert!(vb.explains.is_none());
+        let typo: Vec<_> = vb
+            .typo
+            .unwrap()
+            .into_iter()
+            .filter_map(|t| t.guessing)
+            .collect();
+        assert_eq!(typo, vec!["hello", "halo"]);
+
+        assert!(lookup(&host, "nothing").is_empty());
+    }
+
+    #[test]
+    fn test_show_db() {
+        let host = serve();
+        let mut client = Client::connect(&host, Duration::from_secs(5)).unwrap();
+        let dbs = client.show_db().unwrap();
+        client.quit().unwrap();
+        assert_eq!(dbs.len(), 2);
+        assert_eq!(
+            dbs[0],
+            ("wn".to_string(), "WordNet (r) 3.0 (2006)".to_string())
+        );
+    }
+
+    #[test]
+    fn test_connect_timeout() {
+        // nothing answers there, it shouldn't take much longer than the timeout
+        let start = std::time::Instant::now();
+        assert!(Client::connect("10.255.255.1:2628", Duration::from_millis(200)).is_err());
+        assert!(start.elapsed() < Duration::from_secs(2));
+    }
+
+    #[test]
+    fn test_params() {
+        let p = params("151 \"hello world\" wn 'Word Net'");
+        assert_eq!(p, vec!["151", "hello world", "wn", "Word Net"]);
+    }
+}
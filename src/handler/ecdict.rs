@@ -0,0 +1,304 @@
+use std::path::Path;
+
+use indicatif::{ProgressBar, ProgressStyle};
+use serde::{Deserialize, Serialize};
+
+use super::*;
+use crate::util;
+
+// ECDICT, an open english-chinese dictionary distributed as a csv file.
+// https://github.com/skywind3000/ECDICT
+// The csv is imported into a sled tree once, lookups never leave the disk.
+const ECDICT_TREE: &str = "dict_ecdict";
+const BATCH_SIZE: usize = 10_000;
+
+pub struct Ecdict {
+    tree: Option<sled::Tree>,
+}
+
+// a row of the csv, the columns we don't know are ignored
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct Record {
+    pub word: String,
+    pub phonetic: Option<String>,
+    pub definition: Option<String>,
+    pub translation: Option<String>,
+    pub pos: Option<String>,
+    pub collins: Option<u8>,
+    pub oxford: Option<u8>,
+    pub tag: Option<String>,
+    pub bnc: Option<u32>,
+    pub frq: Option<u32>,
+    pub exchange: Option<String>,
+}
+
+// what fetch hands over to parse
+#[derive(Serialize, Deserialize, Debug)]
+struct Raw {
+    phrase: String,
+    record: Option<Record>,
+}
+
+impl Ecdict {
+    pub fn new() -> Self {
+        Ecdict { tree: None }
+    }
+
+    #[cfg(test)]
+    pub fn with_tree(tree: sled::Tree) -> Self {
+        Ecdict { tree: Some(tree) }
+    }
+
+    fn tree(&self) -> Result<sled::Tree> {
+        match &self.tree {
+            Some(t) => Ok(t.clone()),
+            None => util::open_db()?.open_tree(ECDICT_TREE).map_err(Into::into),
+        }
+    }
+
+    pub fn get(&self, phrase: &str) -> Result<Option<Record>> {
+        let tree = self.tree()?;
+        // "China" and "china" are different entries, the exact one first
+        for key in [phrase.to_string(), phrase.to_lowercase()] {
+            if let Some(v) = tree.get(key)? {
+                return Ok(Some(serde_json::from_slice(&v)?));
+            }
+        }
+        Ok(None)
+    }
+
+    // nothing has been imported
+    pub fn is_empty(&self) -> bool {
+        self.tree().map_or(true, |t| t.is_empty())
+    }
+
+    pub fn import(&self, csv: &Path) -> Result<usize> {
+        let tree = self.tree()?;
+        let mut reader = csv::Reader::from_path(csv)?;
+
+        let bar = ProgressBar::new_spinner();
+        bar.set_style(
+            ProgressStyle::default_spinner().template("{prefix}{spinner:.green} {msg:.green}"),
+        );
+        bar.set_prefix(format!("{:>4}", " "));
+
+        let mut count = 0;
+        let mut batch = sled::Batch::default();
+        for row in reader.deserialize() {
+            let mut r: Record = row?;
+            if r.word.is_empty() {
+                continue;
+            }
+            // multi-line fields are escaped as a literal "\n"
+            for v in [&mut r.definition, &mut r.translation]
+                .into_iter()
+                .flatten()
+            {
+                *v = v.replace("\\n", "\n");
+            }
+            batch.insert(r.word.as_bytes(), serde_json::to_vec(&r)?);
+
+            count += 1;
+            if count % BATCH_SIZE == 0 {
+                tree.apply_batch(std::mem::take(&mut batch))?;
+                bar.set_message(format!("{} words imported", count));
+                bar.tick();
+            }
+        }
+        tree.apply_batch(batch)?;
+        tree.flush()?;
+        bar.finish_and_clear();
+
+        Ok(count)
+    }
+}
+
+impl Dictionary for Ecdict {
+    fn name(&self) -> &'static str {
+        "ecdict"
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        Capabilities {
+            online: false,
+            audio: false,
+        }
+    }
+
+    fn fetch(&self, phrase: &str) -> Result<Vec<u8>> {
+        let raw = Raw {
+            phrase: phrase.to_string(),
+            record: self.get(phrase)?,
+        };
+        serde_json::to_vec(&raw).map_err(Into::into)
+    }
+
+    fn parse(&self, raw: &[u8]) -> Result<VocabBody> {
+        let raw: Raw = serde_json::from_slice(raw)?;
+        let mut vb = VocabBody::new(raw.phrase);
+        if let Some(r) = raw.record {
+            vb.phrase = r.word.clone();
+            vb.fill(r);
+        }
+        Ok(vb)
+    }
+}
+
+impl VocabBody {
+    fn fill(&mut self, r: Record) {
+        let lines = |s: Option<String>| -> Vec<String> {
+            s.unwrap_or_default()
+                .lines()
+                .map(str::trim)
+                .filter(|l| !l.is_empty())
+                .map(String::from)
+                .collect()
+        };
+
+        if let Some(p) = r.phonetic.filter(|p| !p.is_empty()) {
+            self.phonetic = Some(Phonetic {
+                uk: Some(p),
+                us: None,
+            })
+        }
+
+        // chinese translations first, english definitions if there's none
+        let mut explains = lines(r.translation);
+        if explains.is_empty() {
+            explains = lines(r.definition);
+        }
+        if !explains.is_empty() {
+            self.explains = Some(
+                explains
+                    .into_iter()
+                    .map(|e| Explain {
+                        content: Some(e),
+                        source: None,
+                    })
+                    .collect(),
+            );
+        }
+
+        let forms = exchange(r.exchange.as_deref().unwrap_or_default());
+        if !forms.is_empty() {
+            self.forms = Some(forms);
+        }
+    }
+}
+
+// "d:perceived/p:perceived/3:perceives/i:perceiving"
+// "0:perceive/1:pd" means it's the past tense and past participle of perceive.
+fn exchange(s: &str) -> Vec<WordForm> {
+    let mut forms = vec![];
+    let mut lemma = None;
+    let mut of = String::new();
+
+    for (k, v) in s.split('/').filter_map(|v| v.split_once(':')) {
+        match k {
+            "0" => lemma = Some(v.to_string()),
+            "1" => {
+                of = v
+                    .chars()
+                    .filter_map(exchange_name)
+                    .collect::<Vec<_>>()
+                    .join("、")
+            }
+            _ => {
+                if let Some(name) = k.chars().next().and_then(exchange_name) {
+                    forms.push(WordForm {
+                        name: name.to_string(),
+                        value: v.to_string(),
+                    })
+                }
+            }
+        }
+    }
+
+    if let Some(l) = lemma {
+        forms.insert(
+            0,
+            WordForm {
+                name: "原型".to_string(),
+                value: match of.is_empty() {
+                    true => l,
+                    false => format!("{} ({})", l, of),
+                },
+            },
+        );
+    }
+    forms
+}
+
+pub fn exchange_name(c: char) -> Option<&'static str> {
+    match c {
+        'p' => Some("过去式"),
+        'd' => Some("过去分词"),
+        'i' => Some("现在分词"),
+        '3' => Some("第三人称单数"),
+        'r' => Some("比较级"),
+        't' => Some("最高级"),
+        's' => Some("复数"),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const SAMPLE: &str = "tests/fixtures/ecdict/sample.csv";
+
+    fn imported() -> Ecdict {
+        let db = sled::Config::new().temporary(true).open().unwrap();
+        let ecdict = Ecdict::with_tree(db.open_tree(ECDICT_TREE).unwrap());
+        assert_eq!(ecdict.import(Path::new(SAMPLE)).unwrap(), 6);
+        ecdict
+    }
+
+    fn lookup(ecdict: &Ecdict, phrase: &str) -> VocabBody {
+        let raw = ecdict.fetch(phrase).unwrap();
+        ecdict.parse(&raw).unwrap()
+    }
+
+    #[test]
+    fn test_import() {
+        let ecdict = imported();
+        let r = ecdict.get("perceive").unwrap().unwrap();
+        assert_eq!(r.collins, Some(3));
+        assert_eq!(r.frq, Some(2768));
+        assert_eq!(r.tag.as_deref(), Some("cet4 cet6 ky toefl ielts gre"));
+
+        // escaped newlines
+        let r = ecdict.get("a").unwrap().unwrap();
+        assert_eq!(r.translation.as_deref(), Some("第一个字母 A\nart. 一"));
+    }
+
+    #[test]
+    fn test_lookup() {
+        let ecdict = imported();
+
+        let vb = lookup(&ecdict, "perceive");
+        assert_eq!(vb.phonetic.unwrap().uk.as_deref(), Some("pә'si:v"));
+        assert_eq!(
+            vb.explains.unwrap()[0].content.as_deref(),
+            Some("vt. 察觉, 感知, 理解")
+        );
+        let forms = vb.forms.unwrap();
+        assert_eq!(forms[0].name, "过去分词");
+        assert_eq!(forms[3].value, "perceiving");
+
+        let vb = lookup(&ecdict, "perceived");
+        assert_eq!(vb.forms.unwrap()[0].value, "perceive (过去式)");
+
+        // exact case first, then the lower case one
+        assert_eq!(
+            lookup(&ecdict, "China").explains.unwrap()[0]
+                .content
+                .as_deref(),
+            Some("n. 中国")
+        );
+        assert_eq!(lookup(&ecdict, "CHINA").phrase, "china");
+
+        assert!(lookup(&ecdict, "nothing").is_empty());
+    }
+}
@@ -0,0 +1,333 @@
+use serde::Serialize;
+
+use super::*;
+
+// The machine readable form of a lookup, printed by `--format json|jsonl`.
+// Bump the version whenever a field is renamed, removed or changes its type,
+// adding a field doesn't need one. The schema is documented in the README.
+pub const SCHEMA_VERSION: u32 = 1;
+
+#[derive(Serialize, Debug)]
+pub struct Document {
+    pub schema: u32,
+    pub phrase: String,
+    pub results: Vec<Entry>,
+}
+
+// what one engine has to say, `error` is set when it failed
+#[derive(Serialize, Debug, Default)]
+pub struct Entry {
+    pub engine: String,
+    pub found: bool,
+    // as the engine spells it, eg: "british" -> "British"
+    pub phrase: String,
+    pub phonetic: Option<PhoneticJson>,
+    pub explains: Vec<ExplainJson>,
+    pub forms: Vec<FormJson>,
+    pub examples: Vec<ExampleJson>,
+    pub typos: Vec<TypoJson>,
+    // what an inflected form comes from
+    pub lemma: Option<Lemma>,
+    // the extra sections, only youdao has them for now
+    pub synonyms: Vec<SynonymJson>,
+    pub phrases: Vec<PhraseJson>,
+    pub related: Vec<RelatedJson>,
+    pub etymology: Vec<EtymologyJson>,
+    pub web: Vec<WebJson>,
+    pub exams: Vec<String>,
+    pub collins: Vec<CollinsJson>,
+    pub error: Option<String>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct PhoneticJson {
+    pub uk: Option<String>,
+    pub us: Option<String>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct ExplainJson {
+    // "n.", "vt." and so on, if the engine tells
+    pub pos: Option<String>,
+    pub meaning: String,
+    // the database name of dictd
+    pub source: Option<String>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct FormJson {
+    pub name: String,
+    pub value: String,
+}
+
+#[derive(Serialize, Debug)]
+pub struct ExampleJson {
+    pub sentence: String,
+    pub translation: String,
+}
+
+#[derive(Serialize, Debug)]
+pub struct TypoJson {
+    pub word: Option<String>,
+    pub meaning: Option<String>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct SynonymJson {
+    pub pos: Option<String>,
+    pub words: Vec<String>,
+    pub translation: String,
+}
+
+#[derive(Serialize, Debug)]
+pub struct PhraseJson {
+    pub phrase: String,
+    pub translation: String,
+}
+
+#[derive(Serialize, Debug)]
+pub struct RelatedJson {
+    pub pos: Option<String>,
+    pub word: String,
+    pub translation: String,
+}
+
+#[derive(Serialize, Debug)]
+pub struct EtymologyJson {
+    pub source: Option<String>,
+    pub description: String,
+}
+
+#[derive(Serialize, Debug)]
+pub struct WebJson {
+    pub key: String,
+    pub values: Vec<String>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct CollinsJson {
+    pub headword: String,
+    // 1 to 5, the more the more frequent
+    pub star: Option<u8>,
+    pub senses: Vec<CollinsSenseJson>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct CollinsSenseJson {
+    pub number: usize,
+    pub pos: Option<String>,
+    // in english
+    pub definition: String,
+    pub gloss: Option<String>,
+    pub examples: Vec<ExampleJson>,
+}
+
+impl Document {
+    pub fn new(phrase: &str, results: Vec<Entry>) -> Self {
+        Document {
+            schema: SCHEMA_VERSION,
+            phrase: phrase.to_string(),
+            results,
+        }
+    }
+}
+
+impl Entry {
+    pub fn new(engine: &str, phrase: &str, vb: Option<&VocabBody>, error: Option<&Error>) -> Self {
+        let mut e = Entry {
+            engine: engine.to_string(),
+            phrase: phrase.to_string(),
+            error: error.map(|e| e.to_string()),
+            ..Default::default()
+        };
+        if let Some(vb) = vb {
+            e.fill(vb);
+        }
+        e
+    }
+
+    fn fill(&mut self, vb: &VocabBody) {
+        self.found = !vb.is_empty();
+        self.phrase = vb.phrase.clone();
+        self.phonetic = vb.phonetic.as_ref().map(|p| PhoneticJson {
+            uk: p.uk.clone(),
+            us: p.us.clone(),
+        });
+        self.explains = vb
+            .explains
+            .iter()
+            .flatten()
+            .filter_map(|e| {
+                let (pos, meaning) = e.split()?;
+                Some(ExplainJson {
+                    pos: pos.map(|p| format!("{}.", p)),
+                    meaning: meaning.to_string(),
+                    source: e.source.clone(),
+                })
+            })
+            .collect();
+        self.forms = vb
+            .forms
+            .iter()
+            .flatten()
+            .map(|w| FormJson {
+                name: w.name.clone(),
+                value: w.value.clone(),
+            })
+            .collect();
+        self.examples = vb
+            .examples
+            .iter()
+            .flatten()
+            .map(|e| ExampleJson {
+                sentence: e.sentence_eng.clone(),
+                translation: e.trans.clone(),
+            })
+            .collect();
+        self.lemma = vb.lemma.clone();
+        self.typos = vb
+            .typo
+            .iter()
+            .flatten()
+            .map(|t| TypoJson {
+                word: t.guessing.clone(),
+                meaning: t.meaning.clone(),
+            })
+            .collect();
+        self.synonyms = vb
+            .synonyms
+            .iter()
+            .flatten()
+            .map(|s| SynonymJson {
+                pos: s.pos.clone(),
+                words: s.words.clone(),
+                translation: s.trans.clone(),
+            })
+            .collect();
+        self.phrases = vb
+            .phrases
+            .iter()
+            .flatten()
+            .map(|p| PhraseJson {
+                phrase: p.phrase.clone(),
+                translation: p.trans.clone(),
+            })
+            .collect();
+        self.related = vb
+            .related
+            .iter()
+            .flatten()
+            .map(|r| RelatedJson {
+                pos: r.pos.clone(),
+                word: r.word.clone(),
+                translation: r.trans.clone(),
+            })
+            .collect();
+        self.etymology = vb
+            .etymology
+            .iter()
+            .flatten()
+            .map(|e| EtymologyJson {
+                source: e.source.clone(),
+                description: e.desc.clone(),
+            })
+            .collect();
+        self.web = vb
+            .web
+            .iter()
+            .flatten()
+            .map(|w| WebJson {
+                key: w.key.clone(),
+                values: w.values.clone(),
+            })
+            .collect();
+        self.exams = vb.exams.clone().unwrap_or_default();
+        self.collins = vb
+            .collins
+            .iter()
+            .flatten()
+            .map(|c| CollinsJson {
+                headword: c.headword.clone(),
+                star: c.star,
+                senses: c
+                    .senses
+                    .iter()
+                    .map(|s| CollinsSenseJson {
+                        number: s.num,
+                        pos: s.pos.clone(),
+                        definition: s.definition.clone(),
+                        gloss: s.gloss.clone(),
+                        examples: s
+                            .examples
+                            .iter()
+                            .map(|e| ExampleJson {
+                                sentence: e.sentence_eng.clone(),
+                                translation: e.trans.clone(),
+                            })
+                            .collect(),
+                    })
+                    .collect(),
+            })
+            .collect();
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_entry() {
+        let raw = std::fs::read("tests/fixtures/youdao/hello.json").unwrap();
+        let vb = youdao::Youdao.parse(&raw).unwrap();
+        let doc = Document::new(
+            "hello",
+            vec![Entry::new("youdao", "hello", Some(&vb), None)],
+        );
+
+        let v = serde_json::to_value(&doc).unwrap();
+        assert_eq!(v["schema"], SCHEMA_VERSION);
+        let e = &v["results"][0];
+        assert_eq!(e["engine"], "youdao");
+        assert_eq!(e["found"], true);
+        assert_eq!(e["phonetic"]["us"], "heˈloʊ");
+        assert_eq!(e["explains"][0]["pos"], "int.");
+        assert_eq!(e["explains"][0]["meaning"], "喂；哈罗，你好，您好");
+        assert_eq!(e["examples"][0]["translation"], "喂，有人吗？");
+        assert!(e["error"].is_null());
+        assert_eq!(e["forms"][0]["value"], "hellos");
+        assert_eq!(e["synonyms"][1]["words"][0], "greeting");
+        assert_eq!(e["phrases"][0]["translation"], "打招呼；问好");
+        assert_eq!(e["related"][2]["word"], "hellos");
+        assert_eq!(e["etymology"][0]["source"], "牛津词典");
+        assert_eq!(e["web"][1]["values"][0], "凯蒂猫");
+        assert_eq!(e["exams"][2], "CET4");
+        let c = &e["collins"][0];
+        assert_eq!(c["star"], 3);
+        assert_eq!(c["senses"][1]["number"], 2);
+        assert_eq!(c["senses"][1]["pos"], "N-COUNT");
+        assert_eq!(c["senses"][1]["definition"], "Hello is also a noun.");
+        assert_eq!(c["senses"][1]["gloss"], "问候");
+        assert_eq!(
+            c["senses"][0]["examples"][1]["sentence"],
+            "Is that you, Harry? Hello!"
+        );
+
+        let err = Error::Engine("bing", "down");
+        let v = serde_json::to_value(Entry::new("bing", "hello", None, Some(&err))).unwrap();
+        assert_eq!(v["found"], false);
+        assert_eq!(v["phrase"], "hello");
+        assert_eq!(v["error"], "engine error: (bing, down)");
+    }
+
+    #[test]
+    fn test_exams_only() {
+        let mut vb = VocabBody::new("hello".to_string());
+        assert!(vb.is_empty());
+        vb.exams = Some(vec!["CET4".to_string()]);
+        assert!(!vb.is_empty());
+        let e = Entry::new("youdao", "hello", Some(&vb), None);
+        assert!(e.found);
+        assert_eq!(e.exams, ["CET4"]);
+    }
+}
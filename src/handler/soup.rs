@@ -0,0 +1,54 @@
+use scraper::{ElementRef, Html, Selector};
+
+// A thin wrapper around scraper, for engines that only serve html pages.
+pub struct Soup(Html);
+
+impl Soup {
+    pub fn new(html: &str) -> Self {
+        Soup(Html::parse_document(html))
+    }
+
+    pub fn select(&self, css: &str) -> Vec<ElementRef<'_>> {
+        self.0.select(&selector(css)).collect()
+    }
+
+    // text of the first matched element
+    pub fn text(&self, css: &str) -> Option<String> {
+        self.select(css)
+            .into_iter()
+            .map(text)
+            .find(|v| !v.is_empty())
+    }
+
+    pub fn attr(&self, css: &str, attr: &str) -> Option<String> {
+        self.select(css)
+            .into_iter()
+            .find_map(|el| el.value().attr(attr).map(String::from))
+    }
+}
+
+pub fn select<'a>(el: &ElementRef<'a>, css: &str) -> Vec<ElementRef<'a>> {
+    el.select(&selector(css)).collect()
+}
+
+pub fn select_text(el: &ElementRef, css: &str) -> Option<String> {
+    select(el, css)
+        .into_iter()
+        .map(text)
+        .find(|v| !v.is_empty())
+}
+
+// Collapse all whitespaces (including &nbsp;) so that texts splitted into
+// several spans read as a normal sentence.
+pub fn text(el: ElementRef) -> String {
+    let s: String = el.text().collect();
+    s.split(|c: char| c.is_whitespace() || c == '\u{a0}')
+        .filter(|v| !v.is_empty())
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+fn selector(css: &str) -> Selector {
+    // selectors are all hard-coded, a bad one is a bug.
+    Selector::parse(css).unwrap()
+}
@@ -0,0 +1,742 @@
+use std::fs::{self, File};
+use std::io::{Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+
+use flate2::{read::GzDecoder, Decompress, FlushDecompress};
+use serde::{Deserialize, Serialize};
+
+use super::*;
+use crate::config;
+
+// Offline dictionaries in StarDict format, every book (.ifo + .idx + .dict)
+// found in the directory is searched.
+// https://github.com/huzheng001/stardict-3/blob/master/dict/doc/StarDictFileFormat
+const IFO_MAGIC: &str = "StarDict's dict ifo file";
+
+pub struct StarDict {
+    dir: PathBuf,
+    // indexes are loaded on the first lookup, and only once, even if the
+    // directory can't be read.
+    books: OnceLock<io::Result<Vec<Book>>>,
+}
+
+struct Book {
+    name: String,
+    same_type: Option<String>,
+    index: Vec<IdxEntry>,
+    syn: Vec<(String, usize)>,
+    dict: DictFile,
+}
+
+struct IdxEntry {
+    word: String,
+    offset: u64,
+    size: u32,
+}
+
+enum DictFile {
+    Plain(PathBuf),
+    DictZip(DictZip),
+    // gzipped without random access table, decompressed as a whole
+    Memory(Vec<u8>),
+}
+
+// .dict.dz is a gzip file with a table of independently deflated chunks.
+struct DictZip {
+    path: PathBuf,
+    chunk_len: u64,
+    // file offset of each chunk, plus the end of the last one
+    chunks: Vec<u64>,
+}
+
+// what fetch hands over to parse
+#[derive(Serialize, Deserialize, Debug)]
+struct Raw {
+    phrase: String,
+    articles: Vec<Article>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct Article {
+    book: String,
+    word: String,
+    fields: Vec<(char, String)>,
+}
+
+impl StarDict {
+    pub fn new() -> Self {
+        StarDict::with_dir(config::get().stardict_dir.value.clone())
+    }
+
+    pub fn with_dir(dir: PathBuf) -> Self {
+        StarDict {
+            dir,
+            books: OnceLock::new(),
+        }
+    }
+
+    fn books(&self) -> Result<&Vec<Book>> {
+        match self.books.get_or_init(|| load_books(&self.dir)) {
+            Ok(b) => Ok(b),
+            Err(e) => Err(Error::Io(e.kind(), "stardict directory can't be read")),
+        }
+    }
+}
+
+impl Dictionary for StarDict {
+    fn name(&self) -> &'static str {
+        "stardict"
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        Capabilities {
+            online: false,
+            audio: false,
+        }
+    }
+
+    fn fetch(&self, phrase: &str) -> Result<Vec<u8>> {
+        let mut raw = Raw {
+            phrase: phrase.to_string(),
+            articles: vec![],
+        };
+        for book in self.books()? {
+            for e in book.lookup(phrase) {
+                let data = book.dict.read(e.offset, e.size)?;
+                raw.articles.push(Article {
+                    book: book.name.clone(),
+                    word: e.word.clone(),
+                    fields: fields(&data, book.same_type.as_deref()),
+                });
+            }
+        }
+        serde_json::to_vec(&raw).map_err(Into::into)
+    }
+
+    fn parse(&self, raw: &[u8]) -> Result<VocabBody> {
+        let raw: Raw = serde_json::from_slice(raw)?;
+        Ok(raw.into())
+    }
+}
+
+// A book which can't be opened is skipped with a warning, the others still work.
+fn load_books(dir: &Path) -> io::Result<Vec<Book>> {
+    let mut ifos = vec![];
+    for path in fs::read_dir(dir)?.flatten().map(|e| e.path()) {
+        match path.is_dir() {
+            // dictionaries are usually unpacked into their own directories
+            true => ifos.extend(
+                fs::read_dir(&path)
+                    .into_iter()
+                    .flatten()
+                    .flatten()
+                    .map(|e| e.path()),
+            ),
+            false => ifos.push(path),
+        }
+    }
+    ifos.retain(|p| p.extension().is_some_and(|e| e == "ifo"));
+    ifos.sort();
+
+    let books = ifos.iter().filter_map(|p| match Book::open(p) {
+        Ok(b) => Some(b),
+        Err(e) => {
+            eprintln!("{}{}: {}, skipped", ' '.align_right(4), p.display(), e);
+            None
+        }
+    });
+    Ok(books.collect())
+}
+
+impl Book {
+    fn open(ifo: &Path) -> Result<Self> {
+        let info = fs::read_to_string(ifo)?;
+        let mut lines = info.lines();
+        if lines.next().map(str::trim) != Some(IFO_MAGIC) {
+            return Err(invalid("not a stardict ifo file"));
+        }
+
+        let mut name = String::new();
+        let mut same_type = None;
+        let mut offset_bits = 32;
+        for l in lines {
+            match l.split_once('=') {
+                Some(("bookname", v)) => name = v.trim().to_string(),
+                Some(("sametypesequence", v)) => same_type = Some(v.trim().to_string()),
+                Some(("idxoffsetbits", v)) => offset_bits = v.trim().parse().unwrap_or(32),
+                _ => {}
+            }
+        }
+
+        let index = parse_idx(&read_maybe_gz(&sibling(ifo, "idx"))?, offset_bits)?;
+        let syn = match read_maybe_gz(&sibling(ifo, "syn")) {
+            Ok(data) => parse_syn(&data)?,
+            Err(_) => vec![],
+        };
+
+        let dz = sibling(ifo, "dict.dz");
+        let dict = if dz.exists() {
+            DictZip::open(&dz)?
+        } else {
+            DictFile::Plain(sibling(ifo, "dict"))
+        };
+
+        Ok(Book {
+            name,
+            same_type,
+            index,
+            syn,
+            dict,
+        })
+    }
+
+    // entries are sorted by ascii case-insensitive order, so all the entries
+    // of the phrase in any case are adjacent.
+    fn lookup(&self, phrase: &str) -> Vec<&IdxEntry> {
+        let start = self
+            .index
+            .partition_point(|e| casecmp(&e.word, phrase).is_lt());
+        let mut res: Vec<&IdxEntry> = self.index[start..]
+            .iter()
+            .take_while(|e| casecmp(&e.word, phrase).is_eq())
+            .collect();
+
+        let start = self.syn.partition_point(|s| casecmp(&s.0, phrase).is_lt());
+        for (_, i) in self.syn[start..]
+            .iter()
+            .take_while(|s| casecmp(&s.0, phrase).is_eq())
+        {
+            if let Some(e) = self.index.get(*i) {
+                if !res.iter().any(|r| std::ptr::eq(*r, e)) {
+                    res.push(e);
+                }
+            }
+        }
+
+        // the exact one goes first
+        res.sort_by_key(|e| e.word != phrase);
+        res
+    }
+}
+
+impl DictFile {
+    fn read(&self, offset: u64, size: u32) -> Result<Vec<u8>> {
+        match self {
+            DictFile::Plain(path) => {
+                let mut f = File::open(path)?;
+                f.seek(SeekFrom::Start(offset))?;
+                let mut buf = vec![0; size as usize];
+                f.read_exact(&mut buf)?;
+                Ok(buf)
+            }
+            DictFile::DictZip(dz) => dz.read(offset, size),
+            DictFile::Memory(data) => data
+                .get(offset as usize..(offset + size as u64) as usize)
+                .map(|v| v.to_vec())
+                .ok_or_else(|| invalid("offset out of range")),
+        }
+    }
+}
+
+impl DictZip {
+    fn open(path: &Path) -> Result<DictFile> {
+        // the header (and its chunk table) can't be larger than this
+        const MAX_HEADER: u64 = 12 + 65535 + 4096;
+
+        let mut header = vec![];
+        File::open(path)?
+            .take(MAX_HEADER)
+            .read_to_end(&mut header)?;
+        match DictZip::parse_header(path, &header) {
+            Some(dz) if dz.chunk_len == 0 => Err(invalid("zero chunk length of dictzip")),
+            Some(dz) => Ok(DictFile::DictZip(dz)),
+            None => {
+                let mut buf = vec![];
+                GzDecoder::new(File::open(path)?).read_to_end(&mut buf)?;
+                Ok(DictFile::Memory(buf))
+            }
+        }
+    }
+
+    // https://linux.die.net/man/1/dictzip
+    fn parse_header(path: &Path, data: &[u8]) -> Option<DictZip> {
+        const FHCRC: u8 = 0x02;
+        const FEXTRA: u8 = 0x04;
+        const FNAME: u8 = 0x08;
+        const FCOMMENT: u8 = 0x10;
+
+        let u16_at = |i: usize| Some(u16::from_le_bytes([*data.get(i)?, *data.get(i + 1)?]));
+
+        if data.get(..4)?[..3] != [0x1f, 0x8b, 0x08] {
+            return None;
+        }
+        let flags = data[3];
+        if flags & FEXTRA == 0 {
+            return None;
+        }
+
+        let xlen = u16_at(10)? as usize;
+        let mut pos = 12;
+        let extra_end = pos + xlen;
+        let mut table = None;
+        while pos + 4 <= extra_end {
+            let id = data.get(pos..pos + 2)?;
+            let len = u16_at(pos + 2)? as usize;
+            if id == b"RA" {
+                table = Some(pos + 4);
+            }
+            pos += 4 + len;
+        }
+        let t = table?;
+        let chunk_len = u16_at(t + 2)? as u64;
+        let count = u16_at(t + 4)? as usize;
+
+        let mut header_end = extra_end;
+        for flag in [FNAME, FCOMMENT] {
+            if flags & flag != 0 {
+                header_end += data.get(header_end..)?.iter().position(|&b| b == 0)? + 1;
+            }
+        }
+        if flags & FHCRC != 0 {
+            header_end += 2;
+        }
+
+        let mut chunks = vec![header_end as u64];
+        for i in 0..count {
+            let size = u16_at(t + 6 + i * 2)? as u64;
+            chunks.push(chunks[i] + size);
+        }
+
+        Some(DictZip {
+            path: path.to_path_buf(),
+            chunk_len,
+            chunks,
+        })
+    }
+
+    fn read(&self, offset: u64, size: u32) -> Result<Vec<u8>> {
+        let end = offset + size as u64;
+        let first = (offset / self.chunk_len) as usize;
+        let last = ((end.max(1) - 1) / self.chunk_len) as usize;
+        if last + 1 >= self.chunks.len() {
+            return Err(invalid("offset out of range"));
+        }
+
+        let mut f = File::open(&self.path)?;
+        f.seek(SeekFrom::Start(self.chunks[first]))?;
+        let mut compressed = vec![0; (self.chunks[last + 1] - self.chunks[first]) as usize];
+        f.read_exact(&mut compressed)?;
+
+        let mut out = Vec::with_capacity((last - first + 1) * self.chunk_len as usize);
+        for i in first..=last {
+            let from = (self.chunks[i] - self.chunks[first]) as usize;
+            let to = (self.chunks[i + 1] - self.chunks[first]) as usize;
+            let mut chunk = Vec::with_capacity(self.chunk_len as usize);
+            // every chunk is flushed fully, so it inflates on its own
+            Decompress::new(false)
+                .decompress_vec(&compressed[from..to], &mut chunk, FlushDecompress::Sync)
+                .map_err(|_| invalid("broken dictzip chunk"))?;
+            out.extend(chunk);
+        }
+
+        let start = (offset - first as u64 * self.chunk_len) as usize;
+        out.get(start..start + size as usize)
+            .map(|v| v.to_vec())
+            .ok_or_else(|| invalid("offset out of range"))
+    }
+}
+
+// a.ifo -> a.idx, see read_maybe_gz for a.idx.gz
+fn sibling(ifo: &Path, ext: &str) -> PathBuf {
+    ifo.with_extension(ext)
+}
+
+// the file, or the gzipped one next to it if it doesn't exist
+fn read_maybe_gz(path: &Path) -> Result<Vec<u8>> {
+    if path.exists() {
+        return fs::read(path).map_err(Into::into);
+    }
+    let mut gz = path.as_os_str().to_owned();
+    gz.push(".gz");
+    let mut buf = vec![];
+    GzDecoder::new(File::open(gz)?).read_to_end(&mut buf)?;
+    Ok(buf)
+}
+
+fn parse_idx(data: &[u8], offset_bits: u32) -> Result<Vec<IdxEntry>> {
+    let mut res = vec![];
+    let mut rest = data;
+    let offset_len = if offset_bits == 64 { 8 } else { 4 };
+    while !rest.is_empty() {
+        let (word, tail) = cstr(rest)?;
+        if tail.len() < offset_len + 4 {
+            return Err(invalid("truncated idx file"));
+        }
+        let offset = if offset_len == 8 {
+            u64::from_be_bytes(tail[..8].try_into().unwrap())
+        } else {
+            u32::from_be_bytes(tail[..4].try_into().unwrap()) as u64
+        };
+        let size = u32::from_be_bytes(tail[offset_len..offset_len + 4].try_into().unwrap());
+        res.push(IdxEntry { word, offset, size });
+        rest = &tail[offset_len + 4..];
+    }
+    Ok(res)
+}
+
+fn parse_syn(data: &[u8]) -> Result<Vec<(String, usize)>> {
+    let mut res = vec![];
+    let mut rest = data;
+    while !rest.is_empty() {
+        let (word, tail) = cstr(rest)?;
+        if tail.len() < 4 {
+            return Err(invalid("truncated syn file"));
+        }
+        let i = u32::from_be_bytes(tail[..4].try_into().unwrap());
+        res.push((word, i as usize));
+        rest = &tail[4..];
+    }
+    Ok(res)
+}
+
+// split a nul terminated string off the front
+fn cstr(data: &[u8]) -> Result<(String, &[u8])> {
+    let n = data
+        .iter()
+        .position(|&b| b == 0)
+        .ok_or_else(|| invalid("missing nul terminator"))?;
+    Ok((
+        String::from_utf8_lossy(&data[..n]).into_owned(),
+        &data[n + 1..],
+    ))
+}
+
+// g_ascii_strcasecmp, which stardict sorts its indexes with.
+fn casecmp(a: &str, b: &str) -> std::cmp::Ordering {
+    let a = a.bytes().map(|c| c.to_ascii_lowercase());
+    let b = b.bytes().map(|c| c.to_ascii_lowercase());
+    a.cmp(b)
+}
+
+// Split an article into typed fields. Lower case types are nul terminated
+// texts, upper case ones are binaries prefixed with their size. With
+// sametypesequence the type chars are omitted and the last field takes up
+// everything left.
+fn fields(data: &[u8], same_type: Option<&str>) -> Vec<(char, String)> {
+    let mut res = vec![];
+    let mut rest = data;
+
+    let mut take = |t: char, rest: &mut &[u8], last: bool| {
+        let text = if t.is_ascii_lowercase() {
+            let n = match rest.iter().position(|&b| b == 0) {
+                Some(n) => n,
+                None => rest.len(),
+            };
+            let s = String::from_utf8_lossy(&rest[..n]).into_owned();
+            *rest = rest.get(n + 1..).unwrap_or_default();
+            Some(s)
+        } else {
+            // binary resources (sounds, pictures) can't be shown in a terminal
+            let n = if last || rest.len() < 4 {
+                rest.len()
+            } else {
+                4 + u32::from_be_bytes(rest[..4].try_into().unwrap()) as usize
+            };
+            *rest = rest.get(n..).unwrap_or_default();
+            None
+        };
+        if let Some(s) = text {
+            res.push((t, s));
+        }
+    };
+
+    match same_type {
+        Some(seq) => {
+            let n = seq.chars().count();
+            for (i, t) in seq.chars().enumerate() {
+                take(t, &mut rest, i + 1 == n);
+            }
+        }
+        None => {
+            while let Some((&t, tail)) = rest.split_first() {
+                rest = tail;
+                take(t as char, &mut rest, false);
+            }
+        }
+    }
+    res
+}
+
+impl From<Raw> for VocabBody {
+    fn from(raw: Raw) -> VocabBody {
+        let mut vb = VocabBody::new(raw.phrase);
+        let mut phonetic = None;
+        let mut explains = vec![];
+
+        for a in raw.articles {
+            for (t, text) in a.fields {
+                match t {
+                    // english phonetic
+                    't' => phonetic = phonetic.or(Some(text.trim().to_string())),
+                    // xdxf keeps phonetics in <tr>
+                    'x' => {
+                        if let Some(tr) = between(&text, "<tr>", "</tr>") {
+                            phonetic = phonetic.or(Some(tr));
+                        }
+                        explains.extend(lines(&strip_markup(&text), &a.word, &mut phonetic));
+                    }
+                    'g' | 'h' | 'k' => {
+                        explains.extend(lines(&strip_markup(&text), &a.word, &mut phonetic))
+                    }
+                    _ => explains.extend(lines(&text, &a.word, &mut phonetic)),
+                }
+            }
+        }
+
+        if let Some(p) = phonetic {
+            vb.phonetic = Some(Phonetic {
+                uk: Some(p),
+                us: None,
+            })
+        }
+        if !explains.is_empty() {
+            vb.explains = Some(
+                explains
+                    .into_iter()
+                    .map(|e| Explain {
+                        content: Some(e),
+                        source: None,
+                    })
+                    .collect(),
+            );
+        }
+        vb
+    }
+}
+
+// Articles often repeat the headword and a phonetic in the first lines.
+fn lines(text: &str, word: &str, phonetic: &mut Option<String>) -> Vec<String> {
+    let mut res = vec![];
+    for l in text.lines().map(str::trim).filter(|l| !l.is_empty()) {
+        if res.is_empty() && l == word {
+            continue;
+        }
+        let wrapped = |o, c| l.starts_with(o) && l.ends_with(c) && l.len() > 2;
+        if res.is_empty() && (wrapped('/', '/') || wrapped('[', ']')) {
+            if phonetic.is_none() {
+                *phonetic = Some(l[1..l.len() - 1].trim().to_string());
+            }
+            continue;
+        }
+        res.push(l.to_string());
+    }
+    res
+}
+
+fn between(text: &str, open: &str, close: &str) -> Option<String> {
+    let start = text.find(open)? + open.len();
+    let end = start + text[start..].find(close)?;
+    Some(strip_markup(&text[start..end]).trim().to_string())
+}
+
+fn invalid(reason: &'static str) -> Error {
+    Error::Io(io::ErrorKind::InvalidData, reason)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use flate2::{write::GzEncoder, Compress, Compression, FlushCompress};
+    use std::io::Write;
+
+    const WORDS: &[(&str, &str)] = &[
+        ("Apple", "/ˈæpl/\nn. 苹果"),
+        ("apple", "n. 苹果；苹果树"),
+        ("hello", "hello\n[həˈləʊ]\nint. 喂；你好\nn. 招呼"),
+        ("world", "n. 世界；地球"),
+    ];
+
+    // a.ifo, a.idx and the dict content
+    fn write_book(dir: &Path, same_type: Option<&str>) -> Vec<u8> {
+        let mut dict = vec![];
+        let mut idx = vec![];
+        for (w, d) in WORDS {
+            let mut data = vec![];
+            if same_type.is_none() {
+                data.push(b'm');
+            }
+            data.extend(d.as_bytes());
+            if same_type.is_none() {
+                data.push(0);
+            }
+            idx.extend(w.as_bytes());
+            idx.push(0);
+            idx.extend((dict.len() as u32).to_be_bytes());
+            idx.extend((data.len() as u32).to_be_bytes());
+            dict.extend(data);
+        }
+        let mut syn = vec![];
+        syn.extend(b"hi\0");
+        syn.extend(2u32.to_be_bytes());
+
+        let mut ifo = format!(
+            "{}\nversion=2.4.2\nbookname=test\nwordcount={}\nidxfilesize={}\n",
+            IFO_MAGIC,
+            WORDS.len(),
+            idx.len()
+        );
+        if let Some(t) = same_type {
+            ifo.push_str(&format!("sametypesequence={}\n", t));
+        }
+        fs::write(dir.join("a.ifo"), ifo).unwrap();
+        fs::write(dir.join("a.idx"), idx).unwrap();
+        fs::write(dir.join("a.syn"), syn).unwrap();
+        dict
+    }
+
+    fn tmp(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("rdict_stardict_{}", name));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn dictzip(data: &[u8], chunk_len: usize) -> Vec<u8> {
+        let mut sizes = vec![];
+        let mut body = vec![];
+        for chunk in data.chunks(chunk_len) {
+            let mut c = Compress::new(Compression::default(), false);
+            let mut out = Vec::with_capacity(chunk_len * 2 + 64);
+            c.compress_vec(chunk, &mut out, FlushCompress::Full)
+                .unwrap();
+            sizes.push(out.len() as u16);
+            body.extend(out);
+        }
+
+        let mut extra = vec![];
+        extra.extend(b"RA");
+        extra.extend((6 + sizes.len() as u16 * 2).to_le_bytes());
+        extra.extend(1u16.to_le_bytes());
+        extra.extend((chunk_len as u16).to_le_bytes());
+        extra.extend((sizes.len() as u16).to_le_bytes());
+        for s in sizes {
+            extra.extend(s.to_le_bytes());
+        }
+
+        let mut res = vec![0x1f, 0x8b, 0x08, 0x04 | 0x08, 0, 0, 0, 0, 0, 3];
+        res.extend((extra.len() as u16).to_le_bytes());
+        res.extend(extra);
+        res.extend(b"a.dict\0");
+        res.extend(body);
+        res
+    }
+
+    fn lookup(dir: PathBuf, phrase: &str) -> VocabBody {
+        let sd = StarDict::with_dir(dir);
+        let raw = sd.fetch(phrase).unwrap();
+        sd.parse(&raw).unwrap()
+    }
+
+    #[test]
+    fn test_plain_dict() {
+        let dir = tmp("plain");
+        let dict = write_book(&dir, Some("m"));
+        fs::write(dir.join("a.dict"), dict).unwrap();
+
+        let vb = lookup(dir, "hello");
+        assert_eq!(vb.phonetic.unwrap().uk.as_deref(), Some("həˈləʊ"));
+        let explains: Vec<_> = vb
+            .explains
+            .unwrap()
+            .into_iter()
+            .map(|e| e.content.unwrap())
+            .collect();
+        assert_eq!(explains, vec!["int. 喂；你好", "n. 招呼"]);
+    }
+
+    #[test]
+    fn test_case_insensitive_and_syn() {
+        let dir = tmp("case");
+        let dict = write_book(&dir, None);
+        fs::write(dir.join("a.dict"), dict).unwrap();
+
+        let vb = lookup(dir.clone(), "apple");
+        let explains = vb.explains.unwrap();
+        // the exact match goes first
+        assert_eq!(explains[0].content.as_deref(), Some("n. 苹果；苹果树"));
+        assert_eq!(explains.len(), 2);
+
+        let vb = lookup(dir.clone(), "hi");
+        assert_eq!(vb.explains.unwrap().len(), 2);
+
+        assert!(lookup(dir, "nothing").is_empty());
+    }
+
+    #[test]
+    fn test_dictzip() {
+        let dir = tmp("dz");
+        let dict = write_book(&dir, Some("m"));
+        // tiny chunks, so that articles cross chunk boundaries
+        fs::write(dir.join("a.dict.dz"), dictzip(&dict, 7)).unwrap();
+
+        for (w, _) in WORDS {
+            assert!(!lookup(dir.clone(), w).is_empty());
+        }
+        let vb = lookup(dir, "world");
+        assert_eq!(
+            vb.explains.unwrap()[0].content.as_deref(),
+            Some("n. 世界；地球")
+        );
+    }
+
+    #[test]
+    fn test_bad_dictzip() {
+        let path = Path::new("a.dict.dz");
+        assert!(DictZip::parse_header(path, &[0x1f, 0x8b, 0x08]).is_none());
+
+        let dir = tmp("dz0");
+        let dict = write_book(&dir, Some("m"));
+        let mut dz = dictzip(&dict, 7);
+        // the chunk length in the RA field
+        dz[18..20].copy_from_slice(&0u16.to_le_bytes());
+        fs::write(dir.join("a.dict.dz"), dz).unwrap();
+        assert!(DictZip::open(&dir.join("a.dict.dz")).is_err());
+        // the book is skipped
+        assert!(lookup(dir, "world").is_empty());
+    }
+
+    #[test]
+    fn test_bad_book() {
+        let dir = tmp("bad");
+        let dict = write_book(&dir, Some("m"));
+        fs::write(dir.join("a.dict"), dict).unwrap();
+        fs::write(dir.join("b.ifo"), "not a book").unwrap();
+        assert!(!lookup(dir.clone(), "hello").is_empty());
+
+        // loaded once, the missing directory isn't looked for again
+        let missing = dir.join("missing");
+        let sd = StarDict::with_dir(missing.clone());
+        assert!(sd.fetch("hello").is_err());
+        fs::create_dir_all(&missing).unwrap();
+        assert!(sd.fetch("hello").is_err());
+        assert!(StarDict::with_dir(missing).fetch("hello").is_ok());
+    }
+
+    #[test]
+    fn test_gzip_without_table() {
+        let dir = tmp("gz");
+        let dict = write_book(&dir, Some("m"));
+        let mut gz = GzEncoder::new(vec![], Compression::default());
+        gz.write_all(&dict).unwrap();
+        fs::write(dir.join("a.dict.dz"), gz.finish().unwrap()).unwrap();
+
+        assert!(!lookup(dir, "world").is_empty());
+    }
+
+    #[test]
+    fn test_strip_markup() {
+        let s = strip_markup("<b>n.</b> 世界<br/>&lt;地球&gt;");
+        assert_eq!(s, "n. 世界\n<地球>");
+    }
+}
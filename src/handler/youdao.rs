@@ -2,6 +2,49 @@ use super::*;
 use serde::Deserialize;
 use std::fmt::Debug;
 
+// http://dict.youdao.com/jsonapi?q=keyword
+const YD_PHRASE_API: &str = "http://dict.youdao.com/jsonapi";
+
+// http://dict.youdao.com/dictvoice?audio=keyword&type=1
+const YD_AUDIO_API: &str = "http://dict.youdao.com/dictvoice";
+
+pub struct Youdao;
+
+impl Dictionary for Youdao {
+    fn name(&self) -> &'static str {
+        "youdao"
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        Capabilities {
+            online: true,
+            audio: true,
+        }
+    }
+
+    fn fetch(&self, phrase: &str) -> Result<Vec<u8>> {
+        req(agent().get(YD_PHRASE_API).query("q", phrase))
+    }
+
+    fn parse(&self, raw: &[u8]) -> Result<VocabBody> {
+        let data: YoudaoRes = serde_json::from_slice(raw)?;
+        Ok(data.into())
+    }
+
+    fn fetch_audio(&self, phrase: &str, t: AudioType) -> Result<Vec<u8>> {
+        request_audio(phrase, t)
+    }
+}
+
+pub fn request_audio(phrase: &str, t: AudioType) -> Result<Vec<u8>> {
+    let t: &str = t.into();
+    let api = agent()
+        .get(YD_AUDIO_API)
+        .query("audio", phrase)
+        .query("type", t);
+    req(api)
+}
+
 // json root
 #[derive(Deserialize, Debug, Clone)]
 pub struct YoudaoRes {
@@ -10,6 +53,12 @@ pub struct YoudaoRes {
     ec: Option<EC>, // ec: english-chinese, ce: chinese-english
     typos: Option<Typo>,
     blng_sents_part: Option<BlngSentsPart>, // examples
+    syno: Option<Synos>,
+    phrs: Option<Phrs>,
+    rel_word: Option<RelWord>,
+    etym: Option<Etym>,
+    collins: Option<Collins>,
+    web_trans: Option<WebTrans>,
 }
 
 // root > meta
@@ -27,6 +76,8 @@ struct Meta {
 #[derive(Deserialize, Debug, Clone)]
 struct EC {
     word: Vec<Word>,
+    #[serde(default)]
+    exam_type: Vec<String>,
 }
 
 // root > ec > word[]
@@ -37,6 +88,20 @@ struct Word {
     ukphone: Option<String>,
     trs: Option<Vec<Trs>>,
     return_phrase: Option<Phrase>,
+    wfs: Option<Vec<Wfs>>,
+}
+
+// root > ec > word > wfs[]
+#[derive(Deserialize, Clone, Debug)]
+struct Wfs {
+    wf: Wf,
+}
+
+// root > ec > word > wfs[] > wf
+#[derive(Deserialize, Clone, Debug)]
+struct Wf {
+    name: String,
+    value: String,
 }
 
 // root > ec > word > trs[]
@@ -121,6 +186,191 @@ struct TypoContent {
     trans: Option<String>,
 }
 
+// root > syno
+#[derive(Deserialize, Debug, Clone)]
+struct Synos {
+    #[serde(default)]
+    synos: Vec<SynoItem>,
+}
+
+// root > syno > synos[]
+#[derive(Deserialize, Debug, Clone)]
+struct SynoItem {
+    syno: Syno,
+}
+
+// root > syno > synos[] > syno
+#[derive(Deserialize, Debug, Clone)]
+struct Syno {
+    pos: Option<String>,
+    #[serde(default)]
+    ws: Vec<SynoWord>,
+    #[serde(default)]
+    tran: String,
+}
+
+// root > syno > synos[] > syno > ws[]
+#[derive(Deserialize, Debug, Clone)]
+struct SynoWord {
+    w: String,
+}
+
+// root > phrs
+#[derive(Deserialize, Debug, Clone)]
+struct Phrs {
+    #[serde(default)]
+    phrs: Vec<PhrItem>,
+}
+
+// root > phrs > phrs[]
+#[derive(Deserialize, Debug, Clone)]
+struct PhrItem {
+    phr: Phr,
+}
+
+// root > phrs > phrs[] > phr, the headword and trs are shaped like return-phrase
+#[derive(Deserialize, Debug, Clone)]
+struct Phr {
+    headword: Phrase,
+    #[serde(default)]
+    trs: Vec<PhrTrs>,
+}
+
+// root > phrs > phrs[] > phr > trs[]
+#[derive(Deserialize, Debug, Clone)]
+struct PhrTrs {
+    tr: Phrase,
+}
+
+// root > rel_word
+#[derive(Deserialize, Debug, Clone)]
+struct RelWord {
+    #[serde(default)]
+    rels: Vec<RelItem>,
+}
+
+// root > rel_word > rels[]
+#[derive(Deserialize, Debug, Clone)]
+struct RelItem {
+    rel: Rel,
+}
+
+// root > rel_word > rels[] > rel
+#[derive(Deserialize, Debug, Clone)]
+struct Rel {
+    pos: Option<String>,
+    #[serde(default)]
+    words: Vec<RelWordItem>,
+}
+
+// root > rel_word > rels[] > rel > words[]
+#[derive(Deserialize, Debug, Clone)]
+struct RelWordItem {
+    word: String,
+    #[serde(default)]
+    tran: String,
+}
+
+// root > etym
+#[derive(Deserialize, Debug, Clone)]
+struct Etym {
+    etyms: Etyms,
+}
+
+// root > etym > etyms
+#[derive(Deserialize, Debug, Clone)]
+struct Etyms {
+    #[serde(default)]
+    zh: Vec<EtymItem>,
+}
+
+// root > etym > etyms > zh[]
+#[derive(Deserialize, Debug, Clone)]
+struct EtymItem {
+    source: Option<String>,
+    desc: Option<String>,
+}
+
+// root > collins
+#[derive(Deserialize, Debug, Clone)]
+struct Collins {
+    #[serde(default)]
+    collins_entries: Vec<CollinsEntry>,
+}
+
+// root > collins > collins_entries[]
+#[derive(Deserialize, Debug, Clone)]
+struct CollinsEntry {
+    headword: Option<String>,
+    star: Option<String>, // "3"
+    entries: Option<CollinsEntries>,
+}
+
+// root > collins > collins_entries[] > entries
+#[derive(Deserialize, Debug, Clone)]
+struct CollinsEntries {
+    #[serde(default)]
+    entry: Vec<CollinsEntryItem>,
+}
+
+// root > collins > collins_entries[] > entries > entry[]
+#[derive(Deserialize, Debug, Clone)]
+struct CollinsEntryItem {
+    #[serde(default)]
+    tran_entry: Vec<TranEntry>,
+}
+
+// root > collins > collins_entries[] > entries > entry[] > tran_entry[]
+#[derive(Deserialize, Debug, Clone)]
+struct TranEntry {
+    pos_entry: Option<PosEntry>,
+    exam_sents: Option<ExamSents>,
+    // english with <b> tags, then the chinese
+    tran: Option<String>,
+}
+
+// root > collins > ... > tran_entry[] > exam_sents
+#[derive(Deserialize, Debug, Clone)]
+struct ExamSents {
+    #[serde(default)]
+    sent: Vec<ExamSent>,
+}
+
+// root > collins > ... > tran_entry[] > exam_sents > sent[]
+#[derive(Deserialize, Debug, Clone)]
+struct ExamSent {
+    eng_sent: String,
+    #[serde(default)]
+    chn_sent: String,
+}
+
+// root > collins > ... > tran_entry[] > pos_entry
+#[derive(Deserialize, Debug, Clone)]
+struct PosEntry {
+    pos: Option<String>,
+}
+
+// root > web_trans
+#[derive(Deserialize, Debug, Clone)]
+struct WebTrans {
+    #[serde(rename = "web-translation", default)]
+    web_translation: Vec<WebTransItem>,
+}
+
+// root > web_trans > web-translation[]
+#[derive(Deserialize, Debug, Clone)]
+struct WebTransItem {
+    key: String,
+    #[serde(default)]
+    trans: Vec<WebTransValue>,
+}
+
+// root > web_trans > web-translation[] > trans[]
+#[derive(Deserialize, Debug, Clone)]
+struct WebTransValue {
+    value: Option<String>,
+}
+
 impl From<YoudaoRes> for VocabBody {
     fn from(ydr: YoudaoRes) -> VocabBody {
         // Responsed phrase may contain mixed cases. eg: "british" -> "British"
@@ -148,6 +398,7 @@ impl From<YoudaoRes> for VocabBody {
                 for e in trs {
                     explains.push(Explain {
                         content: Some(e.tr[0].l.extract(&ydr.meta.guess_language)),
+                        source: None,
                     });
                 }
             }
@@ -178,6 +429,18 @@ impl From<YoudaoRes> for VocabBody {
                 }
             }
 
+            if let Some(wfs) = &ec.word[0].wfs {
+                let forms: Vec<_> = wfs
+                    .iter()
+                    .map(|w| WordForm {
+                        name: w.wf.name.clone(),
+                        value: w.wf.value.clone(),
+                    })
+                    .collect();
+                vb.forms = non_empty(forms);
+            }
+            vb.exams = non_empty(ec.exam_type);
+
             use std::ops::Not;
             explains
                 .is_empty()
@@ -189,6 +452,154 @@ impl From<YoudaoRes> for VocabBody {
                 .then(|| vb.examples = Some(examples));
         }
 
+        if let Some(syno) = ydr.syno {
+            vb.synonyms = non_empty(
+                syno.synos
+                    .into_iter()
+                    .map(|s| super::Synonym {
+                        pos: s.syno.pos,
+                        words: s.syno.ws.into_iter().map(|w| w.w).collect(),
+                        trans: s.syno.tran,
+                    })
+                    .collect(),
+            );
+        }
+        if let Some(phrs) = ydr.phrs {
+            vb.phrases = non_empty(
+                phrs.phrs
+                    .into_iter()
+                    .map(|p| PhraseExplain {
+                        phrase: p.phr.headword.l.i,
+                        trans: p
+                            .phr
+                            .trs
+                            .into_iter()
+                            .map(|t| t.tr.l.i)
+                            .collect::<Vec<_>>()
+                            .join("；"),
+                    })
+                    .collect(),
+            );
+        }
+        if let Some(rel) = ydr.rel_word {
+            vb.related = non_empty(
+                rel.rels
+                    .into_iter()
+                    .flat_map(|r| {
+                        let pos = r.rel.pos;
+                        r.rel.words.into_iter().map(move |w| RelatedWord {
+                            pos: pos.clone(),
+                            word: w.word,
+                            trans: w.tran,
+                        })
+                    })
+                    .collect(),
+            );
+        }
+        if let Some(etym) = ydr.etym {
+            vb.etymology = non_empty(
+                etym.etyms
+                    .zh
+                    .into_iter()
+                    .filter_map(|e| {
+                        Some(Etymology {
+                            source: e.source,
+                            desc: e.desc.filter(|d| !d.is_empty())?,
+                        })
+                    })
+                    .collect(),
+            );
+        }
+        if let Some(collins) = ydr.collins {
+            let phrase = vb.phrase.clone();
+            vb.collins = non_empty(
+                collins
+                    .collins_entries
+                    .into_iter()
+                    .map(|c| super::CollinsEntry {
+                        headword: c.headword.unwrap_or_else(|| phrase.clone()),
+                        star: c.star.and_then(|s| s.trim().parse().ok()),
+                        senses: collins_senses(c.entries.map(|e| e.entry).unwrap_or_default()),
+                    })
+                    .filter(|c| !c.senses.is_empty())
+                    .collect(),
+            );
+        }
+        if let Some(web) = ydr.web_trans {
+            vb.web = non_empty(
+                web.web_translation
+                    .into_iter()
+                    .map(|w| WebExplain {
+                        key: w.key,
+                        values: w.trans.into_iter().filter_map(|t| t.value).collect(),
+                    })
+                    .collect(),
+            );
+        }
+
         vb
     }
 }
+
+// numbered in the order collins gives them, a sense without a definition is
+// only a cross reference ("seeAlsos") and skipped
+fn collins_senses(entries: Vec<CollinsEntryItem>) -> Vec<CollinsSense> {
+    let mut res = vec![];
+    for t in entries.into_iter().flat_map(|e| e.tran_entry) {
+        let tran = match &t.tran {
+            Some(v) => strip_markup(v),
+            None => continue,
+        };
+        let (definition, gloss) = split_gloss(&tran);
+        if definition.is_empty() {
+            continue;
+        }
+        res.push(CollinsSense {
+            num: res.len() + 1,
+            pos: t.pos_entry.and_then(|p| p.pos).filter(|p| !p.is_empty()),
+            definition: definition.to_string(),
+            gloss: Some(gloss.to_string()).filter(|g| !g.is_empty()),
+            examples: t
+                .exam_sents
+                .map(|e| e.sent)
+                .unwrap_or_default()
+                .into_iter()
+                .map(|s| Example {
+                    sentence_eng: s.eng_sent,
+                    trans: s.chn_sent,
+                })
+                .collect(),
+        });
+    }
+    res
+}
+
+// "You say hello to someone. （用于电话）喂" -> ("You say hello to someone.", "（用于电话）喂"),
+// the chinese starts at the first CJK character or fullwidth punctuation
+fn split_gloss(tran: &str) -> (&str, &str) {
+    let cjk = |c: char| matches!(c, '\u{3000}'..='\u{303f}' | '\u{4e00}'..='\u{9fff}' | '\u{ff00}'..='\u{ffef}');
+    match tran.find(cjk) {
+        Some(i) => (tran[..i].trim(), tran[i..].trim()),
+        None => (tran.trim(), ""),
+    }
+}
+
+fn non_empty<T>(v: Vec<T>) -> Option<Vec<T>> {
+    Some(v).filter(|v| !v.is_empty())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_split_gloss() {
+        let (d, g) = split_gloss("You say 'hello' to someone when you meet them. 喂；你好");
+        assert_eq!(d, "You say 'hello' to someone when you meet them.");
+        assert_eq!(g, "喂；你好");
+        let (d, g) = split_gloss("You say 'hello' on the telephone. （用于电话交谈开头）喂");
+        assert_eq!(d, "You say 'hello' on the telephone.");
+        assert_eq!(g, "（用于电话交谈开头）喂");
+        assert_eq!(split_gloss("Café is a noun."), ("Café is a noun.", ""));
+    }
+}
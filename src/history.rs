@@ -0,0 +1,149 @@
+use std::{collections::HashSet, fmt, time::SystemTime};
+
+use chrono::{Local, TimeZone};
+use serde::{Deserialize, Serialize};
+
+use crate::result::Result;
+use crate::util::{self, ColorfulRole as Role, Style};
+
+// Every lookup is appended to this tree, keyed by the big endian nanoseconds
+// since the epoch, so that iterating it goes in chronological order.
+const HISTORY_TREE: &str = "history";
+// phrase -> how many times it has been looked up
+const COUNT_TREE: &str = "history_count";
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct Record {
+    pub phrase: String,
+    // engines which answered, separated by commas
+    pub engine: String,
+    pub count: u64,
+    // seconds since the epoch
+    pub time: i64,
+}
+
+pub struct History(Vec<Record>);
+
+impl History {
+    pub fn record(phrase: &str, engine: &str) -> Result<Record> {
+        record_in(&util::open_db()?, phrase, engine, SystemTime::now())
+    }
+
+    // the most recent `length` distinct phrases, newest first
+    pub fn getn(length: usize) -> Self {
+        match util::open_db() {
+            Ok(db) => getn_in(&db, length).unwrap_or_default().into(),
+            Err(_) => Vec::new().into(),
+        }
+    }
+
+    pub fn records(&self) -> &[Record] {
+        &self.0
+    }
+}
+
+fn record_in(db: &sled::Db, phrase: &str, engine: &str, now: SystemTime) -> Result<Record> {
+    let since = util::since_epoch(now);
+
+    let count = db
+        .open_tree(COUNT_TREE)?
+        .update_and_fetch(phrase, |v| {
+            let n = v.map(to_u64).unwrap_or(0) + 1;
+            Some(n.to_be_bytes().to_vec())
+        })?
+        .map(|v| to_u64(&v))
+        .unwrap_or(1);
+
+    let r = Record {
+        phrase: phrase.to_string(),
+        engine: engine.to_string(),
+        count,
+        time: since.as_secs() as i64,
+    };
+    let tree = db.open_tree(HISTORY_TREE)?;
+    tree.insert(
+        (since.as_nanos() as u64).to_be_bytes(),
+        serde_json::to_vec(&r)?,
+    )?;
+    tree.flush()?;
+
+    Ok(r)
+}
+
+fn getn_in(db: &sled::Db, length: usize) -> Result<Vec<Record>> {
+    let mut seen = HashSet::new();
+    let mut res = vec![];
+    for entry in db.open_tree(HISTORY_TREE)?.iter().values().rev() {
+        if res.len() == length {
+            break;
+        }
+        let r: Record = serde_json::from_slice(&entry?)?;
+        if seen.insert(r.phrase.clone()) {
+            res.push(r);
+        }
+    }
+    Ok(res)
+}
+
+fn to_u64(v: &[u8]) -> u64 {
+    v.try_into().map(u64::from_be_bytes).unwrap_or(0)
+}
+
+impl fmt::Display for History {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f)?;
+        let width = self.0.iter().map(|r| r.phrase.len()).max().unwrap_or(0) + 2;
+        for (k, r) in self.0.iter().enumerate() {
+            let time = match Local.timestamp_opt(r.time, 0).single() {
+                Some(t) => t.format("%Y-%m-%d %H:%M").to_string(),
+                None => String::new(),
+            };
+            writeln!(
+                f,
+                "{s}{index}{dot}{value}{time}{count}",
+                s = " ".repeat(4),
+                index = (k + 1).align_right(2).coloring(Role::Index),
+                dot = ".".align_left(2).coloring(Role::Dot),
+                value = r.phrase.align_left(width).coloring(Role::Content),
+                time = time.align_left(18).coloring(Role::Other),
+                count = format!("×{}", r.count).coloring(Role::Dot),
+            )?;
+        }
+        Ok(())
+    }
+}
+
+impl From<Vec<Record>> for History {
+    fn from(s: Vec<Record>) -> Self {
+        History(s)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn test_history() {
+        let db = sled::Config::new().temporary(true).open().unwrap();
+        let at = |s: u64| SystemTime::UNIX_EPOCH + Duration::from_secs(1_600_000_000 + s);
+
+        record_in(&db, "hello", "youdao", at(0)).unwrap();
+        record_in(&db, "world", "bing", at(1)).unwrap();
+        let r = record_in(&db, "hello", "youdao,ecdict", at(2)).unwrap();
+        assert_eq!(r.count, 2);
+
+        // newest first, each phrase once
+        let h = getn_in(&db, 5).unwrap();
+        assert_eq!(h.len(), 2);
+        assert_eq!(h[0].phrase, "hello");
+        assert_eq!(h[0].engine, "youdao,ecdict");
+        assert_eq!(h[0].time, 1_600_000_002);
+        assert_eq!(h[1].phrase, "world");
+        assert_eq!(h[1].count, 1);
+
+        assert_eq!(getn_in(&db, 1).unwrap().len(), 1);
+        assert_eq!(getn_in(&db, 0).unwrap().len(), 0);
+    }
+}
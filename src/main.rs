@@ -1,28 +1,215 @@
+mod anki;
+mod annotate;
 mod args;
+mod batch;
+mod book;
+mod cache;
+mod config;
 mod handler;
+mod history;
 mod meta;
+mod morph;
 mod query;
+mod quiz;
+mod repl;
 mod result;
+mod review;
+mod theme;
+mod tui;
 mod util;
 
-use args::{parse_args, CliAction};
-use query::{History, QueryTarget};
+use args::{parse_args, BookAction, CacheAction, CliAction};
+use book::{Book, Word, Words};
+use cache::Policy;
+use handler::{dictd::Dictd, ecdict::Ecdict};
+use history::History;
+use meta::DictMsg;
+use query::{Aggregate, Format};
+use util::{ColorfulRole as Role, Style};
 
 fn main() {
-    match parse_args().unwrap() {
+    let action = match parse_args() {
+        Ok(v) => v,
+        Err(e) => fail(e),
+    };
+    match action {
         CliAction::Query(info) => {
-            let mut target = QueryTarget::new(info.phrase, info.engine);
-            target.query_with_pb().save().unwrap();
-            println!("{}", target);
+            let mut res = Aggregate::new(info.phrase, info.engines, info.mode);
+            match info.format {
+                Format::Text => {
+                    save(res.query_with_pb());
+                    println!("{}", res);
+                }
+                // keep stdout clean of the spinner
+                Format::Json => {
+                    save(res.query());
+                    println!(
+                        "{}",
+                        serde_json::to_string_pretty(&res.to_document()).unwrap()
+                    );
+                }
+                Format::Jsonl => {
+                    save(res.query());
+                    println!("{}", serde_json::to_string(&res.to_document()).unwrap());
+                }
+                Format::Csv => {
+                    save(res.query());
+                    print!("{}", batch::to_csv(&res, true).unwrap());
+                }
+            }
 
+            if info.save {
+                Book::open()
+                    .and_then(|b| b.add(Word::new(res.phrase(), res.answer())))
+                    .unwrap_or_else(|e| fail(e));
+                if let Err(e) = res.prefetch_for_book() {
+                    eprintln!("{}no audio: {}", ' '.align_right(4), e);
+                }
+            }
+            if info.prefetch {
+                if let Err(e) = res.prefetch_audio() {
+                    eprintln!("{}no audio: {}", ' '.align_right(4), e);
+                }
+            }
             if let Some(v) = info.voice {
-                target.play_audio(v).unwrap();
+                res.play_audio(v).unwrap();
             }
         }
         CliAction::ListHistory(s) => {
             let history = History::getn(s);
             println!("{}", history);
         }
-        CliAction::Other => meta::show_logo(),
+        CliAction::ShowDb => {
+            let host = &config::get().dictd_host.value;
+            let mut client = Dictd::new()
+                .connect()
+                .unwrap_or_else(|_| fail(format!("cannot connect to {}", host)));
+            println!();
+            for (name, desc) in client.show_db().unwrap_or_else(|e| fail(e)) {
+                println!(
+                    "{s}{name}{desc}",
+                    s = ' '.align_right(4),
+                    name = name.align_left(12).coloring(Role::Index),
+                    desc = desc.coloring(Role::Content),
+                );
+            }
+            let _ = client.quit();
+        }
+        CliAction::Import(file) => {
+            let n = Ecdict::new().import(&file).unwrap_or_else(|e| fail(e));
+            println!(
+                "{s}{msg}",
+                s = ' '.align_right(4),
+                msg = format!("{} words imported", n).coloring(Role::Content)
+            );
+        }
+        CliAction::Cache(action) => {
+            let db = util::open_db().unwrap_or_else(|e| fail(e));
+            let policy = Policy::from_config();
+            let done = |msg: String| {
+                println!(
+                    "\n{s}{msg}",
+                    s = ' '.align_right(4),
+                    msg = msg.coloring(Role::Content)
+                )
+            };
+            match action {
+                CacheAction::Stats => {
+                    cache::print_stats(&cache::stats(&db, policy).unwrap_or_else(|e| fail(e)))
+                }
+                CacheAction::Clear => done(format!(
+                    "{} entries removed",
+                    cache::clear(&db).unwrap_or_else(|e| fail(e))
+                )),
+                CacheAction::Prune => done(format!(
+                    "{} entries removed",
+                    cache::prune(&db, policy).unwrap_or_else(|e| fail(e))
+                )),
+                CacheAction::Verify => {
+                    cache::print_broken(&cache::verify(&db).unwrap_or_else(|e| fail(e)))
+                }
+            }
+        }
+        CliAction::Book(action) => {
+            let book = Book::open().unwrap_or_else(|e| fail(e));
+            let done = |msg: String| {
+                println!(
+                    "\n{s}{msg}",
+                    s = ' '.align_right(4),
+                    msg = msg.coloring(Role::Content)
+                )
+            };
+            match action {
+                BookAction::Add { phrase, tags, note } => {
+                    let mut res =
+                        Aggregate::from_config(phrase.join(" ")).unwrap_or_else(|e| fail(e));
+                    save(res.query_with_pb());
+                    let word = Word::new(res.phrase(), res.answer())
+                        .tagged(tags)
+                        .noted(note);
+                    let found = word.vocabulary.is_some();
+                    match book.add(word).unwrap_or_else(|e| fail(e)) {
+                        true => done(format!("{} added", res.phrase())),
+                        false => done(format!("{} updated", res.phrase())),
+                    }
+                    if !found {
+                        done(DictMsg::NotFound.to_string());
+                    }
+                    if let Err(e) = res.prefetch_for_book() {
+                        done(format!("no audio: {}", e));
+                    }
+                }
+                BookAction::Rm { phrase } => {
+                    match book.remove(&phrase.join(" ")).unwrap_or_else(|e| fail(e)) {
+                        true => done(format!("{} removed", phrase.join(" "))),
+                        false => done(DictMsg::NotFound.to_string()),
+                    }
+                }
+                BookAction::Ls { tag } => {
+                    println!(
+                        "{}",
+                        Words(book.list(tag.as_deref()).unwrap_or_else(|e| fail(e)))
+                    )
+                }
+                BookAction::Show { phrase } => {
+                    match book.get(&phrase.join(" ")).unwrap_or_else(|e| fail(e)) {
+                        Some(w) => println!("{}", w),
+                        None => done(DictMsg::NotFound.to_string()),
+                    }
+                }
+                BookAction::Export { anki, tag } => {
+                    let words = book.list(tag.as_deref()).unwrap_or_else(|e| fail(e));
+                    let audio = |p: &str, t| query::cached_audio(p, t).ok().flatten();
+                    let n = anki::export(&words, &anki, audio).unwrap_or_else(|e| fail(e));
+                    done(format!(
+                        "{} words and {} audio written to {}",
+                        n.notes,
+                        n.media,
+                        anki.display()
+                    ))
+                }
+            }
+        }
+        CliAction::Review(opts) => review::run(opts).unwrap_or_else(|e| fail(e)),
+        CliAction::Quiz(opts) => println!("{}", quiz::run(opts).unwrap_or_else(|e| fail(e))),
+        CliAction::Annotate(opts) => print!("{}", annotate::run(opts).unwrap_or_else(|e| fail(e))),
+        // stdout is for the results
+        CliAction::Batch(opts) => eprintln!("{}", batch::run(opts).unwrap_or_else(|e| fail(e))),
+        CliAction::ShowConfig => println!("{}", config::get()),
+        CliAction::Tui => tui::run().unwrap_or_else(|e| fail(e)),
+        CliAction::Other => repl::run().unwrap_or_else(|e| fail(e)),
     }
 }
+
+// the answer is here already, a cache which can't be written is not a reason to stop
+fn save(res: &Aggregate) {
+    if let Err(e) = res.save() {
+        eprintln!("{}not cached: {}", ' '.align_right(4), e);
+    }
+}
+
+// errors a user can fix, like a bad config, are printed without a backtrace
+fn fail(e: impl std::fmt::Display) -> ! {
+    eprintln!("{}{}", ' '.align_right(4), e);
+    std::process::exit(1)
+}
@@ -1,3 +1,5 @@
+use std::fmt;
+
 use crate::util::{ColorfulRole as Role, Style};
 
 const VERSION: &str = "Rdict v0.0.1";
@@ -21,23 +23,18 @@ pub fn show_logo() {
         DictMsg::Intro.coloring(Role::Content)
     );
 }
-pub fn wip() {
-    show_logo();
-    println!("{}{}", ' '.align_right(4), DictMsg::Wip.coloring(Role::Wip))
-}
 
 pub enum DictMsg {
     NotFound,
     Shrug,
-    Wip,
     Version,
     Intro,
 }
 
-impl ToString for DictMsg {
-    fn to_string(&self) -> String {
+impl fmt::Display for DictMsg {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         let s: &str = self.into();
-        s.to_owned()
+        write!(f, "{}", s)
     }
 }
 
@@ -49,7 +46,6 @@ impl From<&DictMsg> for &str {
             Shrug => r#"¯\_(ツ)_/¯"#,
             Version => VERSION,
             Intro => "A cli dict tool implemented by Rust with love.",
-            Wip => "🚧 Method Under Construction 🚧",
         }
     }
 }
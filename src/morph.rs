@@ -0,0 +1,551 @@
+use std::collections::HashSet;
+
+use serde::{Deserialize, Serialize};
+
+use crate::handler::ecdict::{self, Ecdict, Record};
+
+// base, past tense and past participle, alternatives separated by "|"
+const VERBS: &[(&str, &str, &str)] = &[
+    ("arise", "arose", "arisen"),
+    ("awake", "awoke", "awoken"),
+    ("be", "was|were", "been"),
+    ("bear", "bore", "born|borne"),
+    ("beat", "beat", "beaten"),
+    ("become", "became", "become"),
+    ("begin", "began", "begun"),
+    ("bend", "bent", "bent"),
+    ("bind", "bound", "bound"),
+    ("bite", "bit", "bitten"),
+    ("bleed", "bled", "bled"),
+    ("blow", "blew", "blown"),
+    ("break", "broke", "broken"),
+    ("breed", "bred", "bred"),
+    ("bring", "brought", "brought"),
+    ("build", "built", "built"),
+    ("buy", "bought", "bought"),
+    ("catch", "caught", "caught"),
+    ("choose", "chose", "chosen"),
+    ("come", "came", "come"),
+    ("cut", "cut", "cut"),
+    ("deal", "dealt", "dealt"),
+    ("dig", "dug", "dug"),
+    ("do", "did", "done"),
+    ("draw", "drew", "drawn"),
+    ("dream", "dreamt", "dreamt"),
+    ("drink", "drank", "drunk"),
+    ("drive", "drove", "driven"),
+    ("eat", "ate", "eaten"),
+    ("fall", "fell", "fallen"),
+    ("feed", "fed", "fed"),
+    ("feel", "felt", "felt"),
+    ("fight", "fought", "fought"),
+    ("find", "found", "found"),
+    ("flee", "fled", "fled"),
+    ("fly", "flew", "flown"),
+    ("forbid", "forbade", "forbidden"),
+    ("forget", "forgot", "forgotten"),
+    ("forgive", "forgave", "forgiven"),
+    ("freeze", "froze", "frozen"),
+    ("get", "got", "got|gotten"),
+    ("give", "gave", "given"),
+    ("go", "went", "gone"),
+    ("grind", "ground", "ground"),
+    ("grow", "grew", "grown"),
+    ("hang", "hung", "hung"),
+    ("have", "had", "had"),
+    ("hear", "heard", "heard"),
+    ("hide", "hid", "hidden"),
+    ("hit", "hit", "hit"),
+    ("hold", "held", "held"),
+    ("hurt", "hurt", "hurt"),
+    ("keep", "kept", "kept"),
+    ("kneel", "knelt", "knelt"),
+    ("know", "knew", "known"),
+    ("lay", "laid", "laid"),
+    ("lead", "led", "led"),
+    ("leave", "left", "left"),
+    ("lend", "lent", "lent"),
+    ("let", "let", "let"),
+    ("lie", "lay", "lain"),
+    ("light", "lit", "lit"),
+    ("lose", "lost", "lost"),
+    ("make", "made", "made"),
+    ("mean", "meant", "meant"),
+    ("meet", "met", "met"),
+    ("mistake", "mistook", "mistaken"),
+    ("pay", "paid", "paid"),
+    ("put", "put", "put"),
+    ("read", "read", "read"),
+    ("ride", "rode", "ridden"),
+    ("ring", "rang", "rung"),
+    ("rise", "rose", "risen"),
+    ("run", "ran", "run"),
+    ("say", "said", "said"),
+    ("see", "saw", "seen"),
+    ("seek", "sought", "sought"),
+    ("sell", "sold", "sold"),
+    ("send", "sent", "sent"),
+    ("set", "set", "set"),
+    ("shake", "shook", "shaken"),
+    ("shine", "shone", "shone"),
+    ("shoot", "shot", "shot"),
+    ("show", "showed", "shown"),
+    ("shrink", "shrank", "shrunk"),
+    ("shut", "shut", "shut"),
+    ("sing", "sang", "sung"),
+    ("sink", "sank", "sunk"),
+    ("sit", "sat", "sat"),
+    ("sleep", "slept", "slept"),
+    ("slide", "slid", "slid"),
+    ("speak", "spoke", "spoken"),
+    ("spend", "spent", "spent"),
+    ("spin", "spun", "spun"),
+    ("split", "split", "split"),
+    ("spread", "spread", "spread"),
+    ("spring", "sprang", "sprung"),
+    ("stand", "stood", "stood"),
+    ("steal", "stole", "stolen"),
+    ("stick", "stuck", "stuck"),
+    ("sting", "stung", "stung"),
+    ("strike", "struck", "struck"),
+    ("swear", "swore", "sworn"),
+    ("swim", "swam", "swum"),
+    ("swing", "swung", "swung"),
+    ("take", "took", "taken"),
+    ("teach", "taught", "taught"),
+    ("tear", "tore", "torn"),
+    ("tell", "told", "told"),
+    ("think", "thought", "thought"),
+    ("throw", "threw", "thrown"),
+    ("understand", "understood", "understood"),
+    ("undergo", "underwent", "undergone"),
+    ("wake", "woke", "woken"),
+    ("wear", "wore", "worn"),
+    ("weep", "wept", "wept"),
+    ("win", "won", "won"),
+    ("withdraw", "withdrew", "withdrawn"),
+    ("write", "wrote", "written"),
+];
+
+// singular and plural
+const NOUNS: &[(&str, &str)] = &[
+    ("alumnus", "alumni"),
+    ("analysis", "analyses"),
+    ("appendix", "appendices"),
+    ("bacterium", "bacteria"),
+    ("cactus", "cacti"),
+    ("calf", "calves"),
+    ("child", "children"),
+    ("crisis", "crises"),
+    ("criterion", "criteria"),
+    ("curriculum", "curricula"),
+    ("datum", "data"),
+    ("foot", "feet"),
+    ("fungus", "fungi"),
+    ("goose", "geese"),
+    ("half", "halves"),
+    ("hypothesis", "hypotheses"),
+    ("index", "indices"),
+    ("knife", "knives"),
+    ("leaf", "leaves"),
+    ("life", "lives"),
+    ("loaf", "loaves"),
+    ("louse", "lice"),
+    ("man", "men"),
+    ("matrix", "matrices"),
+    ("medium", "media"),
+    ("mouse", "mice"),
+    ("nucleus", "nuclei"),
+    ("ox", "oxen"),
+    ("person", "people"),
+    ("phenomenon", "phenomena"),
+    ("radius", "radii"),
+    ("self", "selves"),
+    ("shelf", "shelves"),
+    ("stimulus", "stimuli"),
+    ("thesis", "theses"),
+    ("thief", "thieves"),
+    ("tooth", "teeth"),
+    ("vertex", "vertices"),
+    ("wife", "wives"),
+    ("wolf", "wolves"),
+    ("woman", "women"),
+];
+
+// base, comparative and superlative
+const ADJECTIVES: &[(&str, &str, &str)] = &[
+    ("bad", "worse", "worst"),
+    ("far", "further|farther", "furthest|farthest"),
+    ("good", "better", "best"),
+    ("little", "less", "least"),
+    ("many", "more", "most"),
+    ("old", "elder", "eldest"),
+];
+
+// the present tense of the verbs above which isn't just an "s" away
+const PRESENT: &[(&str, &str, &str)] = &[
+    ("am", "be", ""),
+    ("are", "be", ""),
+    ("is", "be", "3"),
+    ("has", "have", "3"),
+    ("does", "do", "3"),
+];
+
+// a record of ECDICT, or a map of them in the tests
+type Lookup<'a> = &'a dyn Fn(&str) -> Option<Record>;
+
+// "running" -> "run", `kind` is in chinese as ecdict::exchange_name says
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct Lemma {
+    pub form: String,
+    pub lemma: String,
+    pub kind: Option<String>,
+}
+
+impl Lemma {
+    // `kinds` are the codes of the ECDICT exchange field, "pd" and so on
+    fn new(form: &str, lemma: &str, kinds: &str) -> Self {
+        let names: Vec<_> = kinds.chars().filter_map(ecdict::exchange_name).collect();
+        Lemma {
+            form: form.to_string(),
+            lemma: lemma.to_string(),
+            kind: match names.is_empty() {
+                true => None,
+                false => Some(names.join("、")),
+            },
+        }
+    }
+}
+
+// (form, lemma, kinds) of all the irregular forms
+fn irregular_forms() -> impl Iterator<Item = (&'static str, &'static str, &'static str)> {
+    let verbs = VERBS.iter().flat_map(|&(base, past, pp)| {
+        let past = past.split('|').map(move |f| (f, base, "p"));
+        let pp = pp.split('|').map(move |f| (f, base, "d"));
+        past.chain(pp)
+    });
+    let nouns = NOUNS.iter().map(|&(base, plural)| (plural, base, "s"));
+    let adjectives = ADJECTIVES.iter().flat_map(|&(base, r, t)| {
+        let r = r.split('|').map(move |f| (f, base, "r"));
+        let t = t.split('|').map(move |f| (f, base, "t"));
+        r.chain(t)
+    });
+    verbs
+        .chain(nouns)
+        .chain(adjectives)
+        .chain(PRESENT.iter().copied())
+        .filter(|(form, base, _)| form != base)
+}
+
+fn is_vowel(c: char) -> bool {
+    "aeiou".contains(c)
+}
+
+// "run" for "runn", the last letter doubled before a suffix
+fn undouble(stem: &str) -> Option<&str> {
+    match stem.as_bytes() {
+        [_, .., y, z] if y == z && !is_vowel(*z as char) && !b"lsfz".contains(z) => {
+            Some(&stem[..stem.len() - 1])
+        }
+        _ => None,
+    }
+}
+
+// consonant, vowel, consonant at the end, after a consonant or nothing:
+// "mak" of "making" lost an "e", "visit" of "visiting" didn't
+fn lost_e(stem: &str) -> bool {
+    let c: Vec<char> = stem.chars().collect();
+    match c.as_slice() {
+        [.., a, b, d] => {
+            let before = c.len() < 4 || !is_vowel(c[c.len() - 4]);
+            !is_vowel(*a) && is_vowel(*b) && !is_vowel(*d) && !"wxy".contains(*d) && before
+        }
+        _ => false,
+    }
+}
+
+// What `form` may be made of by the regular rules, the likely ones first.
+fn candidates(form: &str) -> Vec<(String, &'static str)> {
+    let mut res = vec![];
+    let mut push = |lemma: &str, kinds| {
+        if lemma.len() > 1 && !res.iter().any(|(l, _)| l == lemma) {
+            res.push((lemma.to_string(), kinds));
+        }
+    };
+    // "ing" and "ed" for verbs, "er" and "est" for adjectives
+    for (suffix, kinds) in [("ing", "i"), ("ed", "pd"), ("est", "t"), ("er", "r")] {
+        let Some(stem) = form.strip_suffix(suffix) else {
+            continue;
+        };
+        if let Some(s) = stem.strip_suffix('i').filter(|_| suffix != "ing") {
+            push(&format!("{}y", s), kinds);
+        }
+        if let Some(s) = stem.strip_suffix('y').filter(|_| suffix == "ing") {
+            push(&format!("{}ie", s), kinds);
+        }
+        if let Some(s) = undouble(stem) {
+            push(s, kinds);
+        }
+        match lost_e(stem) {
+            true => {
+                push(&format!("{}e", stem), kinds);
+                push(stem, kinds);
+            }
+            false => {
+                push(stem, kinds);
+                push(&format!("{}e", stem), kinds);
+            }
+        }
+    }
+    if let Some(stem) = form.strip_suffix("ies") {
+        push(&format!("{}y", stem), "s3");
+    }
+    if let Some(stem) = form.strip_suffix("es") {
+        if ["s", "x", "z", "ch", "sh", "o"]
+            .iter()
+            .any(|e| stem.ends_with(e))
+        {
+            push(stem, "s3");
+        }
+    }
+    if let Some(stem) = form.strip_suffix('s') {
+        if !["s", "u", "i"].iter().any(|e| stem.ends_with(e)) {
+            push(stem, "s3");
+        }
+    }
+    res
+}
+
+// "0:perceive/1:pd" of an ECDICT record, the lemma and the kinds
+fn exchange_lemma(exchange: &str) -> Option<(&str, &str)> {
+    let mut lemma = None;
+    let mut kinds = "";
+    for (k, v) in exchange.split('/').filter_map(|v| v.split_once(':')) {
+        match k {
+            "0" => lemma = Some(v),
+            "1" => kinds = v,
+            _ => {}
+        }
+    }
+    Some((lemma?, kinds))
+}
+
+// The lemma of `word` if it's an inflected form, None for a lemma or a
+// phrase. Only what ECDICT confirms: its exchange field, or the suffix rules
+// checked against it. The rest are guesses, see `irregular` and `guesses`.
+pub fn lemma(word: &str) -> Option<Lemma> {
+    let ecdict = Ecdict::new();
+    let get = |w: &str| ecdict.get(w).ok().flatten();
+    match ecdict.is_empty() {
+        true => lemma_in(word, None),
+        false => lemma_in(word, Some(&get)),
+    }
+}
+
+fn lemma_in(word: &str, dict: Option<Lookup>) -> Option<Lemma> {
+    let form = word.trim().to_lowercase();
+    if form.is_empty() || !form.chars().all(|c| c.is_ascii_alphabetic()) {
+        return None;
+    }
+
+    let get = dict?;
+    let record = get(&form);
+    if let Some(r) = &record {
+        if let Some((lemma, kinds)) = r.exchange.as_deref().and_then(exchange_lemma) {
+            return Some(Lemma::new(word.trim(), lemma, kinds));
+        }
+    }
+    // a word of its own, "news" isn't "new"
+    if record.is_some() || form.len() < 4 {
+        return None;
+    }
+
+    // unchecked, the rules make "need" of "ne" and "thing" of "th"
+    let (lemma, kinds) = candidates(&form)
+        .into_iter()
+        .find(|(c, _)| get(c).is_some())?;
+    Some(Lemma::new(word.trim(), &lemma, kinds))
+}
+
+// What the table says `word` is an irregular form of. Many of them are words
+// of their own, "found", "left", "people", so the caller only trusts this
+// when the engine knows little about `word`.
+pub fn irregular(word: &str) -> Option<Lemma> {
+    let form = word.trim().to_lowercase();
+    // "made" is both the past tense and the past participle
+    let (_, lemma, _) = irregular_forms().find(|(f, ..)| *f == form)?;
+    let kinds: String = irregular_forms()
+        .filter(|(f, l, _)| *f == form && *l == lemma)
+        .map(|(.., k)| k)
+        .collect();
+    Some(Lemma::new(word.trim(), lemma, &kinds))
+}
+
+// What the suffix rules make of `word` when there's no ECDICT to check them
+// against, the likely ones first. They're only guesses, the caller asks the
+// engine whether a lemma is a word.
+pub fn guesses(word: &str) -> Vec<Lemma> {
+    match Ecdict::new().is_empty() {
+        true => guess(word),
+        false => vec![],
+    }
+}
+
+fn guess(word: &str) -> Vec<Lemma> {
+    let form = word.trim().to_lowercase();
+    if form.len() < 4 || !form.chars().all(|c| c.is_ascii_alphabetic()) {
+        return vec![];
+    }
+    candidates(&form)
+        .into_iter()
+        // "er" and "est" are too often a part of the word, "water", "honest"
+        .filter(|(_, kinds)| !["r", "t"].contains(kinds))
+        .map(|(lemma, kinds)| Lemma::new(word.trim(), &lemma, kinds))
+        .collect()
+}
+
+// Every form the lemma may take in a sentence, the lemma included. The
+// regular ones are all guessed, a wrong guess never shows up in a sentence.
+pub fn inflections(lemma: &str) -> HashSet<String> {
+    let w = lemma.trim().to_lowercase();
+    let mut res: HashSet<String> = irregular_forms()
+        .filter(|(_, l, _)| *l == w)
+        .map(|(f, ..)| f.to_string())
+        .collect();
+    for suffix in ["s", "es", "ed", "d", "ing", "er", "est", "r", "st"] {
+        res.insert(format!("{}{}", w, suffix));
+    }
+    if let Some(s) = w.strip_suffix('y') {
+        for suffix in ["ies", "ied", "ier", "iest"] {
+            res.insert(format!("{}{}", s, suffix));
+        }
+    }
+    if let Some(s) = w.strip_suffix("ie") {
+        res.insert(format!("{}ying", s));
+    }
+    if let Some(s) = w.strip_suffix('e') {
+        res.insert(format!("{}ing", s));
+    }
+    if let Some(last) = w.chars().last().filter(|c| !is_vowel(*c)) {
+        for suffix in ["ed", "ing", "er", "est"] {
+            res.insert(format!("{}{}{}", w, last, suffix));
+        }
+    }
+    res.insert(w);
+    res
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::collections::HashMap;
+
+    fn lemma_of(word: &str) -> Option<(String, Option<String>)> {
+        lemma_in(word, None).map(|l| (l.lemma, l.kind))
+    }
+
+    #[test]
+    fn test_rules() {
+        let l = |w: &str| lemma_of(w).map(|(l, _)| l);
+        // the first guess of the rules
+        let g = |w: &str| guess(w).into_iter().next().map(|l| l.lemma);
+        for (form, lemma) in [
+            ("running", "run"),
+            ("making", "make"),
+            ("visiting", "visit"),
+            ("walked", "walk"),
+            ("stopped", "stop"),
+            ("hoped", "hope"),
+            ("studied", "study"),
+            ("dying", "die"),
+            ("watches", "watch"),
+            ("cities", "city"),
+            ("cats", "cat"),
+            ("falling", "fall"),
+        ] {
+            assert_eq!(g(form).as_deref(), Some(lemma), "{}", form);
+            // not without a dictionary to check them
+            assert_eq!(l(form), None, "{}", form);
+        }
+        let i = |w: &str| irregular(w).map(|l| l.lemma);
+        for (form, lemma) in [("mice", "mouse"), ("went", "go"), ("Better", "good")] {
+            // only guesses too, "found" may be a word of its own
+            assert_eq!(i(form).as_deref(), Some(lemma), "{}", form);
+            assert_eq!(l(form), None, "{}", form);
+        }
+        assert_eq!(i("run"), None);
+        for w in [
+            "run", "bus", "this", "water", "honest", "look up", "it's", "need", "thing", "bring",
+            "morning", "news",
+        ] {
+            assert_eq!(l(w), None, "{}", w);
+        }
+        for w in ["run", "bus", "water", "honest", "look up", "it's"] {
+            assert_eq!(g(w), None, "{}", w);
+        }
+        let kind = |w: &str| irregular(w).unwrap().kind;
+        assert_eq!(guess("running")[0].kind.as_deref(), Some("现在分词"));
+        assert_eq!(kind("gone").as_deref(), Some("过去分词"));
+        assert_eq!(kind("made").as_deref(), Some("过去式、过去分词"));
+        assert_eq!(kind("is").as_deref(), Some("第三人称单数"));
+        assert_eq!(kind("are"), None);
+    }
+
+    #[test]
+    fn test_dict() {
+        let record = |word: &str, exchange: Option<&str>| Record {
+            word: word.to_string(),
+            exchange: exchange.map(String::from),
+            ..Default::default()
+        };
+        let dict = HashMap::from([
+            ("perceived", record("perceived", Some("0:perceive/1:pd"))),
+            ("perceive", record("perceive", None)),
+            ("bigger", record("bigger", None)),
+            ("big", record("big", None)),
+            ("news", record("news", None)),
+            ("honest", record("honest", None)),
+            ("tall", record("tall", None)),
+            ("found", record("found", Some("p:founded/d:founded"))),
+            ("went", record("went", Some("0:go/1:p"))),
+        ]);
+        let get = |w: &str| dict.get(w).cloned();
+        let l = |w: &str| lemma_in(w, Some(&get));
+
+        assert_eq!(
+            l("perceived").unwrap(),
+            Lemma {
+                form: "perceived".into(),
+                lemma: "perceive".into(),
+                kind: Some("过去式、过去分词".into()),
+            }
+        );
+        // in the dictionary, but with no exchange
+        assert_eq!(l("bigger"), None);
+        assert_eq!(l("news"), None);
+        assert_eq!(l("honest"), None);
+        assert_eq!(l("tallest").unwrap().lemma, "tall");
+        assert_eq!(l("tallest").unwrap().kind.as_deref(), Some("最高级"));
+        // not a word
+        assert_eq!(l("perceivings"), None);
+        // irregular forms count only if ECDICT says so
+        assert_eq!(l("found"), None);
+        assert_eq!(l("went").unwrap().lemma, "go");
+    }
+
+    #[test]
+    fn test_inflections() {
+        let run = inflections("run");
+        for f in ["run", "runs", "ran", "running"] {
+            assert!(run.contains(f), "{}", f);
+        }
+        let study = inflections("study");
+        for f in ["studies", "studied", "studying"] {
+            assert!(study.contains(f), "{}", f);
+        }
+        assert!(inflections("make").contains("making"));
+        assert!(inflections("die").contains("dying"));
+        assert!(inflections("mouse").contains("mice"));
+        assert!(!inflections("run").contains("rune"));
+    }
+}
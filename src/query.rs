@@ -1,54 +1,387 @@
 use std::io::BufReader;
-use std::{fmt, ops::Deref, sync::mpsc, thread, time};
+use std::{fmt, sync::mpsc, thread, time};
 
 use indicatif::{ProgressBar, ProgressStyle};
 use rodio::{source::Source, Decoder, OutputStream};
 
-use crate::handler::{youdao, AudioType, Engines, VocabBody};
-use crate::result::Result;
+use crate::cache::{self, Cache, Policy, AUDIO_TREE};
+use crate::config;
+use crate::handler::schema::{Document, Entry};
+use crate::handler::{AudioType, Dictionary, Engines, VocabBody, DEFAULT_SECTIONS};
+use crate::history::History;
+use crate::morph::{self, Lemma};
+use crate::result::{Error, Result};
 use crate::util::{self, ColorfulRole as Role, Style};
 
-#[allow(dead_code)]
 pub struct QueryTarget {
-    pub engine: Engines,
+    pub engine: Box<dyn Dictionary>,
     pub phrase: String,
     pub vocabulary: Option<VocabBody>,
+    pub error: Option<Error>,
     raw: Option<Vec<u8>>,
     audio_uk: Option<Vec<u8>>,
     audio_us: Option<Vec<u8>>,
+    // the database of the config if None, tests use a temporary one
+    db: Option<sled::Db>,
+    // what the text output shows, set by Aggregate from --sections
+    sections: Vec<String>,
 }
 
-pub struct History(Vec<String>);
+// The same phrase looked up from several engines.
+pub struct Aggregate {
+    targets: Vec<QueryTarget>,
+    mode: Mode,
+    // index of the target which answered in fallback mode
+    answered: Option<usize>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Mode {
+    // all the engines at once, one section for each
+    All,
+    // engines in order, the first one with a result wins
+    Fallback,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    Text,
+    // pretty printed, see handler::schema
+    Json,
+    // one compact document per line
+    Jsonl,
+    // a header, then a row for each engine, see batch
+    Csv,
+}
+
+// engines of a plain `rdict <phrase>`: the fallback chain of the config, or its engines
+pub fn default_engines() -> (Vec<String>, Mode) {
+    let cfg = config::get();
+    match &cfg.fallback.value {
+        Some(f) => (f.clone(), Mode::Fallback),
+        None => (cfg.engines.value.clone(), Mode::All),
+    }
+}
 
 impl QueryTarget {
-    pub fn new(phrase: String, engine: Engines) -> Self {
+    pub fn new(phrase: String, engine: Box<dyn Dictionary>) -> Self {
         QueryTarget {
             phrase,
             engine,
             vocabulary: None,
+            error: None,
             raw: None,
             audio_uk: None,
             audio_us: None,
+            db: None,
+            sections: DEFAULT_SECTIONS.iter().map(|s| s.to_string()).collect(),
+        }
+    }
+
+    #[cfg(test)]
+    fn with_db(mut self, db: &sled::Db) -> Self {
+        self.db = Some(db.clone());
+        self
+    }
+
+    pub fn query_meaning(&mut self) -> Result<&Self> {
+        if !self.query_cache()? {
+            let raw = self.engine.fetch(&self.phrase)?;
+            self.vocabulary = Some(self.engine.parse(&raw)?);
+            self.raw = Some(raw);
+            self.lemmatize(true);
+        }
+
+        Ok(self)
+    }
+
+    // only looks into the cache, true if it's there
+    pub fn query_cache(&mut self) -> Result<bool> {
+        match self.read_cache(&self.phrase)? {
+            Some(raw) => {
+                self.vocabulary = Some(self.engine.parse(&raw)?);
+                self.raw = Some(raw);
+                self.lemmatize(false);
+                Ok(true)
+            }
+            None => Ok(false),
         }
     }
 
-    pub fn query_meaning(&mut self) -> &Self {
-        // unwrap errors here
-        self.raw = self
-            .from_cache(&self.phrase)
-            .unwrap()
-            .or_else(|| self.engine.request_meaning(&self.phrase).ok());
+    // Tells where an inflected form comes from. "mice" may have no meanings
+    // of its own, then the lemma is fetched too if `fetch`, and its answer
+    // is cached under the form. Without ECDICT the lemma is guessed, and the
+    // engine is asked whether the guess is right.
+    fn lemmatize(&mut self, fetch: bool) {
+        let thin = self.vocabulary.as_ref().is_none_or(|v| !v.has_explains());
+        let lemma = match morph::lemma(&self.phrase) {
+            Some(l) => {
+                let answer = (fetch && thin)
+                    .then(|| self.answer_of(&l.lemma, fetch))
+                    .flatten()
+                    .filter(|(_, v)| v.has_explains());
+                if let Some(answer) = answer {
+                    (self.raw, self.vocabulary) = (Some(answer.0), Some(answer.1));
+                }
+                l
+            }
+            None => match self.guess_lemma(fetch, thin) {
+                Some(l) => l,
+                None => return,
+            },
+        };
+        if let Some(v) = &mut self.vocabulary {
+            v.set_lemma(Some(lemma));
+        }
+    }
 
+    // A guess the engine agrees with. With no meanings of the form any lemma
+    // it knows will do, and the lemma's answer takes the place of the form's.
+    // Otherwise the lemma has to list the form, "run" lists "running". Without
+    // `fetch` only the cache is asked, so a cached answer shows the same.
+    fn guess_lemma(&mut self, fetch: bool, thin: bool) -> Option<Lemma> {
+        let guesses = || {
+            morph::irregular(&self.phrase)
+                .into_iter()
+                .chain(morph::guesses(&self.phrase))
+        };
+        // the lemma's answer cached under the form, the guess was taken before
+        let answered = self.vocabulary.as_ref().map(|v| v.phrase().to_string());
+        if let Some(a) = answered.filter(|a| !a.eq_ignore_ascii_case(&self.phrase)) {
+            return guesses().find(|l| l.lemma.eq_ignore_ascii_case(&a));
+        }
+        if thin {
+            let (l, answer) = guesses().find_map(|l| {
+                let answer = self.answer_of(&l.lemma, fetch)?;
+                answer.1.has_explains().then_some((l, answer))
+            })?;
+            (self.raw, self.vocabulary) = (Some(answer.0), Some(answer.1));
+            return Some(l);
+        }
+        morph::guesses(&self.phrase).into_iter().find(|l| {
+            self.answer_of(&l.lemma, fetch)
+                .is_some_and(|(_, v)| v.has_form(&self.phrase))
+        })
+    }
+
+    // The answer of the engine for `phrase`, from the cache or, if `fetch`,
+    // the engine. One with meanings is cached, so that the lemma is cheap to
+    // check the next time.
+    fn answer_of(&self, phrase: &str, fetch: bool) -> Option<(Vec<u8>, VocabBody)> {
+        if let Some(raw) = self.read_cache(phrase).ok().flatten() {
+            let vb = self.engine.parse(&raw).ok()?;
+            return Some((raw, vb));
+        }
+        if !fetch {
+            return None;
+        }
+        let raw = self.engine.fetch(phrase).ok()?;
+        let vb = self.engine.parse(&raw).ok()?;
+        if vb.has_explains() && self.engine.capabilities().online {
+            let _ = self
+                .cache()
+                .and_then(|c| c.insert(phrase, self.engine.name(), &raw));
+        }
+        Some((raw, vb))
+    }
+
+    fn is_answered(&self) -> bool {
+        self.error.is_none() && self.vocabulary.as_ref().is_some_and(|v| !v.is_empty())
+    }
+
+    pub fn play_audio(&mut self, t: AudioType) -> Result<()> {
+        let data = self.query_audio(t)?;
+
+        let cs = std::io::Cursor::new(data);
+
+        let source = Decoder::new(cs.to_owned())?;
+
+        let mut buf = BufReader::new(cs);
+        let dura = mp3_duration::from_read(&mut buf)?;
+
+        let (_stream, stream_handler) = OutputStream::try_default()?;
+        stream_handler.play_raw(source.convert_samples())?;
+
+        thread::sleep(dura);
+
+        Ok(())
+    }
+
+    fn query_audio(&mut self, t: AudioType) -> Result<Vec<u8>> {
+        if !self.engine.capabilities().audio {
+            return Err(Error::Engine(self.engine.name(), "audio is not supported"));
+        }
+        let cache = self.open_cache(AUDIO_TREE)?;
+        let slot = match t {
+            AudioType::UK => &mut self.audio_uk,
+            AudioType::US => &mut self.audio_us,
+        };
+        if let Some(v) = slot {
+            return Ok(v.clone());
+        }
+
+        let key = audio_key(self.engine.name(), &self.phrase, &t);
+        let data = match cache.get(&key)? {
+            Some(v) => v,
+            None => {
+                let v = self.engine.fetch_audio(&self.phrase, t)?;
+                cache.insert(&key, self.engine.name(), &v)?;
+                v
+            }
+        };
+        *slot = Some(data.clone());
+
+        Ok(data)
+    }
+
+    // downloads both accents, so that `-v` works offline later
+    pub fn prefetch_audio(&mut self) -> Result<()> {
+        self.query_audio(AudioType::UK)?;
+        self.query_audio(AudioType::US)?;
+        Ok(())
+    }
+
+    pub fn save(&self) -> Result<&Self> {
+        // local engines are fast enough, no need to cache them
+        if !self.engine.capabilities().online {
+            return Ok(self);
+        }
+        if let Some(raw) = &self.raw {
+            self.cache()?
+                .insert(&self.phrase, self.engine.name(), raw)?;
+        }
+
+        Ok(self)
+    }
+
+    fn read_cache(&self, key: &str) -> Result<Option<Vec<u8>>> {
+        // nothing is saved for the local engines
+        if !self.engine.capabilities().online {
+            return Ok(None);
+        }
+        self.cache()?.get(key)
+    }
+
+    // each engine keeps its raw responses in its own tree, so that the same
+    // phrase looked up from different engines never collides.
+    fn cache(&self) -> Result<Cache> {
+        self.open_cache(self.engine.name())
+    }
+
+    fn open_cache(&self, name: &str) -> Result<Cache> {
+        match &self.db {
+            Some(db) => Cache::with_db(db, name, Policy::from_config()),
+            None => Cache::open(name),
+        }
+    }
+}
+
+// audio of all the engines share a tree, keyed by "engine/phrase/accent"
+fn audio_key(engine: &str, phrase: &str, t: &AudioType) -> String {
+    let accent = match t {
+        AudioType::UK => "uk",
+        AudioType::US => "us",
+    };
+    format!("{}/{}/{}", engine, phrase, accent)
+}
+
+// audio some engine has downloaded before, the network isn't touched
+pub fn cached_audio(phrase: &str, t: AudioType) -> Result<Option<Vec<u8>>> {
+    let cache = Cache::open(AUDIO_TREE)?;
+    for name in Engines::names() {
+        if let Some(v) = cache.get(&audio_key(name, phrase, &t))? {
+            return Ok(Some(v));
+        }
+    }
+    Ok(None)
+}
+
+impl fmt::Display for QueryTarget {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(e) = &self.error {
+            return writeln!(f, "{}{}", ' '.align_right(4), e.coloring(Role::Wip));
+        }
+        match &self.vocabulary {
+            Some(v) => v.fmt_sections(f, &self.sections),
+            None => Ok(()),
+        }
+    }
+}
+
+impl Aggregate {
+    pub fn new(phrase: String, engines: Vec<Box<dyn Dictionary>>, mode: Mode) -> Self {
+        let sections = &config::get().sections.value;
+        Aggregate {
+            targets: engines
+                .into_iter()
+                .map(|e| QueryTarget {
+                    sections: sections.clone(),
+                    ..QueryTarget::new(phrase.clone(), e)
+                })
+                .collect(),
+            mode,
+            answered: None,
+        }
+    }
+
+    // with the engines of a plain `rdict <phrase>`
+    pub fn from_config(phrase: String) -> Result<Self> {
+        let (names, mode) = default_engines();
+        let engines = names
+            .iter()
+            .map(|n| Engines::get(n))
+            .collect::<Result<_>>()?;
+        Ok(Aggregate::new(phrase, engines, mode))
+    }
+
+    pub fn query(&mut self) -> &mut Self {
+        match self.mode {
+            Mode::All => self.query_all(),
+            Mode::Fallback => self.query_fallback(),
+        }
+    }
+
+    // Engines are queried concurrently, a failed one keeps its error and
+    // never stops the others.
+    fn query_all(&mut self) -> &mut Self {
+        thread::scope(|s| {
+            for t in self.targets.iter_mut() {
+                s.spawn(move || {
+                    if let Err(e) = t.query_meaning() {
+                        t.error = Some(e);
+                    }
+                });
+            }
+        });
         self
     }
 
-    pub fn query_with_pb(&mut self) -> &Self {
-        let (tx, rx) = mpsc::channel();
+    // Cached answers of all the engines go first, since they need no
+    // network. Then the engines are asked one by one.
+    fn query_fallback(&mut self) -> &mut Self {
+        for (i, t) in self.targets.iter_mut().enumerate() {
+            if let Ok(true) = t.query_cache() {
+                if t.is_answered() {
+                    self.answered = Some(i);
+                    return self;
+                }
+            }
+        }
 
-        self.query_meaning();
-        if self.raw.is_some() {
-            tx.send(1).unwrap();
+        for (i, t) in self.targets.iter_mut().enumerate() {
+            if let Err(e) = t.query_meaning() {
+                t.error = Some(e);
+            }
+            if t.is_answered() {
+                self.answered = Some(i);
+                break;
+            }
         }
+        self
+    }
+
+    pub fn query_with_pb(&mut self) -> &mut Self {
+        let (tx, rx) = mpsc::channel();
 
         // let's spin
         let jh = thread::spawn(move || {
@@ -69,11 +402,14 @@ impl QueryTarget {
                     }
                     Err(_) => {
                         bar.inc(1);
-                        thread::sleep(time::Duration::from_micros(1));
+                        thread::sleep(time::Duration::from_millis(3));
                     }
                 }
             }
         });
+
+        self.query();
+        tx.send(1).unwrap();
         jh.join().unwrap();
 
         return self;
@@ -91,145 +427,467 @@ impl QueryTarget {
         }
     }
 
-    pub fn play_audio(&mut self, t: AudioType) -> Result<()> {
-        let data = self.query_audio(t)?;
-
-        let cs = std::io::Cursor::new(data.unwrap());
-
-        let source = Decoder::new(cs.to_owned())?;
-
-        let mut buf = BufReader::new(cs);
-        let dura = mp3_duration::from_read(&mut buf)?;
+    pub fn save(&self) -> Result<&Self> {
+        self.store()?;
+        cache::auto_prune(&util::open_db()?, Policy::from_config())?;
+        Ok(self)
+    }
 
-        let (_stream, stream_handler) = OutputStream::try_default()?;
-        stream_handler.play_raw(source.convert_samples())?;
+    // caches the answers and records the history, without pruning the
+    // cache, which a batch does once at the end
+    pub fn store(&self) -> Result<&Self> {
+        self.keep()?;
+
+        let answered = self
+            .targets
+            .iter()
+            .filter(|t| t.is_answered())
+            .map(|t| t.engine.name())
+            .collect::<Vec<_>>();
+        if let (Some(t), false) = (self.targets.first(), answered.is_empty()) {
+            History::record(&t.phrase, &answered.join(","))?;
+        }
+        Ok(self)
+    }
 
-        thread::sleep(dura);
+    // only caches the answers, for lookups the user didn't ask for
+    pub fn keep(&self) -> Result<&Self> {
+        for t in self.targets.iter().filter(|t| t.error.is_none()) {
+            t.save()?;
+        }
+        Ok(self)
+    }
 
-        Ok(())
+    // plays with the engine answered, or the first one which has audio
+    pub fn play_audio(&mut self, t: AudioType) -> Result<()> {
+        self.audio_target()?.play_audio(t)
     }
 
-    fn query_audio(&mut self, t: AudioType) -> Result<Option<Vec<u8>>> {
-        let v: &str = t.to_owned().into();
-        let key = format!("{}_{}", &self.phrase, v);
+    pub fn prefetch_audio(&mut self) -> Result<()> {
+        self.audio_target()?.prefetch_audio()
+    }
 
-        let source = self
-            .from_cache(key.as_str())
-            .unwrap()
-            .or_else(|| self.engine.request_audio(&self.phrase, t).ok());
+    // A word added to the book has its audio downloaded too, so that review
+    // and the anki export work offline. Nothing to do if no engine has audio,
+    // or `book_audio` is off.
+    pub fn prefetch_for_book(&mut self) -> Result<()> {
+        let audio = self.targets.iter().any(|t| t.engine.capabilities().audio);
+        if !config::get().book_audio.value || !audio || self.answer().is_none() {
+            return Ok(());
+        }
+        self.prefetch_audio()
+    }
 
-        Ok(source)
+    fn audio_target(&mut self) -> Result<&mut QueryTarget> {
+        let answered = self.answered;
+        self.targets
+            .iter_mut()
+            .enumerate()
+            .filter(|(i, _)| answered.is_none() || answered == Some(*i))
+            .map(|(_, v)| v)
+            .find(|v| v.engine.capabilities().audio)
+            .ok_or(Error::Engine("all", "audio is not supported"))
     }
+}
 
-    pub fn save(&self) -> Result<&Self> {
-        let db = util::open_db()?;
-        //TODO: rename audio files by appending suffix like x_us.mp3
-        db.insert(self.phrase.as_str(), self.raw.clone().unwrap())?;
+impl Aggregate {
+    pub fn phrase(&self) -> &str {
+        self.targets.first().map_or("", |t| t.phrase.as_str())
+    }
 
-        Ok(self)
+    // the engine which answered and what it said, the first one in all mode
+    pub fn answer(&self) -> Option<(&'static str, &VocabBody)> {
+        let t = match self.answered {
+            Some(i) => &self.targets[i],
+            None => self.targets.iter().find(|t| t.is_answered())?,
+        };
+        Some((t.engine.name(), t.vocabulary.as_ref()?))
     }
 
-    fn from_cache(&self, key: &str) -> Result<Option<Vec<u8>>> {
-        let raw = util::open_db()?.get(key).ok().unwrap_or(None);
-        Ok(raw.map(|ivec| ivec.deref().to_vec()))
+    // the same targets as the text output shows
+    pub fn to_document(&self) -> Document {
+        let targets = match self.answered {
+            Some(i) => std::slice::from_ref(&self.targets[i]),
+            None => self.targets.as_slice(),
+        };
+        Document::new(
+            self.phrase(),
+            targets
+                .iter()
+                .map(|t| {
+                    Entry::new(
+                        t.engine.name(),
+                        &t.phrase,
+                        t.vocabulary.as_ref(),
+                        t.error.as_ref(),
+                    )
+                })
+                .collect(),
+        )
     }
 }
 
-impl fmt::Display for QueryTarget {
+impl fmt::Display for Aggregate {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        match self.engine {
-            Engines::Bing => todo!(),
-            Engines::Youdao => {
-                let v = self.raw.as_ref().unwrap();
-                let data: youdao::YoudaoRes = serde_json::from_slice(v).unwrap();
-                let vb = VocabBody::from(data);
-                write!(f, "{}", vb)?;
+        let header = |f: &mut fmt::Formatter<'_>, t: &QueryTarget| {
+            writeln!(
+                f,
+                "{s}{name}",
+                s = ' '.align_right(2),
+                name = format!("[{}]", t.engine.name()).coloring(Role::Logo),
+            )
+        };
+
+        if let Some(i) = self.answered {
+            let t = &self.targets[i];
+            if self.targets.len() > 1 {
+                header(f, t)?;
             }
+            return write!(f, "{}", t);
         }
-        if let Some(v) = &self.vocabulary {
-            println!("{:?}", v);
+
+        if let [t] = self.targets.as_slice() {
+            return write!(f, "{}", t);
         }
 
+        // nobody answered in fallback mode, show why
+        for t in self.targets.iter() {
+            header(f, t)?;
+            write!(f, "{}", t)?;
+            writeln!(f)?;
+        }
         Ok(())
     }
 }
 
-impl History {
-    pub fn getn(length: usize) -> Self {
-        let mut res: Vec<String> = Vec::new();
-        if let Ok(db) = util::open_db() {
-            let mut ivecs: Vec<sled::IVec> = vec![];
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::handler::{Capabilities, Engines};
+    use serial_test::serial;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
 
-            for (i, key) in db.iter().keys().enumerate() {
-                if i == length {
-                    break;
-                }
-                if let Ok(v) = key {
-                    ivecs.push(v)
-                }
+    #[test]
+    #[serial]
+    fn test_get_cache() {
+        let target = QueryTarget::new("x".to_string(), Engines::get("youdao").unwrap());
+        let c = target.read_cache(&target.phrase).unwrap();
+        assert!(c.is_some());
+    }
+
+    // an offline engine which is either up or down, answers with a saved youdao response
+    struct Fake(&'static str, bool);
+
+    impl Dictionary for Fake {
+        fn name(&self) -> &'static str {
+            self.0
+        }
+
+        fn capabilities(&self) -> Capabilities {
+            Capabilities {
+                online: false,
+                audio: true,
             }
+        }
 
-            res = ivecs
-                .into_iter()
-                .filter_map(|v| String::from_utf8(v.as_ref().to_vec()).ok())
-                .collect();
+        fn fetch_audio(&self, phrase: &str, t: AudioType) -> Result<Vec<u8>> {
+            match self.1 {
+                true => Ok(format!("{}_{:?}", phrase, t).into_bytes()),
+                false => Err(Error::Engine(self.0, "down")),
+            }
+        }
+
+        fn fetch(&self, phrase: &str) -> Result<Vec<u8>> {
+            match self.1 {
+                true => Ok(std::fs::read(format!(
+                    "tests/fixtures/youdao/{}.json",
+                    phrase
+                ))?),
+                false => Err(Error::Engine(self.0, "down")),
+            }
+        }
+
+        fn parse(&self, raw: &[u8]) -> Result<VocabBody> {
+            Engines::get("youdao")?.parse(raw)
         }
-        res.into()
     }
-}
 
-impl fmt::Display for History {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        writeln!(f)?;
-        for (k, v) in self.0.iter().enumerate() {
-            writeln!(
-                f,
-                "{s}{index}{dot}{value}",
-                s = " ".repeat(4),
-                index = (k + 1).align_right(2).coloring(Role::Index),
-                dot = ".".align_left(2).coloring(Role::Dot),
-                value = v.coloring(Role::Content),
-            )?;
+    // so that the fake engines never touch the real cache
+    fn temporary(res: &mut Aggregate) -> sled::Db {
+        let db = sled::Config::new().temporary(true).open().unwrap();
+        for t in res.targets.iter_mut() {
+            t.db = Some(db.clone());
         }
-        Ok(())
+        db
     }
-}
 
-impl From<Vec<String>> for History {
-    fn from(s: Vec<String>) -> Self {
-        History(s)
+    #[test]
+    #[serial]
+    fn test_aggregate() {
+        let mut res = Aggregate::new(
+            "hello".to_string(),
+            vec![
+                Box::new(Fake("fake_up", true)),
+                Box::new(Fake("fake_down", false)),
+            ],
+            Mode::All,
+        );
+        temporary(&mut res);
+        res.query();
+
+        assert!(res.targets[0].error.is_none());
+        assert!(res.targets[0].vocabulary.is_some());
+        assert!(res.targets[1].error.is_some());
+        assert!(res.targets[1].vocabulary.is_none());
+
+        let out = res.to_string();
+        assert!(out.contains("[fake_up]"));
+        assert!(out.contains("down"));
     }
-}
 
-#[cfg(test)]
-mod test {
-    use super::*;
-    use serial_test::serial;
+    #[test]
+    #[serial]
+    fn test_fallback() {
+        let mut res = Aggregate::new(
+            "hello".to_string(),
+            vec![
+                Box::new(Fake("fake_down", false)),
+                Box::new(Fake("fake_up", true)),
+                Box::new(Fake("fake_never", true)),
+            ],
+            Mode::Fallback,
+        );
+        temporary(&mut res);
+        res.query();
+
+        assert_eq!(res.answered, Some(1));
+        assert!(res.targets[0].error.is_some());
+        // the chain stops at the first answer
+        assert!(res.targets[2].vocabulary.is_none());
+
+        let out = res.to_string();
+        assert!(out.contains("[fake_up]"));
+        assert!(!out.contains("[fake_down]"));
+
+        let doc = res.to_document();
+        assert_eq!(doc.results.len(), 1);
+        assert_eq!(doc.results[0].engine, "fake_up");
+        assert_eq!(res.answer().map(|(e, _)| e), Some("fake_up"));
+        assert_eq!(res.phrase(), "hello");
+    }
+
+    // knows the phrase given only, with the meanings of hello
+    struct Only(&'static str);
+
+    impl Dictionary for Only {
+        fn name(&self) -> &'static str {
+            "only"
+        }
+
+        fn capabilities(&self) -> Capabilities {
+            Capabilities {
+                online: false,
+                audio: false,
+            }
+        }
+
+        fn fetch(&self, phrase: &str) -> Result<Vec<u8>> {
+            Ok(phrase.as_bytes().to_vec())
+        }
+
+        fn parse(&self, raw: &[u8]) -> Result<VocabBody> {
+            match raw == self.0.as_bytes() {
+                true => Engines::get("youdao")?
+                    .parse(&std::fs::read("tests/fixtures/youdao/hello.json")?),
+                false => Ok(VocabBody::new(String::from_utf8_lossy(raw).into_owned())),
+            }
+        }
+    }
 
     #[test]
     #[serial]
-    fn test_get_cache() {
-        let target = QueryTarget::new("x".to_string(), Engines::from("youdao".to_string()));
-        let c = target.from_cache(&target.phrase).unwrap();
-        assert!(c.is_some());
+    fn test_lemma() {
+        let db = sled::Config::new().temporary(true).open().unwrap();
+        // nothing for "mice", so "mouse" is asked too
+        let mut t = QueryTarget::new("mice".to_string(), Box::new(Only("mouse"))).with_db(&db);
+        t.query_meaning().unwrap();
+        assert!(t.is_answered());
+        assert_eq!(t.raw.as_deref(), Some(&b"mouse"[..]));
+        let doc = Entry::new("only", "mice", t.vocabulary.as_ref(), None);
+        let lemma = doc.lemma.unwrap();
+        assert_eq!(
+            (lemma.form.as_str(), lemma.lemma.as_str()),
+            ("mice", "mouse")
+        );
+        assert_eq!(lemma.kind.as_deref(), Some("复数"));
+        assert!(t.to_string().contains("(复数)"));
+
+        // a form with meanings of its own keeps them
+        let mut t = QueryTarget::new("mice".to_string(), Box::new(Only("mice"))).with_db(&db);
+        t.query_meaning().unwrap();
+        assert_eq!(t.raw.as_deref(), Some(&b"mice"[..]));
+        assert!(t.vocabulary.unwrap().has_explains());
+
+        // "found" is a word too, not only the past tense of "find"
+        let mut t = QueryTarget::new("found".to_string(), Box::new(Only("found"))).with_db(&db);
+        t.query_meaning().unwrap();
+        let doc = Entry::new("only", "found", t.vocabulary.as_ref(), None);
+        assert!(doc.lemma.is_none());
+    }
+
+    // an online engine with a few ECDICT records, and how many times it's asked
+    struct Inflected(Arc<AtomicUsize>);
+
+    impl Dictionary for Inflected {
+        fn name(&self) -> &'static str {
+            "fake_inflected"
+        }
+
+        fn capabilities(&self) -> Capabilities {
+            Capabilities {
+                online: true,
+                audio: false,
+            }
+        }
+
+        fn fetch(&self, phrase: &str) -> Result<Vec<u8>> {
+            self.0.fetch_add(1, Ordering::SeqCst);
+            let record = match phrase {
+                "running" => Some(("n. 跑步", "")),
+                "run" => Some(("v. 跑", "p:ran/d:run/i:running/3:runs")),
+                "news" => Some(("n. 新闻", "")),
+                "new" => Some(("adj. 新的", "r:newer/t:newest")),
+                "mouse" => Some(("n. 老鼠", "s:mice")),
+                _ => None,
+            };
+            let record = record.map(|(translation, exchange)| {
+                serde_json::json!({"word": phrase, "translation": translation, "exchange": exchange})
+            });
+            Ok(serde_json::to_vec(
+                &serde_json::json!({"phrase": phrase, "record": record}),
+            )?)
+        }
+
+        fn parse(&self, raw: &[u8]) -> Result<VocabBody> {
+            Engines::get("ecdict")?.parse(raw)
+        }
+    }
+
+    fn inflected(phrase: &str, db: &sled::Db) -> (QueryTarget, Arc<AtomicUsize>) {
+        let fetched = Arc::new(AtomicUsize::new(0));
+        let engine = Inflected(fetched.clone());
+        let t = QueryTarget::new(phrase.to_string(), Box::new(engine)).with_db(db);
+        (t, fetched)
     }
 
     #[test]
     #[serial]
-    fn test_history() {
-        let h = History::getn(0);
-        assert_eq!(h.0.len(), 0);
+    fn test_guess_lemma() {
+        // no ECDICT, and "running" has meanings of its own
+        let db = sled::Config::new().temporary(true).open().unwrap();
+        let (mut t, _) = inflected("running", &db);
+        t.query_meaning().unwrap();
+        let doc = Entry::new("fake_inflected", "running", t.vocabulary.as_ref(), None);
+        let lemma = doc.lemma.unwrap();
+        assert_eq!(lemma.lemma, "run");
+        assert_eq!(lemma.kind.as_deref(), Some("现在分词"));
+        assert_eq!(doc.explains[0].meaning, "跑步");
+        assert!(t.to_string().contains("(现在分词)"));
+
+        // "new" doesn't list "news"
+        let (mut t, _) = inflected("news", &db);
+        t.query_meaning().unwrap();
+        let doc = Entry::new("fake_inflected", "news", t.vocabulary.as_ref(), None);
+        assert!(doc.lemma.is_none());
+    }
+
+    #[test]
+    #[serial]
+    fn test_lemma_cached() {
+        let db = sled::Config::new().temporary(true).open().unwrap();
+        // with meanings of its own, and without, then "mouse" is cached as "mice"
+        for phrase in ["running", "mice"] {
+            let (mut first, _) = inflected(phrase, &db);
+            first.query_meaning().unwrap().save().unwrap();
+            let (mut second, fetched) = inflected(phrase, &db);
+            second.query_meaning().unwrap();
+
+            assert_eq!(fetched.load(Ordering::SeqCst), 0, "{}", phrase);
+            assert!(second.vocabulary.as_ref().unwrap().has_explains());
+            let lemma = |t: &QueryTarget| {
+                Entry::new("fake_inflected", phrase, t.vocabulary.as_ref(), None).lemma
+            };
+            assert!(lemma(&second).is_some(), "{}", phrase);
+            assert_eq!(lemma(&first), lemma(&second));
+            assert_eq!(first.to_string(), second.to_string());
+        }
+    }
+
+    #[test]
+    #[serial]
+    fn test_audio_cache() {
+        let db = sled::Config::new().temporary(true).open().unwrap();
+        let fake = |up| QueryTarget::new("hello".to_string(), Box::new(Fake("fake_audio", up)));
+        let mut up = fake(true).with_db(&db);
+        up.prefetch_audio().unwrap();
+
+        // the engine is down now, but both accents have been downloaded
+        let mut down = fake(false).with_db(&db);
+        assert_eq!(down.query_audio(AudioType::UK).unwrap(), b"hello_UK");
+        assert_eq!(down.query_audio(AudioType::US).unwrap(), b"hello_US");
+
+        let tree = db.open_tree(AUDIO_TREE).unwrap();
+        for t in [AudioType::UK, AudioType::US] {
+            tree.remove(audio_key("fake_audio", "hello", &t)).unwrap();
+        }
+        assert!(down.query_audio(AudioType::UK).is_ok());
+        let mut down = fake(false).with_db(&db);
+        assert!(down.query_audio(AudioType::UK).is_err());
+    }
+
+    #[test]
+    #[serial]
+    fn test_sections() {
+        let db = sled::Config::new().temporary(true).open().unwrap();
+        let mut t =
+            QueryTarget::new("hello".to_string(), Box::new(Fake("fake_up", true))).with_db(&db);
+        t.query_meaning().unwrap();
+        assert!(!t.to_string().contains("同近义词"));
+        t.sections = vec!["explains".to_string(), "synonyms".to_string()];
+        let out = t.to_string();
+        assert!(out.contains("同近义词") && !out.contains("音标"));
+        // the word itself always shows the default ones
+        let vb = t.vocabulary.as_ref().unwrap().to_string();
+        assert!(vb.contains("音标") && !vb.contains("同近义词"));
+    }
+
+    #[test]
+    #[serial]
+    fn test_prefetch_offline() {
+        // none of the engines has audio, an error to report instead of a panic
+        let mut res = Aggregate::new(
+            "hello".to_string(),
+            vec![Box::new(Only("hello"))],
+            Mode::All,
+        );
+        temporary(&mut res);
+        res.query();
+        assert!(res.answer().is_some());
+        assert!(matches!(
+            res.prefetch_audio(),
+            Err(Error::Engine(_, "audio is not supported"))
+        ));
+        assert!(res.prefetch_for_book().is_ok());
     }
 
     #[test]
     #[serial]
     fn test_audio() {
-        let mut target = QueryTarget::new("hello".to_string(), Engines::from("youdao".to_string()));
+        let mut target = QueryTarget::new("hello".to_string(), Engines::get("youdao").unwrap());
 
         let audio = target.query_audio(AudioType::US).unwrap();
-        assert!(audio.is_some());
-
-        let audio = std::io::Cursor::new(audio.unwrap());
+        let audio = std::io::Cursor::new(audio);
 
         let mut deco = Decoder::new(audio).unwrap();
         assert!(deco.any(|x| x != 0));
@@ -0,0 +1,307 @@
+use std::fmt;
+
+use rand::seq::SliceRandom;
+use rand::Rng;
+
+use crate::book::{Book, Word};
+use crate::config;
+use crate::handler::{schema::Entry, AudioType};
+use crate::query::Aggregate;
+use crate::result::Result;
+use crate::review::{ask, say};
+use crate::util::{self, ColorfulRole as Role, Style};
+
+// how many meanings a multiple choice question offers
+const CHOICES: usize = 4;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Kind {
+    // hear the word, spell it
+    Dictation,
+    // read a meaning, type the word
+    Meaning,
+    // read the word, pick its meaning
+    Choice,
+}
+
+pub struct QuizOptions {
+    pub kind: Kind,
+    pub count: usize,
+    pub tag: Option<String>,
+    // grade the review schedule with the results
+    pub schedule: bool,
+}
+
+#[derive(Debug)]
+struct Question {
+    phrase: String,
+    // the meaning to translate, or the word to explain
+    prompt: String,
+    choices: Vec<String>,
+    // index of the right choice
+    answer: usize,
+}
+
+#[derive(Debug, Default)]
+pub struct Report {
+    pub total: usize,
+    pub correct: usize,
+    // the phrase, and what was typed, its meaning or the right choice
+    pub missed: Vec<(String, String)>,
+}
+
+impl Question {
+    fn check(&self, kind: Kind, input: &str) -> bool {
+        match kind {
+            Kind::Dictation | Kind::Meaning => input.trim().eq_ignore_ascii_case(&self.phrase),
+            Kind::Choice => input.trim().parse::<usize>() == Ok(self.answer + 1),
+        }
+    }
+
+    fn solution(&self, kind: Kind) -> String {
+        match kind {
+            Kind::Choice => format!("{}. {}", self.answer + 1, self.choices[self.answer]),
+            _ => self.phrase.clone(),
+        }
+    }
+
+    // what to look at again in the report
+    fn miss(&self, kind: Kind, input: &str) -> (String, String) {
+        let detail = match kind {
+            Kind::Dictation => input.to_string(),
+            Kind::Meaning => self.prompt.clone(),
+            Kind::Choice => self.choices[self.answer].clone(),
+        };
+        (self.phrase.clone(), detail)
+    }
+}
+
+// the first meaning in the snapshot, "n. 中国"
+fn meaning(w: &Word) -> Option<String> {
+    let e = Entry::new("", &w.phrase, w.vocabulary.as_ref(), None);
+    let x = e.explains.first()?;
+    Some(match &x.pos {
+        Some(p) => format!("{} {}", p, x.meaning),
+        None => x.meaning.clone(),
+    })
+}
+
+// Up to `count` random words. Questions which need a meaning skip the words
+// without one, the distractors are meanings of the other words.
+fn questions<R: Rng>(kind: Kind, words: &[Word], count: usize, rng: &mut R) -> Vec<Question> {
+    let pool: Vec<_> = words
+        .iter()
+        .filter_map(|w| meaning(w).map(|m| (w, m)))
+        .collect();
+    let mut picked: Vec<(&Word, String)> = match kind {
+        Kind::Dictation => words.iter().map(|w| (w, String::new())).collect(),
+        Kind::Meaning | Kind::Choice => pool.clone(),
+    };
+    picked.shuffle(rng);
+
+    picked
+        .into_iter()
+        .filter_map(|(w, m)| match kind {
+            Kind::Dictation => Some(Question {
+                phrase: w.phrase.clone(),
+                prompt: String::new(),
+                choices: vec![],
+                answer: 0,
+            }),
+            Kind::Meaning => Some(Question {
+                phrase: w.phrase.clone(),
+                prompt: m,
+                choices: vec![],
+                answer: 0,
+            }),
+            Kind::Choice => {
+                let mut others: Vec<_> = pool
+                    .iter()
+                    .filter(|(o, om)| o.phrase != w.phrase && *om != m)
+                    .map(|(_, om)| om.clone())
+                    .collect();
+                others.sort();
+                others.dedup();
+                // a choice of one isn't a question
+                if others.is_empty() {
+                    return None;
+                }
+                others.shuffle(rng);
+                others.truncate(CHOICES - 1);
+                let answer = rng.gen_range(0..=others.len());
+                others.insert(answer, m);
+                Some(Question {
+                    phrase: w.phrase.clone(),
+                    prompt: w.phrase.clone(),
+                    choices: others,
+                    answer,
+                })
+            }
+        })
+        .take(count)
+        .collect()
+}
+
+// Asks the questions on the terminal, "q" stops early.
+pub fn run(opts: QuizOptions) -> Result<Report> {
+    let book = Book::open()?;
+    let words = book.list(opts.tag.as_deref())?;
+    let qs = questions(opts.kind, &words, opts.count, &mut rand::thread_rng());
+    let mut report = Report::default();
+    if qs.is_empty() {
+        let msg = match (opts.kind, words.is_empty()) {
+            (Kind::Choice, false) => "multiple choice needs at least 2 words of different meanings",
+            _ => "no words to quiz on, add some with `rdict book add`",
+        };
+        say(&msg.coloring(Role::Wip));
+        return Ok(report);
+    }
+
+    let accent = AudioType::try_from(config::get().voice.value.clone())?;
+    for (i, q) in qs.iter().enumerate() {
+        say(&format!("[{}/{}]", i + 1, qs.len()).coloring(Role::Index));
+        let input = match opts.kind {
+            Kind::Dictation => {
+                let mut res = Aggregate::from_config(q.phrase.clone())?;
+                loop {
+                    if let Err(e) = res.play_audio(accent.clone()) {
+                        say(&e.to_string().coloring(Role::Wip));
+                    }
+                    let line = ask("type what you hear, enter to hear it again")?;
+                    if !line.is_empty() {
+                        break line;
+                    }
+                }
+            }
+            Kind::Meaning => {
+                say(&q.prompt.coloring(Role::Content));
+                ask("which word is it?")?
+            }
+            Kind::Choice => {
+                say(&q.prompt.coloring(Role::Emphasis));
+                for (k, c) in q.choices.iter().enumerate() {
+                    println!(
+                        "{s}{index}{dot}{choice}",
+                        s = ' '.align_right(6),
+                        index = (k + 1).to_string().coloring(Role::Index),
+                        dot = ". ".coloring(Role::Dot),
+                        choice = c.coloring(Role::Content)
+                    );
+                }
+                ask(&format!("1 .. {}?", q.choices.len()))?
+            }
+        };
+        if input == "q" {
+            break;
+        }
+
+        report.total += 1;
+        let right = q.check(opts.kind, &input);
+        match right {
+            true => {
+                report.correct += 1;
+                say(&"✓".coloring(Role::Other));
+            }
+            false => {
+                report.missed.push(q.miss(opts.kind, &input));
+                say(&format!("✗ {}", q.solution(opts.kind)).coloring(Role::Wip));
+            }
+        }
+
+        if opts.schedule {
+            if let Some(mut w) = book.get(&q.phrase)? {
+                w.schedule.grade(if right { 4 } else { 1 }, util::now());
+                book.put(&w)?;
+            }
+        }
+    }
+    Ok(report)
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let percent = match self.total {
+            0 => 0,
+            n => self.correct * 100 / n,
+        };
+        writeln!(f)?;
+        writeln!(
+            f,
+            "{s}{score}",
+            s = ' '.align_right(4),
+            score = format!("{}/{} correct, {}%", self.correct, self.total, percent)
+                .coloring(Role::Title),
+        )?;
+        for (phrase, answer) in &self.missed {
+            writeln!(
+                f,
+                "{s}{phrase}{answer}",
+                s = ' '.align_right(6),
+                phrase = phrase.align_left(16).coloring(Role::Emphasis),
+                answer = answer.coloring(Role::Content),
+            )?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::handler::{youdao::Youdao, Dictionary};
+    use rand::{rngs::StdRng, SeedableRng};
+
+    fn words() -> Vec<Word> {
+        let raw = std::fs::read("tests/fixtures/youdao/hello.json").unwrap();
+        let vb = Youdao.parse(&raw).unwrap();
+        let mut words = vec![
+            Word::new("hello", Some(("youdao", &vb))),
+            Word::new("bare", None),
+        ];
+        // the same snapshot under other names makes distinct meanings scarce
+        for p in ["hi", "hey"] {
+            words.push(Word::new(p, Some(("youdao", &vb))));
+        }
+        words
+    }
+
+    #[test]
+    fn test_questions() {
+        let words = words();
+        let mut rng = StdRng::seed_from_u64(7);
+        assert_eq!(meaning(&words[0]).unwrap(), "int. 喂；哈罗，你好，您好");
+        assert!(meaning(&words[1]).is_none());
+
+        assert_eq!(questions(Kind::Dictation, &words, 10, &mut rng).len(), 4);
+        let qs = questions(Kind::Meaning, &words, 2, &mut rng);
+        assert_eq!(qs.len(), 2);
+        assert!(qs.iter().all(|q| q.phrase != "bare"));
+        assert!(qs[0].check(Kind::Meaning, &format!(" {} ", qs[0].phrase.to_uppercase())));
+
+        // the meanings are all the same, nothing to choose from
+        assert!(questions(Kind::Choice, &words, 10, &mut rng).is_empty());
+    }
+
+    #[test]
+    fn test_choices() {
+        let mut words = words();
+        let mut other = Word::new("other", None);
+        other.vocabulary = Some(serde_json::from_str(
+            r#"{"phrase":"other","phonetic":null,"explains":[{"content":"adj. 其他的"}],"forms":null,"examples":null,"typo":null}"#,
+        ).unwrap());
+        words.push(other);
+
+        let mut rng = StdRng::seed_from_u64(1);
+        let qs = questions(Kind::Choice, &words, 10, &mut rng);
+        assert_eq!(qs.len(), 4);
+        assert!(qs.iter().all(|q| q.choices.len() == 2));
+        let q = qs.iter().find(|q| q.phrase == "other").unwrap();
+        assert_eq!(q.choices.len(), 2);
+        assert_eq!(q.choices[q.answer], "adj. 其他的");
+        assert_eq!(
+            q.solution(Kind::Choice),
+            format!("{}. adj. 其他的", q.answer + 1)
+        );
+        assert_eq!(q.miss(Kind::Choice, "9").1, "adj. 其他的");
+    }
+}
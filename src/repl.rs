@@ -0,0 +1,324 @@
+use std::path::PathBuf;
+
+use rustyline::completion::Completer;
+use rustyline::error::ReadlineError;
+use rustyline::highlight::Highlighter;
+use rustyline::hint::Hinter;
+use rustyline::history::DefaultHistory;
+use rustyline::validate::Validator;
+use rustyline::{Context, Editor, Helper};
+
+use crate::book::{Book, Word};
+use crate::cache;
+use crate::config;
+use crate::handler::{AudioType, Engines};
+use crate::meta;
+use crate::query::{self, Aggregate, Mode};
+use crate::result::{Error, Result};
+use crate::util::{self, ColorfulRole as Role, Style};
+
+const PROMPT: &str = "rdict> ";
+const HISTORY_FILE: &str = "rdict_repl_history";
+const HELP: &str = "\
+:v [uk|us]     play the last word
+:d [name,..]   show or switch the engines
+:add           add the last word to the word book
+:h             show this help
+:q             quit";
+
+// one line typed at the prompt
+#[derive(Debug, PartialEq, Eq)]
+enum Input {
+    Lookup(String),
+    Voice(Option<String>),
+    Dict(Option<Vec<String>>),
+    Add,
+    Help,
+    Quit,
+    Empty,
+    Unknown(String),
+}
+
+struct Session {
+    engines: Vec<String>,
+    mode: Mode,
+    last: Option<Aggregate>,
+}
+
+// completes phrases from the cache, and the arguments of :d and :v
+struct Completion {
+    words: Vec<String>,
+}
+
+// Looks up every line typed, until :q, Ctrl-C or Ctrl-D.
+pub fn run() -> Result<()> {
+    meta::show_logo();
+    println!();
+
+    let words = util::open_db()
+        .and_then(|db| cache::phrases(&db))
+        .unwrap_or_default();
+    let mut rl: Editor<Completion, DefaultHistory> = Editor::new().map_err(readline_error)?;
+    rl.set_helper(Some(Completion { words }));
+    let history = history_path();
+    let _ = rl.load_history(&history);
+
+    let mut session = Session::new();
+    loop {
+        let line = match rl.readline(PROMPT) {
+            Ok(line) => line,
+            Err(ReadlineError::Interrupted | ReadlineError::Eof) => break,
+            Err(e) => return Err(readline_error(e)),
+        };
+        if !line.trim().is_empty() {
+            let _ = rl.add_history_entry(line.trim());
+        }
+
+        let input = parse_line(&line);
+        if input == Input::Quit {
+            break;
+        }
+        if let Input::Lookup(phrase) = &input {
+            if let Some(c) = rl.helper_mut() {
+                c.learn(phrase);
+            }
+        }
+        if let Err(e) = session.handle(input) {
+            notice(&e.to_string(), Role::Wip);
+        }
+    }
+
+    rl.save_history(&history).map_err(readline_error)
+}
+
+// kept next to the database
+fn history_path() -> PathBuf {
+    config::get().db_path.value.with_file_name(HISTORY_FILE)
+}
+
+fn readline_error(e: ReadlineError) -> Error {
+    match e {
+        ReadlineError::Io(e) => e.into(),
+        _ => Error::Io(std::io::ErrorKind::Other, "readline error"),
+    }
+}
+
+fn notice(msg: &str, role: Role) {
+    let s = ' '.align_right(4);
+    let msg = msg.replace('\n', &format!("\n{}", s));
+    println!("{}{}", s, msg.coloring(role));
+}
+
+fn parse_line(line: &str) -> Input {
+    let line = line.trim();
+    let (cmd, arg) = match line.split_once(char::is_whitespace) {
+        Some((cmd, arg)) => (cmd, Some(arg.trim().to_string())),
+        None => (line, None),
+    };
+    match cmd {
+        "" => Input::Empty,
+        ":q" | ":quit" => Input::Quit,
+        ":h" | ":help" => Input::Help,
+        ":add" => Input::Add,
+        ":v" => Input::Voice(arg),
+        ":d" => Input::Dict(arg.map(|a| {
+            a.split(',')
+                .map(|n| n.trim().to_string())
+                .filter(|n| !n.is_empty())
+                .collect()
+        })),
+        c if c.starts_with(':') => Input::Unknown(c.to_string()),
+        _ => Input::Lookup(line.to_string()),
+    }
+}
+
+impl Session {
+    fn new() -> Self {
+        let (engines, mode) = query::default_engines();
+        Session {
+            engines,
+            mode,
+            last: None,
+        }
+    }
+
+    fn handle(&mut self, input: Input) -> Result<()> {
+        match input {
+            Input::Lookup(phrase) => {
+                let engines = self
+                    .engines
+                    .iter()
+                    .map(|n| Engines::get(n))
+                    .collect::<Result<Vec<_>>>()?;
+                let mut res = Aggregate::new(phrase, engines, self.mode);
+                res.query_with_pb().save()?;
+                println!("{}", res);
+                if config::get().autoplay.value {
+                    res.play_audio(AudioType::try_from(config::get().voice.value.clone())?)?;
+                }
+                self.last = Some(res);
+            }
+            Input::Voice(accent) => {
+                let accent = accent.unwrap_or_else(|| config::get().voice.value.clone());
+                let t = AudioType::try_from(accent)?;
+                match &mut self.last {
+                    Some(res) => res.play_audio(t)?,
+                    None => notice("nothing looked up yet", Role::Other),
+                }
+            }
+            Input::Dict(None) => {
+                let mode = match self.mode {
+                    Mode::All => "all",
+                    Mode::Fallback => "fallback",
+                };
+                notice(
+                    &format!("{} ({})", self.engines.join(","), mode),
+                    Role::Content,
+                );
+            }
+            Input::Dict(Some(names)) => {
+                if names.is_empty() {
+                    return Err(Error::Engine(
+                        "all",
+                        "no engine is given, try :d youdao,bing",
+                    ));
+                }
+                for n in &names {
+                    Engines::get(n)?;
+                }
+                self.engines = names;
+                self.mode = Mode::All;
+            }
+            Input::Add => match &mut self.last {
+                Some(res) => {
+                    let word = Word::new(res.phrase(), res.answer());
+                    match Book::open()?.add(word)? {
+                        true => notice(&format!("{} added", res.phrase()), Role::Content),
+                        false => notice(&format!("{} updated", res.phrase()), Role::Content),
+                    }
+                    if let Err(e) = res.prefetch_for_book() {
+                        notice(&format!("no audio: {}", e), Role::Other);
+                    }
+                }
+                None => notice("nothing looked up yet", Role::Other),
+            },
+            Input::Help => notice(HELP, Role::Content),
+            Input::Unknown(c) => notice(&format!("unknown command {}, try :h", c), Role::Wip),
+            Input::Quit | Input::Empty => {}
+        }
+        Ok(())
+    }
+}
+
+impl Completion {
+    fn learn(&mut self, phrase: &str) {
+        if let Err(i) = self.words.binary_search_by(|w| w.as_str().cmp(phrase)) {
+            self.words.insert(i, phrase.to_string());
+        }
+    }
+
+    // where the replacement starts, and the candidates
+    fn candidates(&self, line: &str) -> (usize, Vec<String>) {
+        let starts_with = |prefix: &str, all: &mut dyn Iterator<Item = &str>| {
+            all.filter(|w| w.starts_with(prefix))
+                .map(String::from)
+                .collect::<Vec<_>>()
+        };
+
+        if let Some(arg) = line.strip_prefix(":d ") {
+            // only the engine after the last comma
+            let prefix = arg.rsplit(',').next().unwrap_or("").trim_start();
+            let start = line.len() - prefix.len();
+            return (
+                start,
+                starts_with(prefix, &mut Engines::names().into_iter()),
+            );
+        }
+        if let Some(prefix) = line.strip_prefix(":v ") {
+            return (3, starts_with(prefix, &mut ["uk", "us"].into_iter()));
+        }
+        if line.is_empty() || line.starts_with(':') {
+            return (0, vec![]);
+        }
+        (
+            0,
+            starts_with(line, &mut self.words.iter().map(|w| w.as_str())),
+        )
+    }
+}
+
+impl Completer for Completion {
+    type Candidate = String;
+
+    fn complete(
+        &self,
+        line: &str,
+        pos: usize,
+        _ctx: &Context<'_>,
+    ) -> rustyline::Result<(usize, Vec<String>)> {
+        Ok(self.candidates(&line[..pos]))
+    }
+}
+
+impl Hinter for Completion {
+    type Hint = String;
+}
+
+impl Highlighter for Completion {}
+
+impl Validator for Completion {}
+
+impl Helper for Completion {}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse_line() {
+        assert_eq!(
+            parse_line("  hello world "),
+            Input::Lookup("hello world".into())
+        );
+        assert_eq!(parse_line(""), Input::Empty);
+        assert_eq!(parse_line(":q"), Input::Quit);
+        assert_eq!(parse_line(":v"), Input::Voice(None));
+        assert_eq!(parse_line(":v uk"), Input::Voice(Some("uk".into())));
+        assert_eq!(
+            parse_line(":d bing, ecdict"),
+            Input::Dict(Some(vec!["bing".into(), "ecdict".into()]))
+        );
+        assert_eq!(parse_line(":d"), Input::Dict(None));
+        assert_eq!(parse_line(":add"), Input::Add);
+        assert_eq!(parse_line(":x 1"), Input::Unknown(":x".into()));
+    }
+
+    #[test]
+    fn test_dict() {
+        let mut s = Session::new();
+        let e = s.handle(parse_line(":d youdao, bnig")).unwrap_err();
+        let e = e.to_string();
+        assert!(e.contains("bnig") && e.contains("youdao, bing"), "{}", e);
+        let e = s.handle(parse_line(":d ,")).unwrap_err();
+        assert!(e.to_string().contains("no engine is given"));
+    }
+
+    #[test]
+    fn test_complete() {
+        let mut c = Completion {
+            words: vec!["hello".into(), "help".into(), "world".into()],
+        };
+        c.learn("hero");
+        c.learn("hello");
+        assert_eq!(c.words, ["hello", "help", "hero", "world"]);
+
+        assert_eq!(
+            c.candidates("hel"),
+            (0, vec!["hello".into(), "help".into()])
+        );
+        assert_eq!(c.candidates(""), (0, vec![]));
+        assert_eq!(c.candidates(":v u"), (3, vec!["uk".into(), "us".into()]));
+        assert_eq!(c.candidates(":d bi"), (3, vec!["bing".into()]));
+        assert_eq!(c.candidates(":d youdao, ec"), (11, vec!["ecdict".into()]));
+    }
+}
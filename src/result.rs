@@ -1,5 +1,7 @@
 use std::{fmt, io};
 
+use crate::handler::Engines;
+
 pub type Result<T> = std::result::Result<T, Error>;
 
 #[derive(Debug)]
@@ -9,8 +11,15 @@ pub enum Error {
     Arg(clap::ErrorKind, &'static str),
     Db(sled::Error, &'static str),
     Audio(AudioError, &'static str),
+    Engine(&'static str, &'static str),
+    // the name asked for, which isn't one of Engines::names
+    NoEngine(String),
+    Config(&'static str, &'static str),
+    // what toml says is wrong with the file, the line and the key
+    ConfigFile(String),
 }
 
+#[allow(dead_code)]
 #[derive(Debug)]
 pub enum AudioError {
     Play(rodio::PlayError),
@@ -53,6 +62,28 @@ impl From<sled::Error> for Error {
     }
 }
 
+impl From<csv::Error> for Error {
+    fn from(err: csv::Error) -> Self {
+        match err.into_kind() {
+            csv::ErrorKind::Io(e) => e.into(),
+            _ => Error::Io(io::ErrorKind::InvalidData, "bad csv record"),
+        }
+    }
+}
+
+impl From<rusqlite::Error> for Error {
+    fn from(_: rusqlite::Error) -> Self {
+        Error::Io(io::ErrorKind::Other, "sqlite error")
+    }
+}
+
+impl From<zip::result::ZipError> for Error {
+    fn from(err: zip::result::ZipError) -> Self {
+        let k = io::Error::from(err);
+        Error::Io(k.kind(), "zip error")
+    }
+}
+
 impl From<rodio::PlayError> for Error {
     fn from(err: rodio::PlayError) -> Self {
         Error::Audio(AudioError::Play(err), "audio erorr")
@@ -97,6 +128,19 @@ impl fmt::Display for Error {
             Error::Audio(ref kind, ref reason) => {
                 write!(f, "audio error: {:?} {}", kind, reason)
             }
+            Error::Engine(ref name, ref reason) => {
+                write!(f, "engine error: ({}, {})", name, reason)
+            }
+            Error::NoEngine(ref name) => write!(
+                f,
+                "engine error: no such engine {}, try one of {}",
+                name,
+                Engines::names().join(", ")
+            ),
+            Error::Config(ref key, ref reason) => {
+                write!(f, "config error: ({}, {})", key, reason)
+            }
+            Error::ConfigFile(ref reason) => write!(f, "config error: {}", reason),
         }
     }
 }
@@ -0,0 +1,263 @@
+use std::{fmt, io};
+
+use chrono::{Days, Local, TimeZone};
+use serde::{Deserialize, Serialize};
+
+use crate::book::{Book, Word};
+use crate::handler::AudioType;
+use crate::query::Aggregate;
+use crate::result::Result;
+use crate::util::{self, ColorfulRole as Role, Style};
+
+const DAY: i64 = 24 * 60 * 60;
+
+// SM-2, as in https://super-memory.com/english/ol/sm2.htm
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Schedule {
+    // successful reviews in a row
+    pub reps: u32,
+    // days until the next review
+    pub interval: u32,
+    // the e-factor, how easy the word is
+    pub ease: f64,
+    // seconds since the epoch, a new word is due at once
+    pub due: i64,
+}
+
+pub struct ReviewOptions {
+    pub limit: usize,
+    pub tag: Option<String>,
+    pub voice: Option<AudioType>,
+    pub summary: bool,
+}
+
+// how many words are due, by the end of today and of tomorrow
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct Summary {
+    pub today: usize,
+    pub tomorrow: usize,
+    pub total: usize,
+}
+
+impl Default for Schedule {
+    fn default() -> Self {
+        Schedule {
+            reps: 0,
+            interval: 0,
+            ease: 2.5,
+            due: 0,
+        }
+    }
+}
+
+impl Schedule {
+    // `q` from 0 (blackout) to 5 (perfect), below 3 starts over
+    pub fn grade(&mut self, q: u8, now: i64) {
+        let q = q.min(5);
+        if q < 3 {
+            self.reps = 0;
+            self.interval = 1;
+        } else {
+            self.reps += 1;
+            self.interval = match self.reps {
+                1 => 1,
+                2 => 6,
+                _ => (self.interval as f64 * self.ease).round() as u32,
+            };
+            let d = (5 - q) as f64;
+            self.ease = (self.ease + 0.1 - d * (0.08 + d * 0.02)).max(1.3);
+        }
+        self.due = now + self.interval as i64 * DAY;
+    }
+}
+
+// midnight at the end of the day `days` after the one `now` is in
+fn day_end(now: i64, days: u64) -> i64 {
+    Local
+        .timestamp_opt(now, 0)
+        .single()
+        .and_then(|t| t.date_naive().checked_add_days(Days::new(days + 1)))
+        .and_then(|d| d.and_hms_opt(0, 0, 0))
+        .and_then(|t| Local.from_local_datetime(&t).earliest())
+        .map_or(now + (days as i64 + 1) * DAY, |t| t.timestamp())
+}
+
+// due by the end of today, the most overdue first
+pub fn due(words: &[Word], now: i64) -> Vec<&Word> {
+    let end = day_end(now, 0);
+    let mut res: Vec<_> = words.iter().filter(|w| w.schedule.due < end).collect();
+    res.sort_by_key(|w| w.schedule.due);
+    res
+}
+
+pub fn summary(words: &[Word], now: i64) -> Summary {
+    let (today, tomorrow) = (day_end(now, 0), day_end(now, 1));
+    Summary {
+        today: words.iter().filter(|w| w.schedule.due < today).count(),
+        tomorrow: words
+            .iter()
+            .filter(|w| (today..tomorrow).contains(&w.schedule.due))
+            .count(),
+        total: words.len(),
+    }
+}
+
+// Asks for the due words one by one on the terminal, until they're done or
+// "q" is typed. A word graded below 4 comes back at the end of the session,
+// it's only rescheduled the first time.
+pub fn run(opts: ReviewOptions) -> Result<()> {
+    let book = Book::open()?;
+    let words = book.list(opts.tag.as_deref())?;
+    println!("{}", summary(&words, util::now()));
+    if opts.summary {
+        return Ok(());
+    }
+
+    let mut queue: Vec<_> = due(&words, util::now())
+        .into_iter()
+        .take(opts.limit)
+        .map(|w| (w.clone(), true))
+        .collect();
+    let total = queue.len();
+    let mut n = 0;
+    while !queue.is_empty() {
+        let (mut w, first) = queue.remove(0);
+        let progress = match first {
+            true => {
+                n += 1;
+                format!("[{}/{}]", n, total)
+            }
+            false => "[again]".to_string(),
+        };
+        say(&format!(
+            "{} {}",
+            progress,
+            w.phrase.coloring(Role::Emphasis)
+        ));
+
+        let mut res = Aggregate::from_config(w.phrase.clone())?;
+        if let Some(v) = &opts.voice {
+            if let Err(e) = res.play_audio(v.clone()) {
+                say(&e.to_string().coloring(Role::Wip));
+            }
+        }
+
+        if ask("press enter to show, q to quit")? == "q" {
+            break;
+        }
+        match &w.vocabulary {
+            Some(v) => println!("\n{}", v),
+            // added without an answer, try again
+            None => println!("{}", res.query_with_pb()),
+        }
+
+        let q = loop {
+            let line = ask("how well did you know it? 0 (not at all) .. 5 (perfectly)")?;
+            if line == "q" {
+                return Ok(());
+            }
+            if let Ok(q @ 0..=5) = line.parse::<u8>() {
+                break q;
+            }
+        };
+        if first {
+            w.schedule.grade(q, util::now());
+            book.put(&w)?;
+        }
+        if q < 4 {
+            queue.push((w, false));
+        }
+    }
+
+    let words = book.list(opts.tag.as_deref())?;
+    println!("{}", summary(&words, util::now()));
+    Ok(())
+}
+
+pub fn say(msg: &str) {
+    println!("\n{}{}", ' '.align_right(4), msg);
+}
+
+pub fn ask(prompt: &str) -> Result<String> {
+    say(&prompt.coloring(Role::Other));
+    let mut line = String::new();
+    if io::stdin().read_line(&mut line)? == 0 {
+        return Ok("q".to_string());
+    }
+    Ok(line.trim().to_lowercase())
+}
+
+impl fmt::Display for Summary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f)?;
+        write!(
+            f,
+            "{s}{today}{dot}{tomorrow}{dot}{total}",
+            s = ' '.align_right(4),
+            today = format!("{} due today", self.today).coloring(Role::Content),
+            dot = " · ".coloring(Role::Dot),
+            tomorrow = format!("{} tomorrow", self.tomorrow).coloring(Role::Content),
+            total = format!("{} in the book", self.total).coloring(Role::Other),
+        )
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_grade() {
+        let mut s = Schedule::default();
+        s.grade(5, 0);
+        assert_eq!((s.reps, s.interval, s.due), (1, 1, DAY));
+        s.grade(4, 0);
+        assert_eq!((s.reps, s.interval), (2, 6));
+        assert!((s.ease - 2.6).abs() < 1e-9);
+        s.grade(3, 0);
+        assert_eq!((s.reps, s.interval), (3, 16));
+        assert!((s.ease - 2.46).abs() < 1e-9);
+
+        // forgotten, starts over and keeps the ease
+        s.grade(1, 100);
+        assert_eq!((s.reps, s.interval, s.due), (0, 1, 100 + DAY));
+        assert!((s.ease - 2.46).abs() < 1e-9);
+
+        let mut hard = Schedule::default();
+        for _ in 0..10 {
+            hard.grade(3, 0);
+        }
+        assert_eq!(hard.ease, 1.3);
+    }
+
+    #[test]
+    fn test_due() {
+        let now = Local
+            .with_ymd_and_hms(2024, 3, 10, 12, 0, 0)
+            .unwrap()
+            .timestamp();
+        let word = |phrase: &str, due: i64| {
+            let mut w = Word::new(phrase, None);
+            w.schedule.due = due;
+            w
+        };
+        let words = vec![
+            word("new", 0),
+            word("tonight", now + 6 * 3600),
+            word("late", now - DAY),
+            word("tomorrow", now + DAY),
+            word("later", now + 3 * DAY),
+        ];
+
+        let d: Vec<_> = due(&words, now).iter().map(|w| w.phrase.as_str()).collect();
+        assert_eq!(d, ["new", "late", "tonight"]);
+        assert_eq!(
+            summary(&words, now),
+            Summary {
+                today: 3,
+                tomorrow: 1,
+                total: 5
+            }
+        );
+    }
+}
@@ -0,0 +1,161 @@
+use std::{fs, path::PathBuf, sync::OnceLock};
+
+use serde::Deserialize;
+
+use crate::config;
+use crate::result::{Error, Result};
+use crate::util::ColorfulRole;
+
+// Built-in themes, a theme file only has to list the roles it changes.
+const DARK: &str = include_str!("../themes/dark.toml");
+const LIGHT: &str = include_str!("../themes/light.toml");
+
+static THEME: OnceLock<Theme> = OnceLock::new();
+
+#[derive(Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+#[serde(default, deny_unknown_fields)]
+pub struct Paint {
+    // "#rrggbb", the terminal's own color if there's none
+    #[serde(deserialize_with = "hex")]
+    pub color: Option<(u8, u8, u8)>,
+    pub bold: bool,
+    pub italic: bool,
+    pub underline: bool,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Theme {
+    pub title: Paint,
+    pub index: Paint,
+    pub dot: Paint,
+    pub content: Paint,
+    pub emphasis: Paint,
+    pub logo: Paint,
+    pub wip: Paint,
+    pub other: Paint,
+}
+
+// what a theme file looks like, every role is optional
+#[derive(Deserialize, Debug, Default)]
+#[serde(default, deny_unknown_fields)]
+struct ThemeFile {
+    title: Option<Paint>,
+    index: Option<Paint>,
+    dot: Option<Paint>,
+    content: Option<Paint>,
+    emphasis: Option<Paint>,
+    logo: Option<Paint>,
+    wip: Option<Paint>,
+    other: Option<Paint>,
+}
+
+impl Theme {
+    pub fn dark() -> Self {
+        Theme::parse(DARK, Theme::default()).unwrap()
+    }
+
+    pub fn light() -> Self {
+        Theme::parse(LIGHT, Theme::dark()).unwrap()
+    }
+
+    // "dark", "light", a theme in the config directory, or the path of a toml file
+    pub fn load(name: &str) -> Result<Self> {
+        match name {
+            "dark" => return Ok(Theme::dark()),
+            "light" => return Ok(Theme::light()),
+            _ => {}
+        }
+
+        let path = match name.ends_with(".toml") {
+            true => PathBuf::from(name),
+            false => theme_dir().join(format!("{}.toml", name)),
+        };
+        let s = fs::read_to_string(path)?;
+        Theme::parse(&s, Theme::dark())
+    }
+
+    // roles missing from `s` are taken from `base`
+    fn parse(s: &str, base: Theme) -> Result<Self> {
+        let f: ThemeFile = toml::from_str(s)
+            .map_err(|_| Error::Io(std::io::ErrorKind::InvalidData, "bad theme"))?;
+        Ok(Theme {
+            title: f.title.unwrap_or(base.title),
+            index: f.index.unwrap_or(base.index),
+            dot: f.dot.unwrap_or(base.dot),
+            content: f.content.unwrap_or(base.content),
+            emphasis: f.emphasis.unwrap_or(base.emphasis),
+            logo: f.logo.unwrap_or(base.logo),
+            wip: f.wip.unwrap_or(base.wip),
+            other: f.other.unwrap_or(base.other),
+        })
+    }
+
+    pub fn paint(&self, role: ColorfulRole) -> &Paint {
+        match role {
+            ColorfulRole::Title => &self.title,
+            ColorfulRole::Index => &self.index,
+            ColorfulRole::Dot => &self.dot,
+            ColorfulRole::Content => &self.content,
+            ColorfulRole::Emphasis => &self.emphasis,
+            ColorfulRole::Logo => &self.logo,
+            ColorfulRole::Wip => &self.wip,
+            ColorfulRole::Other => &self.other,
+        }
+    }
+}
+
+// the theme can only be set once, before anything is printed
+pub fn set_theme(theme: Theme) {
+    let _ = THEME.set(theme);
+}
+
+pub fn theme() -> &'static Theme {
+    THEME.get_or_init(Theme::dark)
+}
+
+pub fn theme_dir() -> PathBuf {
+    config::dir().join("themes")
+}
+
+fn hex<'de, D>(d: D) -> std::result::Result<Option<(u8, u8, u8)>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    let s = String::deserialize(d)?;
+    let v = s
+        .strip_prefix('#')
+        .filter(|v| v.len() == 6)
+        .and_then(|v| u32::from_str_radix(v, 16).ok())
+        .ok_or_else(|| serde::de::Error::custom(format!("bad color {}, expects #rrggbb", s)))?;
+    Ok(Some(((v >> 16) as u8, (v >> 8) as u8, v as u8)))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_theme() {
+        let dark = Theme::dark();
+        assert_eq!(dark.title.color, Some((255, 95, 175)));
+        assert_eq!(dark.other.color, Some((0, 134, 1)));
+
+        let light = Theme::light();
+        assert!(light.title.bold);
+        assert_ne!(light.content, dark.content);
+
+        let t = Theme::parse(
+            "[dot]\ncolor = \"#000000\"\nunderline = true\n[wip]\nitalic = true",
+            Theme::dark(),
+        )
+        .unwrap();
+        assert_eq!(t.dot.color, Some((0, 0, 0)));
+        assert!(t.dot.underline);
+        assert_eq!(t.wip.color, None);
+        assert_eq!(t.title, dark.title);
+
+        assert!(Theme::parse("[dot]\ncolor = \"red\"", Theme::dark()).is_err());
+        assert!(Theme::parse("[nothing]", Theme::dark()).is_err());
+        assert!(Theme::load("no-such-theme").is_err());
+    }
+}
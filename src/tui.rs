@@ -0,0 +1,568 @@
+use std::io::{self, Stdout};
+
+use crossterm::event::{self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers};
+use crossterm::execute;
+use crossterm::terminal::{self, EnterAlternateScreen, LeaveAlternateScreen};
+use ratatui::backend::CrosstermBackend;
+use ratatui::layout::{Constraint, Direction, Layout, Rect};
+use ratatui::style::{Color, Modifier, Style as TuiStyle};
+use ratatui::text::{Line, Span, Text};
+use ratatui::widgets::{Block, Borders, List, ListItem, ListState, Paragraph, Wrap};
+use ratatui::{Frame, Terminal};
+
+use crate::book::{Book, Word};
+use crate::config;
+use crate::handler::AudioType;
+use crate::history::History;
+use crate::query::Aggregate;
+use crate::result::Result;
+use crate::theme::theme;
+use crate::util::{self, ColorLevel, ColorfulRole as Role};
+
+// how many phrases the history pane keeps
+const HISTORY_LEN: usize = 100;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Focus {
+    Search,
+    Result,
+    History,
+    Book,
+}
+
+// what a key asks for, done between two frames since it may block
+#[derive(Debug, PartialEq, Eq)]
+enum Action {
+    Lookup(String),
+    // the snapshot in the word book, no network needed
+    Show(String),
+    Add,
+    Remove(String),
+    Play(&'static str),
+    Quit,
+}
+
+struct App {
+    input: String,
+    focus: Focus,
+    result: Option<Aggregate>,
+    // the result as Display writes it, colors included
+    text: Text<'static>,
+    scroll: u16,
+    history: Vec<String>,
+    selected: ListState,
+    book: Vec<String>,
+    book_selected: ListState,
+    status: Option<String>,
+}
+
+// leaves the alternate screen even if something panics
+struct Guard;
+
+impl Drop for Guard {
+    fn drop(&mut self) {
+        let _ = terminal::disable_raw_mode();
+        let _ = execute!(io::stdout(), LeaveAlternateScreen);
+    }
+}
+
+pub fn run() -> Result<()> {
+    terminal::enable_raw_mode()?;
+    let _guard = Guard;
+    execute!(io::stdout(), EnterAlternateScreen)?;
+    let mut term = Terminal::new(CrosstermBackend::new(io::stdout()))?;
+
+    let mut app = App::new();
+    loop {
+        term.draw(|f| app.draw(f))?;
+        let key = match event::read()? {
+            Event::Key(k) if k.kind == KeyEventKind::Press => k,
+            _ => continue,
+        };
+        match app.on_key(key) {
+            Some(Action::Quit) => break,
+            Some(action) => app.perform(action, &mut term),
+            None => {}
+        }
+    }
+    Ok(())
+}
+
+impl App {
+    fn new() -> Self {
+        let mut app = App {
+            input: String::new(),
+            focus: Focus::Search,
+            result: None,
+            text: Text::default(),
+            scroll: 0,
+            history: vec![],
+            selected: ListState::default(),
+            book: vec![],
+            book_selected: ListState::default(),
+            status: None,
+        };
+        app.refresh();
+        app
+    }
+
+    fn refresh(&mut self) {
+        self.history = History::getn(HISTORY_LEN)
+            .records()
+            .iter()
+            .map(|r| r.phrase.clone())
+            .collect();
+        self.book = Book::open()
+            .and_then(|b| b.list(None))
+            .unwrap_or_default()
+            .into_iter()
+            .map(|w| w.phrase)
+            .collect();
+        clamp(&mut self.selected, self.history.len());
+        clamp(&mut self.book_selected, self.book.len());
+    }
+
+    fn on_key(&mut self, key: KeyEvent) -> Option<Action> {
+        let ctrl = key.modifiers.contains(KeyModifiers::CONTROL);
+        if key.code == KeyCode::Esc || (ctrl && key.code == KeyCode::Char('c')) {
+            return Some(Action::Quit);
+        }
+        if key.code == KeyCode::Tab {
+            self.focus = match self.focus {
+                Focus::Search => Focus::Result,
+                Focus::Result => Focus::History,
+                Focus::History => Focus::Book,
+                Focus::Book => Focus::Search,
+            };
+            return None;
+        }
+        self.status = None;
+
+        if self.focus == Focus::Search {
+            match key.code {
+                KeyCode::Char(c) => self.input.push(c),
+                KeyCode::Backspace => {
+                    self.input.pop();
+                }
+                KeyCode::Enter if !self.input.trim().is_empty() => {
+                    self.focus = Focus::Result;
+                    return Some(Action::Lookup(self.input.trim().to_string()));
+                }
+                _ => {}
+            }
+            return None;
+        }
+
+        match key.code {
+            KeyCode::Char('q') => return Some(Action::Quit),
+            KeyCode::Char('/') => self.focus = Focus::Search,
+            KeyCode::Char('u') => return Some(Action::Play("uk")),
+            KeyCode::Char('s') => return Some(Action::Play("us")),
+            KeyCode::Char('a') if self.result.is_some() => return Some(Action::Add),
+            _ => {}
+        }
+        match self.focus {
+            Focus::Result => self.scroll_by(match key.code {
+                KeyCode::Down | KeyCode::Char('j') => 1,
+                KeyCode::Up | KeyCode::Char('k') => -1,
+                KeyCode::PageDown | KeyCode::Char(' ') => 10,
+                KeyCode::PageUp => -10,
+                KeyCode::Home | KeyCode::Char('g') => i32::MIN,
+                KeyCode::End | KeyCode::Char('G') => i32::MAX,
+                _ => 0,
+            }),
+            Focus::History => {
+                if let Some(p) = pick(&mut self.selected, &self.history, key.code) {
+                    self.input = p;
+                    self.focus = Focus::Result;
+                    return Some(Action::Lookup(self.input.clone()));
+                }
+            }
+            Focus::Book => {
+                let i = self.book_selected.selected();
+                if let (KeyCode::Char('d'), Some(p)) = (key.code, i.and_then(|i| self.book.get(i)))
+                {
+                    return Some(Action::Remove(p.clone()));
+                }
+                if let Some(p) = pick(&mut self.book_selected, &self.book, key.code) {
+                    self.input = p.clone();
+                    self.focus = Focus::Result;
+                    return Some(Action::Show(p));
+                }
+            }
+            Focus::Search => {}
+        }
+        None
+    }
+
+    fn scroll_by(&mut self, n: i32) {
+        let max = self.text.lines.len().saturating_sub(1) as i64;
+        self.scroll = (self.scroll as i64 + n as i64).clamp(0, max) as u16;
+    }
+
+    // shows what's going on first, lookups and audio take a while
+    fn perform(&mut self, action: Action, term: &mut Terminal<CrosstermBackend<Stdout>>) {
+        let busy = match &action {
+            Action::Lookup(p) => Some(format!("searching {}...", p)),
+            Action::Play(t) => Some(format!("playing {}...", t)),
+            _ => None,
+        };
+        if busy.is_some() {
+            self.status = busy;
+            let _ = term.draw(|f| self.draw(f));
+            self.status = None;
+        }
+
+        let res = match action {
+            Action::Lookup(p) => self.lookup(p),
+            Action::Show(p) => self.show(p),
+            Action::Add => self.add(),
+            Action::Remove(p) => self.remove(p),
+            Action::Play(t) => self.play(t),
+            Action::Quit => Ok(()),
+        };
+        if let Err(e) = res {
+            self.status = Some(e.to_string());
+        }
+    }
+
+    fn lookup(&mut self, phrase: String) -> Result<()> {
+        let mut res = Aggregate::from_config(phrase)?;
+        res.query().save()?;
+        self.text = to_text(&res.to_string());
+        self.scroll = 0;
+        self.result = Some(res);
+        self.refresh();
+        if config::get().autoplay.value {
+            self.play_with(AudioType::try_from(config::get().voice.value.clone())?)?;
+        }
+        Ok(())
+    }
+
+    fn show(&mut self, phrase: String) -> Result<()> {
+        let word = Book::open()?.get(&phrase)?;
+        self.text = to_text(&word.map(|w| w.to_string()).unwrap_or_default());
+        self.scroll = 0;
+        // not looked up, only there to play the audio
+        self.result = Some(Aggregate::from_config(phrase)?);
+        Ok(())
+    }
+
+    fn add(&mut self) -> Result<()> {
+        if let Some(res) = &self.result {
+            let new = Book::open()?.add(Word::new(res.phrase(), res.answer()))?;
+            self.status = Some(match new {
+                true => format!("{} added", res.phrase()),
+                false => format!("{} updated", res.phrase()),
+            });
+        }
+        self.refresh();
+        Ok(())
+    }
+
+    fn remove(&mut self, phrase: String) -> Result<()> {
+        Book::open()?.remove(&phrase)?;
+        self.status = Some(format!("{} removed", phrase));
+        self.refresh();
+        Ok(())
+    }
+
+    fn play(&mut self, accent: &str) -> Result<()> {
+        self.play_with(AudioType::try_from(accent.to_string())?)
+    }
+
+    fn play_with(&mut self, t: AudioType) -> Result<()> {
+        match &mut self.result {
+            Some(res) => res.play_audio(t),
+            None => Ok(()),
+        }
+    }
+
+    fn draw(&mut self, f: &mut Frame) {
+        let rows = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints([
+                Constraint::Length(3),
+                Constraint::Min(0),
+                Constraint::Length(1),
+            ])
+            .split(f.size());
+        let cols = Layout::default()
+            .direction(Direction::Horizontal)
+            .constraints([Constraint::Length(24), Constraint::Min(0)])
+            .split(rows[1]);
+        let side = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints([Constraint::Percentage(60), Constraint::Percentage(40)])
+            .split(cols[0]);
+
+        // search box
+        f.render_widget(
+            Paragraph::new(self.input.as_str())
+                .style(style(Role::Content))
+                .block(self.block("search", Focus::Search)),
+            rows[0],
+        );
+        if self.focus == Focus::Search {
+            let x = rows[0].x + 1 + self.input.chars().count() as u16;
+            f.set_cursor(x.min(rows[0].right().saturating_sub(2)), rows[0].y + 1);
+        }
+
+        // history and word book
+        let items: Vec<_> = self
+            .history
+            .iter()
+            .map(|p| ListItem::new(p.as_str()).style(style(Role::Content)))
+            .collect();
+        f.render_stateful_widget(
+            List::new(items)
+                .block(self.block("history", Focus::History))
+                .highlight_style(style(Role::Emphasis).add_modifier(Modifier::REVERSED)),
+            side[0],
+            &mut self.selected,
+        );
+        let items: Vec<_> = self
+            .book
+            .iter()
+            .map(|p| ListItem::new(p.as_str()).style(style(Role::Content)))
+            .collect();
+        f.render_stateful_widget(
+            List::new(items)
+                .block(self.block("word book", Focus::Book))
+                .highlight_style(style(Role::Emphasis).add_modifier(Modifier::REVERSED)),
+            side[1],
+            &mut self.book_selected,
+        );
+
+        // result
+        let title = match &self.result {
+            Some(_) => self.input.trim(),
+            None => "result",
+        };
+        f.render_widget(
+            Paragraph::new(self.text.clone())
+                .wrap(Wrap { trim: false })
+                .scroll((self.scroll, 0))
+                .block(self.block(title, Focus::Result)),
+            cols[1],
+        );
+
+        // status, or the keys of the focused pane
+        let help = match self.focus {
+            Focus::Search => "enter look up · tab next pane · esc quit",
+            Focus::Result => "j/k scroll · u/s uk/us audio · a add to book · / search · q quit",
+            Focus::History => "j/k select · enter look up · u/s uk/us audio · / search · q quit",
+            Focus::Book => "j/k select · enter show · d remove · u/s uk/us audio · q quit",
+        };
+        let (msg, role) = match &self.status {
+            Some(s) => (s.as_str(), Role::Wip),
+            None => (help, Role::Other),
+        };
+        f.render_widget(Paragraph::new(msg).style(style(role)), pad(rows[2]));
+    }
+
+    fn block<'a>(&self, title: &'a str, focus: Focus) -> Block<'a> {
+        let border = match self.focus == focus {
+            true => style(Role::Title),
+            false => style(Role::Dot),
+        };
+        Block::default()
+            .borders(Borders::ALL)
+            .border_style(border)
+            .title(Span::styled(format!(" {} ", title), style(Role::Title)))
+    }
+}
+
+// moves the selection of a list, the phrase under it on enter
+fn pick(state: &mut ListState, items: &[String], code: KeyCode) -> Option<String> {
+    let i = state.selected().unwrap_or(0);
+    match code {
+        KeyCode::Down | KeyCode::Char('j') if i + 1 < items.len() => state.select(Some(i + 1)),
+        KeyCode::Up | KeyCode::Char('k') if i > 0 => state.select(Some(i - 1)),
+        KeyCode::Enter => return items.get(i).cloned(),
+        _ => {}
+    }
+    None
+}
+
+// keeps the selection inside a list which may have shrunk
+fn clamp(state: &mut ListState, len: usize) {
+    state.select(match (state.selected(), len) {
+        (_, 0) => None,
+        (Some(i), _) => Some(i.min(len - 1)),
+        (None, _) => Some(0),
+    });
+}
+
+fn pad(r: Rect) -> Rect {
+    Rect {
+        x: r.x + 1,
+        width: r.width.saturating_sub(1),
+        ..r
+    }
+}
+
+// a role of the theme, in as many colors as the terminal has
+fn style(role: Role) -> TuiStyle {
+    let p = theme().paint(role);
+    let mut s = TuiStyle::default();
+    if let Some(rgb) = p.color {
+        s = match util::color_level() {
+            ColorLevel::Plain => s,
+            ColorLevel::Ansi16 => s.fg(Color::Indexed(util::ansi16(rgb))),
+            ColorLevel::Ansi256 => s.fg(Color::Indexed(util::ansi256(rgb))),
+            ColorLevel::TrueColor => s.fg(Color::Rgb(rgb.0, rgb.1, rgb.2)),
+        };
+    }
+    if p.bold {
+        s = s.add_modifier(Modifier::BOLD);
+    }
+    if p.italic {
+        s = s.add_modifier(Modifier::ITALIC);
+    }
+    if p.underline {
+        s = s.add_modifier(Modifier::UNDERLINED);
+    }
+    s
+}
+
+// The text output, so that the pane looks the same as the command line.
+// Only the escape codes util::paint writes are understood.
+fn to_text(s: &str) -> Text<'static> {
+    let mut lines = vec![];
+    for line in s.lines() {
+        let mut spans = vec![];
+        let mut style = TuiStyle::default();
+        let mut rest = line;
+        while let Some(i) = rest.find("\x1b[") {
+            if i > 0 {
+                spans.push(Span::styled(rest[..i].to_string(), style));
+            }
+            let codes = &rest[i + 2..];
+            let end = codes.find('m').unwrap_or(codes.len());
+            style = sgr(&codes[..end], style);
+            rest = codes.get(end + 1..).unwrap_or("");
+        }
+        if !rest.is_empty() {
+            spans.push(Span::styled(rest.to_string(), style));
+        }
+        lines.push(Line::from(spans));
+    }
+    Text::from(lines)
+}
+
+fn sgr(codes: &str, mut style: TuiStyle) -> TuiStyle {
+    let mut codes = codes.split(';').map(|c| c.parse::<u8>().unwrap_or(0));
+    while let Some(c) = codes.next() {
+        style = match c {
+            0 => TuiStyle::default(),
+            1 => style.add_modifier(Modifier::BOLD),
+            3 => style.add_modifier(Modifier::ITALIC),
+            4 => style.add_modifier(Modifier::UNDERLINED),
+            30..=37 => style.fg(Color::Indexed(c - 30)),
+            90..=97 => style.fg(Color::Indexed(c - 90 + 8)),
+            38 => match codes.next() {
+                Some(5) => style.fg(Color::Indexed(codes.next().unwrap_or(0))),
+                Some(2) => {
+                    let mut c = || codes.next().unwrap_or(0);
+                    style.fg(Color::Rgb(c(), c(), c()))
+                }
+                _ => style,
+            },
+            _ => style,
+        };
+    }
+    style
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn key(code: KeyCode) -> KeyEvent {
+        KeyEvent::new(code, KeyModifiers::NONE)
+    }
+
+    #[test]
+    fn test_to_text() {
+        let t = to_text("    \x1b[1;38;2;255;95;175m音标\x1b[0m\n\x1b[38;5;28mhi\x1b[0m there\n");
+        assert_eq!(t.lines.len(), 2);
+        let first = &t.lines[0].spans;
+        assert_eq!(first[0].content, "    ");
+        assert_eq!(first[1].content, "音标");
+        assert_eq!(first[1].style.fg, Some(Color::Rgb(255, 95, 175)));
+        assert!(first[1].style.add_modifier.contains(Modifier::BOLD));
+
+        let second = &t.lines[1].spans;
+        assert_eq!(second[0].style.fg, Some(Color::Indexed(28)));
+        assert_eq!(second[1].content, " there");
+        assert_eq!(second[1].style, TuiStyle::default());
+
+        assert_eq!(
+            to_text("\x1b[91mred").lines[0].spans[0].style.fg,
+            Some(Color::Indexed(9))
+        );
+    }
+
+    #[test]
+    fn test_keys() {
+        let mut app = App {
+            input: String::new(),
+            focus: Focus::Search,
+            result: None,
+            text: to_text("a\nb\nc\n"),
+            scroll: 0,
+            history: vec!["hello".into(), "world".into()],
+            selected: ListState::default().with_selected(Some(0)),
+            book: vec!["hi".into()],
+            book_selected: ListState::default().with_selected(Some(0)),
+            status: None,
+        };
+
+        assert_eq!(app.on_key(key(KeyCode::Char('q'))), None);
+        app.on_key(key(KeyCode::Backspace));
+        assert_eq!(app.on_key(key(KeyCode::Enter)), None);
+        app.on_key(key(KeyCode::Char('s')));
+        assert_eq!(
+            app.on_key(key(KeyCode::Enter)),
+            Some(Action::Lookup("s".into()))
+        );
+        assert_eq!(app.focus, Focus::Result);
+
+        app.on_key(key(KeyCode::End));
+        assert_eq!(app.scroll, 2);
+        app.on_key(key(KeyCode::Char('k')));
+        assert_eq!(app.scroll, 1);
+        assert_eq!(
+            app.on_key(key(KeyCode::Char('u'))),
+            Some(Action::Play("uk"))
+        );
+
+        app.on_key(key(KeyCode::Tab));
+        assert_eq!(app.focus, Focus::History);
+        app.on_key(key(KeyCode::Down));
+        app.on_key(key(KeyCode::Down));
+        assert_eq!(
+            app.on_key(key(KeyCode::Enter)),
+            Some(Action::Lookup("world".into()))
+        );
+
+        app.on_key(key(KeyCode::Tab));
+        app.on_key(key(KeyCode::Tab));
+        assert_eq!(app.focus, Focus::Book);
+        app.on_key(key(KeyCode::Down));
+        assert_eq!(
+            app.on_key(key(KeyCode::Enter)),
+            Some(Action::Show("hi".into()))
+        );
+        app.focus = Focus::Book;
+        assert_eq!(
+            app.on_key(key(KeyCode::Char('d'))),
+            Some(Action::Remove("hi".into()))
+        );
+
+        app.on_key(key(KeyCode::Char('/')));
+        assert_eq!(app.focus, Focus::Search);
+        assert_eq!(app.on_key(key(KeyCode::Esc)), Some(Action::Quit));
+    }
+}
@@ -1,10 +1,13 @@
-use std::{env, path::PathBuf};
-
-use colored::Colorize;
+use std::io::{self, IsTerminal};
+use std::sync::atomic::{AtomicU8, Ordering};
+use std::time::{Duration, SystemTime};
+use std::{env, sync::Mutex};
 
+use crate::cache;
+use crate::config;
 use crate::result::Result;
+use crate::theme::{theme, Paint};
 
-const DB_NAME: &str = "rdict";
 pub enum ColorfulRole {
     Title,
     Index,
@@ -16,39 +19,162 @@ pub enum ColorfulRole {
     Other,
 }
 
+// sled locks the database, so a process (and all its threads) shares a
+// single handle. Writers flush by themselves, it's never dropped.
+static DB: Mutex<Option<sled::Db>> = Mutex::new(None);
+
+// zero for a clock set before 1970
+pub fn since_epoch(t: SystemTime) -> Duration {
+    t.duration_since(SystemTime::UNIX_EPOCH).unwrap_or_default()
+}
+
+// seconds since the epoch, what the database keeps
+pub fn now() -> i64 {
+    since_epoch(SystemTime::now()).as_secs() as i64
+}
+
 pub fn open_db() -> Result<sled::Db> {
-    let path = match set_path() {
-        Err(e) => return Err(e),
-        Ok(p) => p,
+    let mut db = DB.lock().unwrap();
+    if db.is_none() {
+        let opened = sled::open(&config::get().db_path.value)?;
+        cache::migrate(&opened)?;
+        *db = Some(opened);
+    }
+
+    Ok(db.clone().unwrap())
+}
+
+// How many colors the terminal can show, `--color` and the environment decide.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum ColorLevel {
+    Plain = 0,
+    Ansi16 = 1,
+    Ansi256 = 2,
+    TrueColor = 3,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ColorMode {
+    Always,
+    Never,
+    Auto,
+}
+
+static COLOR_LEVEL: AtomicU8 = AtomicU8::new(ColorLevel::TrueColor as u8);
+
+pub fn set_color(mode: ColorMode) {
+    let level = match mode {
+        ColorMode::Never => ColorLevel::Plain,
+        ColorMode::Always => detect_level(),
+        // https://no-color.org, any value but an empty one
+        ColorMode::Auto if env::var_os("NO_COLOR").is_some_and(|v| !v.is_empty()) => {
+            ColorLevel::Plain
+        }
+        ColorMode::Auto if !io::stdout().is_terminal() => ColorLevel::Plain,
+        ColorMode::Auto => detect_level(),
     };
+    COLOR_LEVEL.store(level as u8, Ordering::Relaxed);
+}
 
-    return sled::open(path).map_err(Into::into);
+pub fn color_level() -> ColorLevel {
+    match COLOR_LEVEL.load(Ordering::Relaxed) {
+        0 => ColorLevel::Plain,
+        1 => ColorLevel::Ansi16,
+        2 => ColorLevel::Ansi256,
+        _ => ColorLevel::TrueColor,
+    }
+}
 
-    fn set_path() -> Result<PathBuf> {
-        let mut p = match dirs::data_dir() {
-            Some(v) => v,
-            None => env::current_dir()?,
-        };
-        p.push(DB_NAME);
-        Ok(p)
+// the best a colorful terminal can do
+fn detect_level() -> ColorLevel {
+    let var = |k: &str| env::var(k).unwrap_or_default().to_lowercase();
+    let (colorterm, term) = (var("COLORTERM"), var("TERM"));
+    if colorterm == "truecolor" || colorterm == "24bit" {
+        ColorLevel::TrueColor
+    } else if term.contains("256color") {
+        ColorLevel::Ansi256
+    } else {
+        ColorLevel::Ansi16
     }
 }
 
-// let's RGB
-
-impl From<ColorfulRole> for (u8, u8, u8) {
-    fn from(role: ColorfulRole) -> Self {
-        match role {
-            ColorfulRole::Title => (255, 95, 175),
-            ColorfulRole::Index => (2, 169, 170),
-            ColorfulRole::Dot => (188, 188, 188),
-            ColorfulRole::Content => (95, 175, 95),
-            ColorfulRole::Emphasis => (30, 250, 110),
-            ColorfulRole::Logo => (0, 221, 192),
-            ColorfulRole::Other => (0, 134, 1),
-            ColorfulRole::Wip => (214, 158, 19),
-        }
+pub fn paint(s: &str, p: &Paint, level: ColorLevel) -> String {
+    if level == ColorLevel::Plain {
+        return s.to_string();
     }
+
+    let mut sgr = vec![];
+    if p.bold {
+        sgr.push("1".to_string());
+    }
+    if p.italic {
+        sgr.push("3".to_string());
+    }
+    if p.underline {
+        sgr.push("4".to_string());
+    }
+    if let Some((r, g, b)) = p.color {
+        sgr.push(match level {
+            ColorLevel::TrueColor => format!("38;2;{};{};{}", r, g, b),
+            ColorLevel::Ansi256 => format!("38;5;{}", ansi256((r, g, b))),
+            _ => match ansi16((r, g, b)) {
+                i @ 0..=7 => (30 + i).to_string(),
+                i => (90 + i - 8).to_string(),
+            },
+        });
+    }
+
+    match sgr.is_empty() {
+        true => s.to_string(),
+        false => format!("\x1b[{}m{}\x1b[0m", sgr.join(";"), s),
+    }
+}
+
+// the 6x6x6 cube or the grayscale ramp of xterm-256color
+pub fn ansi256((r, g, b): (u8, u8, u8)) -> u8 {
+    if r == g && g == b {
+        return match r {
+            0..=7 => 16,
+            249..=255 => 231,
+            _ => 232 + ((r as u16 - 8) * 24 / 247) as u8,
+        };
+    }
+    // the levels are 0, 95, 135, 175, 215, 255
+    let cube = |c: u8| match c {
+        0..=47 => 0,
+        48..=114 => 1,
+        _ => (c - 35) / 40,
+    };
+    16 + 36 * cube(r) + 6 * cube(g) + cube(b)
+}
+
+// the nearest of the 16 colors as xterm shows them, 8 and above are the bright ones
+pub fn ansi16(rgb: (u8, u8, u8)) -> u8 {
+    const PALETTE: [(u8, u8, u8); 16] = [
+        (0, 0, 0),
+        (205, 0, 0),
+        (0, 205, 0),
+        (205, 205, 0),
+        (0, 0, 238),
+        (205, 0, 205),
+        (0, 205, 205),
+        (229, 229, 229),
+        (127, 127, 127),
+        (255, 0, 0),
+        (0, 255, 0),
+        (255, 255, 0),
+        (92, 92, 255),
+        (255, 0, 255),
+        (0, 255, 255),
+        (255, 255, 255),
+    ];
+    let distance = |(r, g, b): (u8, u8, u8)| {
+        let d = |x: u8, y: u8| (x as i32 - y as i32).pow(2);
+        d(r, rgb.0) + d(g, rgb.1) + d(b, rgb.2)
+    };
+    (0..16u8)
+        .min_by_key(|i| distance(PALETTE[*i as usize]))
+        .unwrap()
 }
 
 pub trait Style
@@ -56,8 +182,7 @@ where
     Self: ToString,
 {
     fn coloring(&self, role: ColorfulRole) -> String {
-        let rgb: (u8, u8, u8) = role.into();
-        Colorize::truecolor(self.to_string().as_str(), rgb.0, rgb.1, rgb.2).to_string()
+        paint(&self.to_string(), theme().paint(role), color_level())
     }
 
     fn align_right(&self, width: usize) -> String {
@@ -76,3 +201,37 @@ where
 }
 
 impl<T> Style for T where T: ToString {}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_paint() {
+        let p = Paint {
+            color: Some((95, 175, 95)),
+            bold: true,
+            ..Default::default()
+        };
+        assert_eq!(paint("hi", &p, ColorLevel::Plain), "hi");
+        assert_eq!(
+            paint("hi", &p, ColorLevel::TrueColor),
+            "\x1b[1;38;2;95;175;95mhi\x1b[0m"
+        );
+        assert_eq!(
+            paint("hi", &p, ColorLevel::Ansi256),
+            "\x1b[1;38;5;71mhi\x1b[0m"
+        );
+        assert_eq!(paint("hi", &p, ColorLevel::Ansi16), "\x1b[1;90mhi\x1b[0m");
+        assert_eq!(paint("hi", &Paint::default(), ColorLevel::Ansi16), "hi");
+
+        assert_eq!(ansi256((0, 0, 0)), 16);
+        assert_eq!(ansi256((255, 255, 255)), 231);
+        assert_eq!(ansi256((188, 188, 188)), 249);
+        assert_eq!(ansi256((255, 95, 175)), 205);
+
+        // cyan and yellow
+        assert_eq!(ansi16((0, 221, 192)), 6);
+        assert_eq!(ansi16((214, 158, 19)), 3);
+    }
+}